@@ -0,0 +1,235 @@
+//! Descriptions of the chains that our tests run
+//!
+//! A [ChainSpec] is the single place for the chain id, binary, prefixes, and
+//! denoms of a chain, from which its dockerfile, hermes config, and setup
+//! options are produced.
+
+use super_orchestrator::stacked_errors::{Result, StackableErr};
+
+use crate::{
+    dockerfiles::{
+        onexd_release, onomyd_release, standalone_onexd_release, DaemonSource, DockerfileBuilder,
+    },
+    hermes::HermesChainConfig,
+    setups::CosmosSetupOptions,
+    versions::versions,
+};
+
+/// Builds the interchain-security test consumer from source, expects
+/// `ICS_VERSION` to be set
+#[rustfmt::skip]
+const INTERCHAIN_SECURITY_CDD: &str = r#"ADD https://github.com/cosmos/interchain-security/archive/refs/tags/v$ICS_VERSION.tar.gz /root/v$ICS_VERSION.tar.gz
+RUN cd /root && tar -xvf ./v$ICS_VERSION.tar.gz
+RUN cd /root/interchain-security-$ICS_VERSION && go build ./cmd/interchain-security-cdd
+RUN mkdir -p $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/
+RUN mv /root/interchain-security-$ICS_VERSION/interchain-security-cdd $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/$DAEMON_NAME
+"#;
+
+/// How the genesis binary of a [ChainSpec] gets into its image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainBinary {
+    Source(DaemonSource),
+    /// Dockerfile steps that place the binary at
+    /// `$DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/$DAEMON_NAME`
+    Arbitrary(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainSpec {
+    pub chain_id: String,
+    /// The name of the binary (e.x. `onomyd`), also used as the container name
+    pub daemon_name: String,
+    /// The name of the home directory under `/root` (e.x. `.onomy`)
+    pub daemon_dir_name: String,
+    pub account_prefix: String,
+    pub valoper_prefix: String,
+    pub valcons_prefix: String,
+    pub gov_denom: String,
+    pub gas_denom: String,
+    /// The `DAEMON_VERSION` of the genesis binary
+    pub version: String,
+    pub binary: ChainBinary,
+    /// If this is an ICS consumer chain
+    pub is_consumer: bool,
+    /// See [CosmosSetupOptions::onomy_special]
+    pub onomy_special: bool,
+}
+
+impl ChainSpec {
+    /// Uses the standard `valoper` and `valcons` prefixes of `account_prefix`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        chain_id: &str,
+        daemon_name: &str,
+        daemon_dir_name: &str,
+        account_prefix: &str,
+        gov_denom: &str,
+        gas_denom: &str,
+        version: &str,
+        binary: ChainBinary,
+    ) -> Self {
+        Self {
+            chain_id: chain_id.to_owned(),
+            daemon_name: daemon_name.to_owned(),
+            daemon_dir_name: daemon_dir_name.to_owned(),
+            account_prefix: account_prefix.to_owned(),
+            valoper_prefix: format!("{account_prefix}valoper"),
+            valcons_prefix: format!("{account_prefix}valcons"),
+            gov_denom: gov_denom.to_owned(),
+            gas_denom: gas_denom.to_owned(),
+            version: version.to_owned(),
+            binary,
+            is_consumer: false,
+            onomy_special: false,
+        }
+    }
+
+    /// The Onomy provider chain
+    pub fn onomy() -> Result<Self> {
+        let version = &versions().stack()?.onomyd;
+        let mut spec = Self::new(
+            "onomy",
+            "onomyd",
+            ".onomy",
+            "onomy",
+            "anom",
+            "anom",
+            version,
+            ChainBinary::Source(onomyd_release(version)),
+        );
+        spec.onomy_special = true;
+        Ok(spec)
+    }
+
+    /// The ONEX consumer chain
+    pub fn onex() -> Result<Self> {
+        let version = &versions().stack()?.onexd;
+        let mut spec = Self::new(
+            "onex",
+            "onexd",
+            ".onomy_onex",
+            "onomy",
+            "aonex",
+            "aonex",
+            version,
+            ChainBinary::Source(onexd_release(version)),
+        );
+        spec.is_consumer = true;
+        Ok(spec)
+    }
+
+    /// The standalone ONEX market chain
+    pub fn market_standalone() -> Result<Self> {
+        let version = &versions().stack()?.marketd;
+        Ok(Self::new(
+            "market",
+            "marketd",
+            ".market",
+            "onomy",
+            "aonex",
+            "aonex",
+            version,
+            ChainBinary::Source(standalone_onexd_release(version)),
+        ))
+    }
+
+    /// The interchain-security test consumer, built from source
+    pub fn ics_test_consumer() -> Result<Self> {
+        let versions = versions().stack()?;
+        let mut spec = Self::new(
+            "interchain-security-cd",
+            "interchain-security-cdd",
+            ".interchain-security-cd",
+            "cosmos",
+            "anative",
+            "anative",
+            &versions.ics_consumer,
+            ChainBinary::Arbitrary(format!(
+                "ENV ICS_VERSION={}\n{INTERCHAIN_SECURITY_CDD}",
+                versions.ics
+            )),
+        );
+        spec.is_consumer = true;
+        Ok(spec)
+    }
+
+    /// `/root/{daemon_dir_name}`
+    pub fn daemon_home(&self) -> String {
+        format!("/root/{}", self.daemon_dir_name)
+    }
+
+    /// The hostname of the container named after the daemon in the
+    /// `ContainerNetwork` with `uuid`
+    pub fn hostname(&self, uuid: &str) -> String {
+        format!("{}_{uuid}", self.daemon_name)
+    }
+
+    /// The dockerfile with cosmovisor and the genesis binary
+    pub fn dockerfile(&self) -> Result<String> {
+        let builder = DockerfileBuilder::onomy_std().cosmovisor(&versions().stack()?.cosmovisor);
+        let builder = match self.binary {
+            ChainBinary::Source(ref source) => builder.cosmovisor_daemon(
+                &self.daemon_name,
+                &self.daemon_dir_name,
+                &self.version,
+                source,
+            ),
+            ChainBinary::Arbitrary(ref arbitrary) => builder
+                .daemon_env(&self.daemon_name, &self.daemon_dir_name, &self.version)
+                .raw(arbitrary)
+                .cosmovisor_init(&self.daemon_name),
+        };
+        builder.build()
+    }
+
+    /// The hermes config for the chain running in the container with
+    /// [ChainSpec::hostname]
+    pub fn hermes_config(&self, uuid: &str) -> HermesChainConfig {
+        self.hermes_config_for_host(&self.hostname(uuid))
+    }
+
+    /// The hermes config for the chain running at `hostname`. Fast block times
+    /// are assumed, since `cosmovisor_setup` sets them.
+    pub fn hermes_config_for_host(&self, hostname: &str) -> HermesChainConfig {
+        HermesChainConfig::new(
+            &self.chain_id,
+            hostname,
+            &self.account_prefix,
+            self.is_consumer,
+            &self.gas_denom,
+            true,
+        )
+    }
+
+    /// `ccvconsumer_state` should be set for consumer chains
+    pub fn setup_options(&self, ccvconsumer_state: Option<&str>) -> CosmosSetupOptions {
+        let mut options = CosmosSetupOptions::new(
+            &self.daemon_home(),
+            &self.chain_id,
+            &self.gov_denom,
+            &self.gas_denom,
+            ccvconsumer_state,
+        );
+        options.onomy_special = self.onomy_special;
+        options
+    }
+}
+
+#[test]
+fn test_chain_spec() {
+    let onomy = ChainSpec::onomy().unwrap();
+    assert_eq!(onomy.valoper_prefix, "onomyvaloper");
+    assert_eq!(onomy.hostname("uuid"), "onomyd_uuid");
+    assert_eq!(onomy.daemon_home(), "/root/.onomy");
+    assert!(onomy.setup_options(None).onomy_special);
+
+    let consumer = ChainSpec::ics_test_consumer().unwrap();
+    let config = consumer.hermes_config("uuid");
+    assert_eq!(config.chain_id, "interchain-security-cd");
+    assert_eq!(config.rpc_addr, "http://interchain-security-cdd_uuid:26657");
+    assert!(config.ccv_consumer_chain);
+    assert!(consumer
+        .dockerfile()
+        .unwrap()
+        .contains("go build ./cmd/interchain-security-cdd"));
+}
@@ -10,7 +10,281 @@ use super_orchestrator::{
 use tokio::time::sleep;
 use u64_array_bigints::U256;
 
-use crate::{anom_to_nom, json_inner, yaml_str_to_json_value, STD_DELAY, STD_TRIES};
+use crate::{
+    anom_to_nom,
+    executor::{execute, CommandOutput},
+    gov::{cosmovisor_gov_deposit, cosmovisor_gov_vote, proposal_id_from_events, VoteOption},
+    json_inner,
+    tx::{FeePolicy, TxError, TxResponse},
+    yaml_str_to_json_value, STD_DELAY, STD_TRIES,
+};
+
+/// A builder for `cosmovisor run ...` commands. This handles the common flags,
+/// removes the INF line that always shows with cosmovisor runs, and for
+/// transactions propogates if the chain level transaction failed.
+///
+/// ```text
+/// let balances = CosmovisorCmd::query("bank balances")
+///     .arg(addr)
+///     .home("/firehose")
+///     .output_json()
+///     .run_json()
+///     .await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CosmovisorCmd {
+    args: Vec<String>,
+    home: Option<String>,
+    node: Option<String>,
+    chain_id: Option<String>,
+    from: Option<String>,
+    output_json: bool,
+    debug: bool,
+    broadcast_mode: Option<String>,
+    yes: bool,
+    inclusion_tries: u64,
+    inclusion_delay: Duration,
+    /// A policy given to [CosmovisorCmd::fee_policy] that failed
+    /// [FeePolicy::to_args], the error is returned when the command is run
+    invalid_fee_policy: Option<FeePolicy>,
+}
+
+impl CosmovisorCmd {
+    /// `args` is split by whitespace, use [CosmovisorCmd::arg] for arguments
+    /// that may contain whitespace
+    pub fn new(args: &str) -> Self {
+        Self {
+            args: args.split_whitespace().map(|s| s.to_owned()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// A "query ..." command
+    pub fn query(subcommand: &str) -> Self {
+        Self::new(&format!("query {subcommand}"))
+    }
+
+    /// A "tx ..." command, by default with `-y -b sync` and waiting for
+    /// inclusion with `STD_TRIES` and `STD_DELAY`
+    pub fn tx(subcommand: &str) -> Self {
+        Self {
+            broadcast_mode: Some("sync".to_owned()),
+            yes: true,
+            inclusion_tries: STD_TRIES,
+            inclusion_delay: STD_DELAY,
+            ..Self::new(&format!("tx {subcommand}"))
+        }
+    }
+
+    /// Constructs from the older `sh_cosmovisor` style arguments, where the
+    /// first item is split by whitespace and the rest are passed verbatim
+    pub fn from_program_with_args<I, S>(program_with_args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut iter = program_with_args.into_iter();
+        let first = iter.next().stack_err(|| "called with an empty iterator")?;
+        Ok(Self::new(first.as_ref()).args(iter))
+    }
+
+    /// Adds a single argument verbatim
+    pub fn arg(mut self, arg: impl AsRef<str>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Adds arguments verbatim
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|s| s.as_ref().to_owned()));
+        self
+    }
+
+    /// `--home {home}`
+    pub fn home(mut self, home: &str) -> Self {
+        self.home = Some(home.to_owned());
+        self
+    }
+
+    /// `--node {node}`
+    pub fn node(mut self, node: &str) -> Self {
+        self.node = Some(node.to_owned());
+        self
+    }
+
+    /// `--chain-id {chain_id}`
+    pub fn chain_id(mut self, chain_id: &str) -> Self {
+        self.chain_id = Some(chain_id.to_owned());
+        self
+    }
+
+    /// `--from {key}`
+    pub fn from(mut self, key: &str) -> Self {
+        self.from = Some(key.to_owned());
+        self
+    }
+
+    /// Adds the gas and fee flags of `fee`. If `fee` is invalid, running the
+    /// command returns the error.
+    pub fn fee_policy(mut self, fee: &FeePolicy) -> Self {
+        match fee.to_args() {
+            Ok(args) => self.args(args),
+            Err(_) => {
+                self.invalid_fee_policy = Some(fee.clone());
+                self
+            }
+        }
+    }
+
+    /// `-o json`
+    pub fn output_json(mut self) -> Self {
+        self.output_json = true;
+        self
+    }
+
+    /// If the output should be forwarded to stdout, `false` by default
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// `-b {mode}`, or no `-b` flag if `None`. With "sync" or "async", the
+    /// transaction results are found by polling `query tx`. Note that "block"
+    /// mode was removed in SDK v0.47.
+    pub fn broadcast_mode(mut self, mode: Option<&str>) -> Self {
+        self.broadcast_mode = mode.map(|s| s.to_owned());
+        self
+    }
+
+    /// Sets the number of tries and delay between them used when polling for
+    /// the inclusion of a transaction
+    pub fn inclusion_wait(mut self, num_tries: u64, delay: Duration) -> Self {
+        self.inclusion_tries = num_tries;
+        self.inclusion_delay = delay;
+        self
+    }
+
+    /// If `-y` is passed to skip confirmation
+    pub fn yes(mut self, yes: bool) -> Self {
+        self.yes = yes;
+        self
+    }
+
+    /// Returns the full argv starting with "cosmovisor run"
+    pub fn argv(&self) -> Vec<String> {
+        let mut argv: Vec<String> = vec!["cosmovisor".to_owned(), "run".to_owned()];
+        argv.extend(self.args.iter().cloned());
+        let mut flag = |name: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                argv.push(name.to_owned());
+                argv.push(value.clone());
+            }
+        };
+        flag("--home", &self.home);
+        flag("--node", &self.node);
+        flag("--chain-id", &self.chain_id);
+        flag("--from", &self.from);
+        flag("-b", &self.broadcast_mode);
+        if self.output_json {
+            argv.extend(["-o".to_owned(), "json".to_owned()]);
+        }
+        if self.yes {
+            argv.push("-y".to_owned());
+        }
+        argv
+    }
+
+    fn check_fee_policy(&self) -> Result<()> {
+        if let Some(ref fee) = self.invalid_fee_policy {
+            fee.to_args()
+                .stack_err(|| format!("CosmovisorCmd command {:?}", self.argv()))?;
+        }
+        Ok(())
+    }
+
+    /// Runs the command without checking the status
+    pub async fn run_output(&self) -> Result<CommandOutput> {
+        self.check_fee_policy().stack()?;
+        let argv = self.argv();
+        execute(&argv, self.debug).await.stack()
+    }
+
+    /// Runs the command and returns the stdout without the INF line
+    pub async fn run(&self) -> Result<String> {
+        self.check_fee_policy().stack()?;
+        let argv = self.argv();
+        let output = execute(&argv, self.debug).await.stack()?;
+        output.assert_success(&argv)?;
+        strip_info_line(&output.stdout)
+    }
+
+    /// Runs the command and parses the output as YAML or JSON
+    pub async fn run_json(&self) -> Result<Value> {
+        let s = self.run().await.stack()?;
+        yaml_str_to_json_value(&s).stack_err(|| format!("command {:?}", self.argv()))
+    }
+
+    /// Runs the command and parses the [TxResponse], without checking the
+    /// chain level result
+    pub async fn run_tx_response(&self) -> Result<TxResponse> {
+        let res = self.run_json().await.stack()?;
+        let res = TxResponse::from_value(res).stack_err(|| format!("command {:?}", self.argv()))?;
+        match self.broadcast_mode.as_deref() {
+            // if `CheckTx` failed the transaction will never be included
+            Some("sync") | Some("async") if res.is_success() => self
+                .wait_for_inclusion(&res.txhash)
+                .await
+                .stack_err(|| format!("command {:?}", self.argv())),
+            _ => Ok(res),
+        }
+    }
+
+    /// Polls `query tx {txhash}` until the transaction is included in a block
+    async fn wait_for_inclusion(&self, txhash: &str) -> Result<TxResponse> {
+        let query = CosmovisorCmd {
+            home: self.home.clone(),
+            node: self.node.clone(),
+            ..CosmovisorCmd::query("tx").arg(txhash).output_json()
+        };
+        let res = wait_for_ok(self.inclusion_tries, self.inclusion_delay, || {
+            query.run_json()
+        })
+        .await
+        .stack_err(|| format!("transaction {txhash} was not included in a block in time"))?;
+        TxResponse::from_value(res).stack()
+    }
+
+    /// Runs the command and returns an error if the chain level transaction
+    /// has a nonzero code. Use [CosmovisorCmd::run_tx_typed] to check the
+    /// [TxError] of a failed transaction.
+    pub async fn run_tx(&self) -> Result<TxResponse> {
+        self.run_tx_typed()
+            .await
+            .stack()?
+            .map_err(|e| Error::from(e.to_string()))
+            .stack_err(|| format!("CosmovisorCmd::run_tx command {:?}", self.argv()))
+    }
+
+    /// Runs the command, the outer error is for failures to run the command or
+    /// parse its output and the inner [TxError] is for a chain level
+    /// transaction failure, so that its codespace and code can be checked
+    pub async fn run_tx_typed(&self) -> Result<core::result::Result<TxResponse, TxError>> {
+        Ok(self.run_tx_response().await.stack()?.into_result())
+    }
+}
+
+fn strip_info_line(stdout: &str) -> Result<String> {
+    Ok(stdout
+        .split_once('\n')
+        .stack_err(|| "cosmovisor run command did not have expected info line")?
+        .1
+        .to_owned())
+}
 
 /// A wrapper around `super_orchestrator::sh` that prefixes "cosmovisor run"
 /// onto `program_with_args` and removes the first line of output (in order to
@@ -20,30 +294,11 @@ where
     I: IntoIterator<Item = S>,
     S: AsRef<str>,
 {
-    let mut command = None;
-    for (i, part) in program_with_args.into_iter().enumerate() {
-        if i == 0 {
-            let s = format!("cosmovisor run {}", part.as_ref());
-            command = Some(Command::new(s));
-        } else {
-            command = Some(command.unwrap().arg(part.as_ref()));
-        }
-    }
-    let comres = command
+    CosmovisorCmd::from_program_with_args(program_with_args)
         .stack_err(|| "`sh_cosmovisor` called with an empty iterator")?
         .debug(true)
-        .run_to_completion()
-        .await?;
-    comres.assert_success()?;
-    let stdout = comres
-        .stdout_as_utf8()
-        .map(|s| s.to_owned())
-        .stack_err_locationless(|| "`Command` output was not UTF-8")?;
-    Ok(stdout
-        .split_once('\n')
-        .stack_err(|| "cosmovisor run command did not have expected info line")?
-        .1
-        .to_owned())
+        .run()
+        .await
 }
 
 pub async fn sh_cosmovisor_no_debug<I, S>(program_with_args: I) -> Result<String>
@@ -51,29 +306,10 @@ where
     I: IntoIterator<Item = S>,
     S: AsRef<str>,
 {
-    let mut command = None;
-    for (i, part) in program_with_args.into_iter().enumerate() {
-        if i == 0 {
-            let s = format!("cosmovisor run {}", part.as_ref());
-            command = Some(Command::new(s));
-        } else {
-            command = Some(command.unwrap().arg(part.as_ref()));
-        }
-    }
-    let comres = command
+    CosmovisorCmd::from_program_with_args(program_with_args)
         .stack_err(|| "`sh_cosmovisor_no_debug` called with an empty iterator")?
-        .run_to_completion()
-        .await?;
-    comres.assert_success()?;
-    let stdout = comres
-        .stdout_as_utf8()
-        .map(|s| s.to_owned())
-        .stack_err_locationless(|| "`Command` output was not UTF-8")?;
-    Ok(stdout
-        .split_once('\n')
-        .stack_err(|| "cosmovisor run command did not have expected info line")?
-        .1
-        .to_owned())
+        .run()
+        .await
 }
 
 /// This adds on a "tx" command arg and adds extra handling to propogate if the
@@ -81,47 +317,21 @@ where
 /// status if the transaction was at least successfully transmitted, ignoring if
 /// the transaction result was unsuccessful)
 ///
-/// NOTE: You need to pass the argument `-y` to confirm without needing piped
-/// input, and the arguments `-b block` for the error handling to work properly
-pub async fn sh_cosmovisor_tx<I, S>(program_with_args: I) -> Result<serde_json::Value>
+/// This uses the defaults of [CosmovisorCmd::tx], broadcasting with `-y -b
+/// sync` and then waiting for the transaction to be included.
+pub async fn sh_cosmovisor_tx<I, S>(program_with_args: I) -> Result<TxResponse>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<str>,
 {
-    let mut command = None;
-    for (i, part) in program_with_args.into_iter().enumerate() {
-        if i == 0 {
-            let s = format!("cosmovisor run tx {}", part.as_ref());
-            command = Some(Command::new(s));
-        } else {
-            command = Some(command.unwrap().arg(part.as_ref()));
-        }
-    }
-    let comres = command
-        .stack_err(|| "`sh_cosmovisor_no_debug` called with an empty iterator")?
-        .run_to_completion()
-        .await?;
-    comres.assert_success()?;
-    let stdout = comres
-        .stdout_as_utf8()
-        .map(|s| s.to_owned())
-        .stack_err_locationless(|| "`Command` output was not UTF-8")?;
-    let res = stdout
-        .split_once('\n')
-        .stack_err(|| "cosmovisor run command did not have expected info line")?
-        .1
-        .to_owned();
-
-    let res = yaml_str_to_json_value(&res).stack_err(|| "failed to convert json value")?;
-    if stacked_get!(res["code"]).as_u64().stack()? == 0 {
-        Ok(res)
-    } else {
-        Err(Error::from(format!(
-            "raw_log: {}",
-            stacked_get!(res["raw_log"])
-        )))
-        .stack_err(|| format!("sh_cosmovisor_tx command {comres:?}"))
-    }
+    let mut program_with_args = program_with_args.into_iter();
+    let first = program_with_args
+        .next()
+        .stack_err(|| "`sh_cosmovisor_tx` called with an empty iterator")?;
+    CosmovisorCmd::tx(first.as_ref())
+        .args(program_with_args)
+        .run_tx()
+        .await
 }
 
 /// Cosmos-SDK configuration gets messed up by different Git commit and tag
@@ -331,15 +541,12 @@ pub async fn wait_for_num_blocks(num_blocks: u64) -> Result<()> {
 
 /// Returns the number of proposals
 pub async fn cosmovisor_get_num_proposals() -> Result<u64> {
-    let comres = Command::new("cosmovisor run query gov proposals --count-total --limit 1")
-        .run_to_completion()
-        .await
-        .stack()?;
-    if let Err(e) = comres.assert_success() {
+    let cmd = CosmovisorCmd::query("gov proposals --count-total --limit 1");
+    let output = cmd.run_output().await.stack()?;
+    if let Err(e) = output.assert_success(&cmd.argv()) {
         // work around bad zero casing design
-        if comres
-            .stderr_as_utf8()
-            .stack()?
+        if output
+            .stderr
             .trim()
             .starts_with("Error: no proposals found")
         {
@@ -348,14 +555,9 @@ pub async fn cosmovisor_get_num_proposals() -> Result<u64> {
             return Err(e)
         }
     }
-    let stdout = comres
-        .stdout_as_utf8()
-        .stack()?
-        .split_once('\n')
-        .stack_err(|| "cosmovisor run command did not have expected info line")?
-        .1;
+    let stdout = strip_info_line(&output.stdout).stack()?;
 
-    let v = yaml_str_to_json_value(stdout).stack()?;
+    let v = yaml_str_to_json_value(&stdout).stack()?;
     let total = stacked_get!(v["pagination"]["total"]).as_str().stack()?;
     total.parse::<u64>().stack()
 }
@@ -376,7 +578,7 @@ pub async fn cosmovisor_get_num_proposals() -> Result<u64> {
  */
 
 /// Writes the proposal at `{daemon_home}/config/proposal.json` and runs `tx gov
-/// submit-proposal [proposal_type]`.
+/// submit-proposal [proposal_type]` from the `from_key` account.
 ///
 /// If `proposal_type.is_none()`, `--proposal` is passed without the
 /// [proposal_type], and instead the type needs to be specified in the
@@ -385,168 +587,108 @@ pub async fn cosmovisor_get_num_proposals() -> Result<u64> {
 /// Gov proposals have the annoying property that error statuses (e.x. bad fees
 /// will not result in an error at the `Command` level) are not propogated, this
 /// will detect if an error happens.
+///
+/// Returns the proposal ID
 pub async fn cosmovisor_submit_gov_file_proposal(
     daemon_home: &str,
+    from_key: &str,
     proposal_type: Option<&str>,
     proposal_s: &str,
-    base_fee: &str,
-) -> Result<()> {
+    fee: &FeePolicy,
+) -> Result<u64> {
     let proposal_file_path = format!("{daemon_home}/config/proposal.json");
     FileOptions::write_str(&proposal_file_path, proposal_s)
         .await
         .stack()?;
-    if let Some(proposal_type) = proposal_type {
-        sh_cosmovisor_tx([
-            "gov submit-proposal",
-            proposal_type,
-            &proposal_file_path,
-            "--gas",
-            "auto",
-            "--gas-adjustment",
-            "1.3",
-            "--gas-prices",
-            base_fee,
-            "-y",
-            "-b",
-            "block",
-            "--from",
-            "validator",
-        ])
-        .await
+    let cmd = if let Some(proposal_type) = proposal_type {
+        CosmovisorCmd::tx("gov submit-proposal").arg(proposal_type)
     } else {
-        sh_cosmovisor_tx([
-            "gov submit-proposal",
-            "--proposal",
-            &proposal_file_path,
-            "--gas",
-            "auto",
-            "--gas-adjustment",
-            "1.3",
-            "--gas-prices",
-            base_fee,
-            "-y",
-            "-b",
-            "block",
-            "--from",
-            "validator",
-        ])
+        CosmovisorCmd::tx("gov submit-proposal --proposal")
+    };
+    let res = cmd
+        .arg(&proposal_file_path)
+        .fee_policy(fee)
+        .from(from_key)
+        .run_tx()
         .await
-    }
-    .stack_err(|| {
-        format!(
-            "cosmovisor_submit_gov_file_proposal(proposal_type: {proposal_type:?}, proposal_s: \
-             {proposal_s})"
-        )
-    })?;
-    Ok(())
+        .stack_err(|| {
+            format!(
+                "cosmovisor_submit_gov_file_proposal(from_key: {from_key}, proposal_type: \
+                 {proposal_type:?}, proposal_s: {proposal_s})"
+            )
+        })?;
+    proposal_id_from_events(&res).stack()
 }
 
+/// Submits the proposal with `submit_fee` and votes yes on it with `vote_fee`
+/// from the `from_key` account. Returns the proposal ID, use
+/// [crate::gov::wait_for_proposal_status] to wait for the result.
+///
+/// Note: market ICS needs a gas adjustment of 2.3 for the vote for some reason
 pub async fn cosmovisor_gov_file_proposal(
     daemon_home: &str,
+    from_key: &str,
     proposal_type: Option<&str>,
     proposal_s: &str,
-    base_fee: &str,
-) -> Result<()> {
-    cosmovisor_submit_gov_file_proposal(daemon_home, proposal_type, proposal_s, base_fee)
-        .await
-        .stack()?;
-    let proposal_id = format!("{}", cosmovisor_get_num_proposals().await.stack()?);
-    // the deposit is done as part of the chain addition proposal
-    sh_cosmovisor_tx([
-        "gov vote",
-        &proposal_id,
-        "yes",
-        "--gas",
-        "auto",
-        "--gas-adjustment",
-        // market ICS needs this for some reason
-        "2.3",
-        "--gas-prices",
-        base_fee,
-        "-y",
-        "-b",
-        "block",
-        "--from",
-        "validator",
-    ])
+    submit_fee: &FeePolicy,
+    vote_fee: &FeePolicy,
+) -> Result<u64> {
+    let proposal_id = cosmovisor_submit_gov_file_proposal(
+        daemon_home,
+        from_key,
+        proposal_type,
+        proposal_s,
+        submit_fee,
+    )
     .await
     .stack()?;
-    Ok(())
+    // the deposit is done as part of the chain addition proposal
+    cosmovisor_gov_vote(from_key, proposal_id, VoteOption::Yes, vote_fee)
+        .await
+        .stack()?;
+    Ok(proposal_id)
 }
 
+/// Returns the proposal ID
 pub async fn cosmovisor_submit_gov_proposal(
+    from_key: &str,
     proposal_type: &str,
     proposal_args: &[&str],
-    base_fee: &str,
-) -> Result<()> {
-    let mut args = vec!["gov submit-proposal"];
-    args.push(proposal_type);
-    args.extend(proposal_args);
-    args.extend([
-        "--gas",
-        "auto",
-        "--gas-adjustment",
-        "1.3",
-        "--gas-prices",
-        base_fee,
-        "-y",
-        "-b",
-        "block",
-        "--from",
-        "validator",
-    ]);
-    sh_cosmovisor_tx(args).await.stack()?;
-    Ok(())
+    fee: &FeePolicy,
+) -> Result<u64> {
+    let res = CosmovisorCmd::tx("gov submit-proposal")
+        .arg(proposal_type)
+        .args(proposal_args)
+        .fee_policy(fee)
+        .from(from_key)
+        .run_tx()
+        .await
+        .stack()?;
+    proposal_id_from_events(&res).stack()
 }
 
+/// Submits the proposal with `submit_fee`, then deposits `deposit` and votes
+/// yes on it with `vote_fee`, all from the `from_key` account. Returns the
+/// proposal ID.
 pub async fn cosmovisor_gov_proposal(
+    from_key: &str,
     proposal_type: &str,
     proposal_args: &[&str],
     deposit: &str,
-    base_fee: &str,
-) -> Result<()> {
-    cosmovisor_submit_gov_proposal(proposal_type, proposal_args, base_fee)
+    submit_fee: &FeePolicy,
+    vote_fee: &FeePolicy,
+) -> Result<u64> {
+    let proposal_id =
+        cosmovisor_submit_gov_proposal(from_key, proposal_type, proposal_args, submit_fee)
+            .await
+            .stack()?;
+    cosmovisor_gov_deposit(from_key, proposal_id, deposit, vote_fee)
         .await
         .stack()?;
-    let proposal_id = format!("{}", cosmovisor_get_num_proposals().await.stack()?);
-    sh_cosmovisor_tx([
-        "gov deposit",
-        &proposal_id,
-        deposit,
-        "--gas",
-        "auto",
-        "--gas-adjustment",
-        "2.3",
-        "--gas-prices",
-        base_fee,
-        "-y",
-        "-b",
-        "block",
-        "--from",
-        "validator",
-    ])
-    .await
-    .stack()?;
-    // the deposit is done as part of the chain addition proposal
-    sh_cosmovisor_tx([
-        "gov vote",
-        &proposal_id,
-        "yes",
-        "--gas",
-        "auto",
-        "--gas-adjustment",
-        "2.3",
-        "--gas-prices",
-        base_fee,
-        "-y",
-        "-b",
-        "block",
-        "--from",
-        "validator",
-    ])
-    .await
-    .stack()?;
-    Ok(())
+    cosmovisor_gov_vote(from_key, proposal_id, VoteOption::Yes, vote_fee)
+        .await
+        .stack()?;
+    Ok(proposal_id)
 }
 
 pub async fn get_persistent_peer_info(hostname: &str) -> Result<String> {
@@ -706,17 +848,22 @@ pub async fn cosmovisor_start(
 }
 
 pub async fn cosmovisor_get_addr(key_name: &str) -> Result<String> {
-    let yaml = sh_cosmovisor(["keys show", key_name]).await.stack()?;
-    let validator = yaml_str_to_json_value(&yaml).stack()?;
+    let validator = CosmovisorCmd::new("keys show")
+        .arg(key_name)
+        .debug(true)
+        .run_json()
+        .await
+        .stack()?;
     Ok(json_inner(stacked_get!(validator[0]["address"])))
 }
 
 /// Returns a mapping of denoms to amounts
 pub async fn cosmovisor_get_balances(addr: &str) -> Result<BTreeMap<String, U256>> {
-    let balances = sh_cosmovisor_no_debug(["query bank balances", addr])
+    let balances = CosmovisorCmd::query("bank balances")
+        .arg(addr)
+        .run_json()
         .await
         .stack()?;
-    let balances = yaml_str_to_json_value(&balances).stack()?;
     let mut res = BTreeMap::new();
     for balance in balances["balances"].as_array().stack()? {
         res.insert(
@@ -727,20 +874,19 @@ pub async fn cosmovisor_get_balances(addr: &str) -> Result<BTreeMap<String, U256
     Ok(res)
 }
 
-/// This uses flags "-b block --gas auto --gas-adjustment 1.3 --gas-prices
-/// 1{denom}"
 pub async fn cosmovisor_bank_send(
     src_addr: &str,
     dst_addr: &str,
     amount: &str,
     denom: &str,
+    fee: &FeePolicy,
 ) -> Result<()> {
-    sh_cosmovisor_tx([format!(
-        "bank send {src_addr} {dst_addr} {amount}{denom} -y -b block --gas auto --gas-adjustment \
-         1.3 --gas-prices 1{denom}"
-    )])
-    .await
-    .stack_err(|| "cosmovisor_bank_send")?;
+    CosmovisorCmd::tx("bank send")
+        .args([src_addr, dst_addr, &format!("{amount}{denom}")])
+        .fee_policy(fee)
+        .run_tx()
+        .await
+        .stack_err(|| "cosmovisor_bank_send")?;
     Ok(())
 }
 
@@ -749,7 +895,10 @@ pub async fn get_delegations_to(valoper_addr: &str) -> Result<String> {
 }
 
 pub async fn get_treasury() -> Result<f64> {
-    let tmp = yaml_str_to_json_value(&sh_cosmovisor(["query dao show-treasury"]).await.stack()?)
+    let tmp = CosmovisorCmd::query("dao show-treasury")
+        .debug(true)
+        .run_json()
+        .await
         .stack()?;
     let inner = json_inner(stacked_get!(tmp["treasury_balance"][0]["amount"]));
     anom_to_nom(&inner).stack_err(|| format!("inner was: {inner}"))
@@ -785,15 +934,12 @@ pub async fn get_staking_pool() -> Result<DbgStakingPool> {
 }
 
 pub async fn get_outstanding_rewards(valoper_addr: &str) -> Result<f64> {
-    let tmp = yaml_str_to_json_value(
-        &sh_cosmovisor([
-            "query distribution validator-outstanding-rewards",
-            valoper_addr,
-        ])
+    let tmp = CosmovisorCmd::query("distribution validator-outstanding-rewards")
+        .arg(valoper_addr)
+        .debug(true)
+        .run_json()
         .await
-        .stack()?,
-    )
-    .stack()?;
+        .stack()?;
     anom_to_nom(&json_inner(stacked_get!(tmp["rewards"][0]["amount"]))).stack()
 }
 
@@ -804,10 +950,12 @@ pub async fn get_validator_delegated() -> Result<f64> {
         "address",
         ":",
     )?;
-    let s = sh_cosmovisor(["query staking delegations", &validator_addr])
+    let tmp = CosmovisorCmd::query("staking delegations")
+        .arg(&validator_addr)
+        .debug(true)
+        .run_json()
         .await
         .stack()?;
-    let tmp = yaml_str_to_json_value(&s).stack()?;
     anom_to_nom(&json_inner(stacked_get!(
         tmp["delegation_responses"][0]["balance"]["amount"]
     )))
@@ -824,3 +972,108 @@ pub async fn get_apr_annual(valoper_addr: &str, blocks_per_year: f64) -> Result<
     let reward_end = get_outstanding_rewards(valoper_addr).await.stack()?;
     Ok(((reward_end - reward_start) * blocks_per_year) / delegated)
 }
+
+#[test]
+fn test_cosmovisor_cmd_argv() {
+    let cmd = CosmovisorCmd::tx("bank send")
+        .args(["a", "b", "1anom"])
+        .home("/firehose")
+        .from("validator");
+    assert_eq!(
+        cmd.argv().join(" "),
+        "cosmovisor run tx bank send a b 1anom --home /firehose --from validator -b sync -y"
+    );
+    let cmd = CosmovisorCmd::from_program_with_args(["query bank balances", "x y"])
+        .unwrap()
+        .output_json();
+    assert_eq!(cmd.argv(), [
+        "cosmovisor",
+        "run",
+        "query",
+        "bank",
+        "balances",
+        "x y",
+        "-o",
+        "json"
+    ]);
+}
+
+#[tokio::test]
+async fn test_cosmovisor_output_parsing() {
+    use std::sync::Arc;
+
+    use u64_array_bigints::u256;
+
+    use crate::executor::{with_command_executor, CommandOutput, ReplayExecutor};
+
+    let replay = Arc::new(ReplayExecutor::new());
+    replay.respond(
+        "cosmovisor run query bank balances onomy1abc",
+        CommandOutput::success(
+            "INF running app args=[query bank balances onomy1abc]\nbalances:\n- amount: \
+             \"1337\"\n  denom: anom\n- amount: \"5\"\n  denom: ibc/hello\npagination:\n  \
+             next_key: null\n  total: \"0\"\n",
+        ),
+    );
+    replay.respond(
+        "cosmovisor run query gov proposals --count-total --limit 1",
+        CommandOutput::failure("Error: no proposals found\n"),
+    );
+    replay.respond(
+        "cosmovisor run query gov proposals --count-total --limit 1",
+        CommandOutput::success(
+            "INF running app\npagination:\n  next_key: AAAA\n  total: \"3\"\nproposals: []\n",
+        ),
+    );
+    // `CheckTx` succeeds but the transaction fails when included
+    replay.respond(
+        "cosmovisor run tx bank send a b 1anom -b sync -y",
+        CommandOutput::success(
+            "INF running app\ncode: 0\nheight: \"0\"\nraw_log: '[]'\ntxhash: ABCD\n",
+        ),
+    );
+    replay.respond(
+        "cosmovisor run query tx ABCD -o json",
+        CommandOutput::failure("Error: tx (ABCD) not found\n"),
+    );
+    replay.respond(
+        "cosmovisor run query tx ABCD -o json",
+        CommandOutput::success(
+            "INF running \
+             app\n{\"height\":\"7\",\"txhash\":\"ABCD\",\"codespace\":\"sdk\",\"code\":13,\"\
+             raw_log\":\"insufficient fees\",\"logs\":[]}\n",
+        ),
+    );
+    with_command_executor(replay.clone(), async {
+        let balances = cosmovisor_get_balances("onomy1abc").await.unwrap();
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances["anom"], u256!(1337));
+        assert_eq!(balances["ibc/hello"], u256!(5));
+
+        assert_eq!(cosmovisor_get_num_proposals().await.unwrap(), 0);
+        assert_eq!(cosmovisor_get_num_proposals().await.unwrap(), 3);
+
+        let e = sh_cosmovisor_tx(["bank send a b 1anom"]).await.unwrap_err();
+        assert!(format!("{e:?}").contains("insufficient fees"));
+
+        let e = CosmovisorCmd::tx("bank send")
+            .args(["a", "b", "1anom"])
+            .run_tx_typed()
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!((e.codespace.as_str(), e.code), ("sdk", 13));
+        assert!(e.is_insufficient_fee());
+        assert!(!e.is_out_of_gas());
+
+        // rejected before running anything
+        let both = FeePolicy {
+            fees: Some("1anom".to_owned()),
+            ..FeePolicy::chain_default("anom")
+        };
+        let cmd = CosmovisorCmd::tx("bank send").fee_policy(&both);
+        assert!(cmd.run_tx().await.is_err());
+    })
+    .await;
+    assert_eq!(replay.calls().len(), 8);
+}
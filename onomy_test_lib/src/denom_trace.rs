@@ -0,0 +1,228 @@
+//! ICS-20 denom traces, computed locally so that the `ibc/{hash}` denom of a
+//! token can be known before it is transferred
+//!
+//! A token received over a channel gets the receiving `{port}/{channel}`
+//! prefixed to its trace, and the denom on the receiving chain is
+//! `ibc/{SHA256(path/base_denom)}` with an uppercase hex hash. Sending a token
+//! back over the channel it arrived through removes the prefix again.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use super_orchestrator::{
+    stacked_errors::{Error, Result, StackableErr},
+    stacked_get,
+};
+
+use crate::{cosmovisor::CosmovisorCmd, json_inner};
+
+/// One `{port_id}/{channel_id}` element of a trace path
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TraceHop {
+    pub port_id: String,
+    pub channel_id: String,
+}
+
+/// If `s` is a valid `channel-{N}` identifier
+fn is_channel_id(s: &str) -> bool {
+    s.strip_prefix("channel-")
+        .map(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DenomTrace {
+    /// The hops with the most recent one first, empty for a native denom
+    pub hops: Vec<TraceHop>,
+    pub base_denom: String,
+}
+
+impl DenomTrace {
+    /// The trace of a denom native to the chain it is on
+    pub fn new(base_denom: &str) -> Self {
+        Self {
+            hops: vec![],
+            base_denom: base_denom.to_owned(),
+        }
+    }
+
+    /// Prefixes the receiving `port_id/channel_id` of a hop, e.x.
+    /// `DenomTrace::new("anom").prefix("transfer", "channel-1")` is the trace
+    /// of NOM received over "channel-1"
+    pub fn prefix(mut self, port_id: &str, channel_id: &str) -> Self {
+        self.hops.insert(0, TraceHop {
+            port_id: port_id.to_owned(),
+            channel_id: channel_id.to_owned(),
+        });
+        self
+    }
+
+    /// Returns the trace on the receiving chain after this denom is sent from
+    /// the `src_port/src_channel` end to the `dst_port/dst_channel` end. The
+    /// last prefix is removed if the denom is returning to where it came from.
+    pub fn after_transfer(
+        &self,
+        src_port: &str,
+        src_channel: &str,
+        dst_port: &str,
+        dst_channel: &str,
+    ) -> Self {
+        match self.hops.first() {
+            Some(hop) if (hop.port_id == src_port) && (hop.channel_id == src_channel) => Self {
+                hops: self.hops[1..].to_vec(),
+                base_denom: self.base_denom.clone(),
+            },
+            _ => self.clone().prefix(dst_port, dst_channel),
+        }
+    }
+
+    /// Parses a full trace path such as "transfer/channel-1/anom". Base denoms
+    /// may contain '/', the path ends at the first pair that is not a port and
+    /// a `channel-{N}` identifier.
+    pub fn parse(full_path: &str) -> Result<Self> {
+        if full_path.starts_with("ibc/") {
+            return Err(Error::from(format!(
+                "DenomTrace::parse called on the hashed denom {full_path}, use \
+                 `cosmovisor_denom_trace` to look up its trace"
+            )))
+        }
+        let parts: Vec<&str> = full_path.split('/').collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(Error::from(format!(
+                "DenomTrace::parse: {full_path} has an empty element"
+            )))
+        }
+        let mut hops = vec![];
+        let mut i = 0;
+        while ((i + 1) < parts.len()) && is_channel_id(parts[i + 1]) {
+            hops.push(TraceHop {
+                port_id: parts[i].to_owned(),
+                channel_id: parts[i + 1].to_owned(),
+            });
+            i += 2;
+        }
+        if i == parts.len() {
+            return Err(Error::from(format!(
+                "DenomTrace::parse: {full_path} has no base denom"
+            )))
+        }
+        Ok(Self {
+            hops,
+            base_denom: parts[i..].join("/"),
+        })
+    }
+
+    /// Parses the "denom_trace" object of `query ibc-transfer denom-trace`
+    pub fn from_value(denom_trace: &Value) -> Result<Self> {
+        let path = json_inner(stacked_get!(denom_trace["path"]));
+        let base_denom = json_inner(stacked_get!(denom_trace["base_denom"]));
+        if path.is_empty() {
+            Ok(Self::new(&base_denom))
+        } else {
+            Self::parse(&format!("{path}/{base_denom}")).stack()
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    /// The hops joined with '/', e.x. "transfer/channel-1/transfer/channel-0"
+    pub fn path(&self) -> String {
+        self.hops
+            .iter()
+            .map(|hop| format!("{}/{}", hop.port_id, hop.channel_id))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// The path followed by the base denom, e.x. "transfer/channel-1/anom"
+    pub fn full_path(&self) -> String {
+        if self.is_native() {
+            self.base_denom.clone()
+        } else {
+            format!("{}/{}", self.path(), self.base_denom)
+        }
+    }
+
+    /// The uppercase hex SHA256 hash of the full path
+    pub fn hash(&self) -> String {
+        format!("{:X}", Sha256::digest(self.full_path().as_bytes()))
+    }
+
+    /// The denom used in balances, `ibc/{hash}` or the base denom if native
+    pub fn ibc_denom(&self) -> String {
+        if self.is_native() {
+            self.base_denom.clone()
+        } else {
+            format!("ibc/{}", self.hash())
+        }
+    }
+
+    /// Queries the chain for the trace of this denom and returns an error if
+    /// it differs
+    pub async fn cosmovisor_verify(&self) -> Result<()> {
+        if self.is_native() {
+            return Ok(())
+        }
+        let trace = cosmovisor_denom_trace(&self.ibc_denom()).await.stack()?;
+        if &trace != self {
+            return Err(Error::from(format!(
+                "DenomTrace::cosmovisor_verify: the chain has trace {} for {} instead of {}",
+                trace.full_path(),
+                self.ibc_denom(),
+                self.full_path()
+            )))
+        }
+        Ok(())
+    }
+}
+
+/// Looks up the trace of `ibc_denom` (with or without the "ibc/" prefix) on
+/// the chain
+pub async fn cosmovisor_denom_trace(ibc_denom: &str) -> Result<DenomTrace> {
+    let hash = ibc_denom.strip_prefix("ibc/").unwrap_or(ibc_denom);
+    let res = CosmovisorCmd::query("ibc-transfer denom-trace")
+        .arg(hash)
+        .output_json()
+        .run_json()
+        .await
+        .stack()?;
+    DenomTrace::from_value(stacked_get!(res["denom_trace"]))
+        .stack_err(|| format!("cosmovisor_denom_trace({ibc_denom})"))
+}
+
+#[test]
+fn test_denom_trace() {
+    let nom = DenomTrace::new("anom");
+    assert!(nom.is_native());
+    assert_eq!(nom.ibc_denom(), "anom");
+
+    // the trace of `ONOMY_IBC_NOM`
+    let ibc_nom = nom.after_transfer("transfer", "channel-0", "transfer", "channel-1");
+    assert_eq!(ibc_nom.full_path(), "transfer/channel-1/anom");
+    assert_eq!(ibc_nom.ibc_denom(), crate::ONOMY_IBC_NOM);
+
+    let two_hops = ibc_nom.after_transfer("transfer", "channel-4", "transfer", "channel-0");
+    assert_eq!(two_hops.path(), "transfer/channel-0/transfer/channel-1");
+    assert_eq!(
+        DenomTrace::parse("transfer/channel-0/transfer/channel-1/anom").unwrap(),
+        two_hops
+    );
+    // returning over the channel it came through unwinds the last hop
+    assert_eq!(
+        two_hops.after_transfer("transfer", "channel-0", "transfer", "channel-4"),
+        ibc_nom
+    );
+
+    // base denoms can contain '/'
+    let pool = DenomTrace::parse("transfer/channel-2/gamm/pool/1").unwrap();
+    assert_eq!(pool.hops.len(), 1);
+    assert_eq!(pool.base_denom, "gamm/pool/1");
+    assert_eq!(DenomTrace::parse("gamm/pool/1").unwrap().hops, vec![]);
+    let queried = serde_json::json!({"path": "transfer/channel-1", "base_denom": "anom"});
+    assert_eq!(DenomTrace::from_value(&queried).unwrap(), ibc_nom);
+
+    assert!(DenomTrace::parse("transfer/channel-1").is_err());
+    assert!(DenomTrace::parse("transfer//anom").is_err());
+    assert!(DenomTrace::parse(crate::ONOMY_IBC_NOM).is_err());
+}
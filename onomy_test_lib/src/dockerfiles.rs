@@ -1,104 +1,1065 @@
+use std::{collections::BTreeMap, path::Path, sync::OnceLock};
+
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use super_orchestrator::stacked_errors::{Error, Result, StackableErr};
+
+use crate::{chain::ChainSpec, executor::execute, versions::versions};
+
 pub const ONOMY_BASE: &str = "fedora:39";
 
-#[rustfmt::skip]
-pub const ONOMY_STD: &str = r#"FROM fedora:39
-RUN dnf install -y git rust cargo make cmake gcc gcc-c++ golang perl npm which iproute iputils net-tools procps-ng vim-minimal tmux htop tar jq openssl-devel pass
-"#;
+/// The `dnf` packages of [DockerfileBuilder::onomy_std]
+pub const ONOMY_STD_PACKAGES: &[&str] = &[
+    "git",
+    "rust",
+    "cargo",
+    "make",
+    "cmake",
+    "gcc",
+    "gcc-c++",
+    "golang",
+    "perl",
+    "npm",
+    "which",
+    "iproute",
+    "iputils",
+    "net-tools",
+    "procps-ng",
+    "vim-minimal",
+    "tmux",
+    "htop",
+    "tar",
+    "jq",
+    "openssl-devel",
+    "pass",
+];
 
-pub const COSMOVISOR: &str = r#"RUN go install cosmossdk.io/tools/cosmovisor/cmd/cosmovisor@latest
-ENV PATH=$PATH:/root/go/bin
-"#;
+/// The default `dockerfiles_dir` of our test binaries
+pub const DOCKERFILES_DIR: &str = "./tests/dockerfiles";
+/// The directory under `dockerfile_resources` that [ArtifactCache] uses
+pub const ARTIFACT_CACHE_SUBDIR: &str = "artifact_cache";
+/// The environment variable read by [ArtifactCache::from_env], it can be unset,
+/// "prefer", or "offline"
+pub const ARTIFACT_CACHE_ENV: &str = "ONOMY_ARTIFACT_CACHE";
 
-#[rustfmt::skip]
-pub const HERMES: &str = r#"ADD https://github.com/informalsystems/hermes/releases/download/v1.7.4/hermes-v1.7.4-x86_64-unknown-linux-gnu.tar.gz /root/.hermes/bin/
-RUN cd /root/.hermes/bin/ && tar -vxf *
-ENV PATH=$PATH:/root/.hermes/bin
-ENV HERMES_HOME="/root/.hermes"
-"#;
+/// The default path of the sha256 manifest, relative to the workspace root
+pub const SHA256_MANIFEST_PATH: &str = "./onomy_test_lib/sha256_manifest.toml";
+/// If set, the path of the sha256 manifest to use instead of
+/// [SHA256_MANIFEST_PATH]
+pub const SHA256_MANIFEST_ENV: &str = "ONOMY_SHA256_MANIFEST";
 
-pub fn dockerfile_hermes(config_resource: &str) -> String {
-    format!(
-        r#"{ONOMY_STD}
+static STD_MANIFEST: OnceLock<VersionManifest> = OnceLock::new();
 
-{HERMES}
+/// Pins the versions of artifacts to sha256 hashes, so that a re-uploaded
+/// release asset fails the image build instead of being silently tested. In
+/// TOML, this is a table for each artifact name mapping versions to hex hashes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VersionManifest {
+    pub sha256: BTreeMap<String, BTreeMap<String, String>>,
+}
 
-ADD ./dockerfile_resources/{config_resource} $HERMES_HOME/config.toml
-"#
-    )
+impl VersionManifest {
+    /// The manifest of this process as of the first call, see
+    /// [VersionManifest::load]. This is used by [Artifact::new].
+    ///
+    /// # Panics
+    ///
+    /// If the manifest at the path in [SHA256_MANIFEST_ENV] or at
+    /// [SHA256_MANIFEST_PATH] cannot be read or parsed
+    pub fn std() -> &'static Self {
+        STD_MANIFEST.get_or_init(|| Self::load().unwrap())
+    }
+
+    /// The path in [SHA256_MANIFEST_ENV] if it is set, else
+    /// [SHA256_MANIFEST_PATH]
+    pub fn path() -> String {
+        std::env::var(SHA256_MANIFEST_ENV).unwrap_or_else(|_| SHA256_MANIFEST_PATH.to_owned())
+    }
+
+    /// Loads from [VersionManifest::path] if it exists (the default path will
+    /// not inside of containers), else uses [VersionManifest::embedded]. This
+    /// way, pins for versions overridden at runtime (see
+    /// [versions](crate::versions)) can be added without recompiling.
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if std::env::var(SHA256_MANIFEST_ENV).is_ok() || Path::new(&path).exists() {
+            Self::from_file(&path).stack()
+        } else {
+            Ok(Self::embedded())
+        }
+    }
+
+    /// The manifest as of compilation
+    pub fn embedded() -> Self {
+        Self::from_toml(include_str!("../sha256_manifest.toml")).unwrap()
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        toml::from_str(s).stack_err(|| "VersionManifest::from_toml")
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .stack_err(|| format!("VersionManifest::from_file could not read {path}"))?;
+        Self::from_toml(&s).stack_err(|| format!("VersionManifest::from_file({path})"))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).stack_err(|| "VersionManifest::to_toml")
+    }
+
+    /// Writes the manifest to `path`, keeping the leading comment lines of an
+    /// existing file
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut contents: String = std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .take_while(|line| line.starts_with('#'))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        contents += &self.to_toml().stack()?;
+        std::fs::write(path, contents).stack_err(|| format!("VersionManifest::save({path})"))
+    }
+
+    pub fn get(&self, name: &str, version: &str) -> Option<&str> {
+        self.sha256
+            .get(name)
+            .and_then(|versions| versions.get(version))
+            .map(|s| s.as_str())
+    }
+
+    pub fn pin(&mut self, name: &str, version: &str, sha256: &str) {
+        self.sha256
+            .entry(name.to_owned())
+            .or_default()
+            .insert(version.to_owned(), sha256.to_lowercase());
+    }
+}
+
+/// Returns the hex sha256 hash of the file at `path`
+pub fn file_sha256(path: &str) -> Result<String> {
+    let bytes = std::fs::read(path).stack_err(|| format!("file_sha256 reading {path}"))?;
+    Ok(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+/// A file downloaded at image build time that can also be served from an
+/// [ArtifactCache]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    /// If set, cached files are keyed by and checked against this hex hash,
+    /// and the image build checks the file with `sha256sum`
+    pub sha256: Option<String>,
+}
+
+impl Artifact {
+    /// Uses the hash pinned in [VersionManifest::std] if there is one
+    pub fn new(name: &str, version: &str, url: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            url: url.to_owned(),
+            sha256: VersionManifest::std()
+                .get(name, version)
+                .map(|s| s.to_owned()),
+        }
+    }
+
+    pub fn sha256(mut self, sha256: &str) -> Self {
+        self.sha256 = Some(sha256.to_lowercase());
+        self
+    }
+
+    /// The file name in the cache, `{name}-{version}` or
+    /// `{name}-{version}-{sha256}` if the hash is pinned
+    pub fn cache_key(&self) -> String {
+        match self.sha256 {
+            Some(ref sha256) => format!("{}-{}-{sha256}", self.name, self.version),
+            None => format!("{}-{}", self.name, self.version),
+        }
+    }
+
+    /// The `daemon_name` binary of a GitHub release of `repo` (e.x.
+    /// "onomyprotocol/onomy")
+    pub fn github_release(repo: &str, version: &str, daemon_name: &str) -> Self {
+        Self::new(
+            daemon_name,
+            version,
+            &format!("https://github.com/{repo}/releases/download/{version}/{daemon_name}"),
+        )
+    }
+
+    /// The release tarball of cosmovisor
+    pub fn cosmovisor(version: &str) -> Self {
+        Self::new(
+            "cosmovisor",
+            version,
+            &format!(
+                "https://github.com/cosmos/cosmos-sdk/releases/download/cosmovisor%2F{version}/\
+                 cosmovisor-{version}-linux-amd64.tar.gz"
+            ),
+        )
+    }
+
+    /// The release tarball of hermes
+    pub fn hermes(version: &str) -> Self {
+        Self::new(
+            "hermes",
+            version,
+            &format!(
+                "https://github.com/informalsystems/hermes/releases/download/{version}/\
+                 hermes-{version}-x86_64-unknown-linux-gnu.tar.gz"
+            ),
+        )
+    }
+}
+
+/// How [DockerfileBuilder::resolve_artifacts] uses an [ArtifactCache]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactCacheMode {
+    /// Always download artifacts at build time
+    Disabled,
+    /// Use cached artifacts and download the rest at build time
+    Prefer,
+    /// Every artifact must be cached
+    Offline,
+}
+
+/// A cache of [Artifact]s at
+/// `{dockerfiles_dir}/dockerfile_resources/artifact_cache/`, so that images can
+/// be built without network access. The cache is populated with
+/// [ArtifactCache::fetch] (see the `fetch_artifacts` binary).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactCache {
+    pub dockerfiles_dir: String,
+    pub mode: ArtifactCacheMode,
+}
+
+impl ArtifactCache {
+    pub fn new(dockerfiles_dir: &str, mode: ArtifactCacheMode) -> Self {
+        Self {
+            dockerfiles_dir: dockerfiles_dir.to_owned(),
+            mode,
+        }
+    }
+
+    /// Uses [DOCKERFILES_DIR] with the mode from the [ARTIFACT_CACHE_ENV]
+    /// environment variable
+    pub fn from_env() -> Result<Self> {
+        let mode = match std::env::var(ARTIFACT_CACHE_ENV) {
+            Err(std::env::VarError::NotPresent) => ArtifactCacheMode::Disabled,
+            Ok(s) => match s.as_str() {
+                "" => ArtifactCacheMode::Disabled,
+                "prefer" => ArtifactCacheMode::Prefer,
+                "offline" => ArtifactCacheMode::Offline,
+                _ => {
+                    return Err(Error::from(format!(
+                        "{ARTIFACT_CACHE_ENV} is \"{s}\", expected \"prefer\" or \"offline\""
+                    )))
+                }
+            },
+            Err(e) => return Err(Error::from(format!("{ARTIFACT_CACHE_ENV}: {e}"))),
+        };
+        Ok(Self::new(DOCKERFILES_DIR, mode))
+    }
+
+    /// The path of `artifact` relative to `dockerfile_resources`
+    pub fn resource(&self, artifact: &Artifact) -> String {
+        format!("{ARTIFACT_CACHE_SUBDIR}/{}", artifact.cache_key())
+    }
+
+    pub fn path(&self, artifact: &Artifact) -> String {
+        format!(
+            "{}/dockerfile_resources/{}",
+            self.dockerfiles_dir,
+            self.resource(artifact)
+        )
+    }
+
+    pub fn contains(&self, artifact: &Artifact) -> bool {
+        Path::new(&self.path(artifact)).exists()
+    }
+
+    /// Returns an error if `artifact` is pinned and the cached file does not
+    /// have the pinned hash
+    pub fn verify(&self, artifact: &Artifact) -> Result<()> {
+        let expected = match artifact.sha256 {
+            Some(ref expected) => expected,
+            None => return Ok(()),
+        };
+        let path = self.path(artifact);
+        let actual = file_sha256(&path).stack()?;
+        if &actual != expected {
+            return Err(Error::from(format!(
+                "ArtifactCache::verify: {path} has sha256 {actual} instead of {expected}"
+            )))
+        }
+        Ok(())
+    }
+
+    /// Downloads `artifact` into the cache if it is not already there, checking
+    /// its hash if it is pinned
+    pub async fn fetch(&self, artifact: &Artifact) -> Result<()> {
+        let path = self.path(artifact);
+        if self.contains(artifact) && self.verify(artifact).is_ok() {
+            return Ok(())
+        }
+        let dir = format!(
+            "{}/dockerfile_resources/{ARTIFACT_CACHE_SUBDIR}",
+            self.dockerfiles_dir
+        );
+        std::fs::create_dir_all(&dir).stack_err(|| format!("creating {dir}"))?;
+        let tmp = format!("{path}.tmp");
+        let argv: Vec<String> = ["curl", "-L", "--fail", "-o", &tmp, &artifact.url]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        execute(&argv, false)
+            .await
+            .stack()?
+            .assert_success(&argv)
+            .stack_err(|| format!("ArtifactCache::fetch({artifact:?})"))?;
+        if let Some(ref expected) = artifact.sha256 {
+            let actual = file_sha256(&tmp).stack()?;
+            if &actual != expected {
+                let _ = std::fs::remove_file(&tmp);
+                return Err(Error::from(format!(
+                    "ArtifactCache::fetch({artifact:?}) got sha256 {actual}"
+                )))
+            }
+        }
+        std::fs::rename(&tmp, &path).stack_err(|| format!("moving {tmp} to {path}"))
+    }
+
+    /// Pins an unpinned `artifact` that is in the cache to the hash of the
+    /// cached file, moving the file to its pinned key and recording the hash in
+    /// `manifest`. Returns the pinned artifact.
+    pub fn pin(&self, artifact: &Artifact, manifest: &mut VersionManifest) -> Result<Artifact> {
+        if artifact.sha256.is_some() {
+            return Ok(artifact.clone())
+        }
+        let path = self.path(artifact);
+        let sha256 = file_sha256(&path).stack()?;
+        let pinned = artifact.clone().sha256(&sha256);
+        let pinned_path = self.path(&pinned);
+        std::fs::rename(&path, &pinned_path)
+            .stack_err(|| format!("moving {path} to {pinned_path}"))?;
+        manifest.pin(&artifact.name, &artifact.version, &sha256);
+        Ok(pinned)
+    }
+}
+
+/// Where a daemon binary for a cosmovisor genesis or upgrade comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DaemonSource {
+    /// Downloaded from the URL
+    Url(String),
+    /// A file in the `dockerfile_resources` directory
+    Resource(String),
+    /// Downloaded or taken from the [ArtifactCache]
+    Artifact(Artifact),
+}
+
+impl DaemonSource {
+    /// See [Artifact::github_release]
+    pub fn github_release(repo: &str, version: &str, daemon_name: &str) -> Self {
+        DaemonSource::Artifact(Artifact::github_release(repo, version, daemon_name))
+    }
+}
+
+/// A binary that gets placed at `cosmovisor/upgrades/{name}/bin`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaemonUpgrade {
+    pub name: String,
+    pub source: DaemonSource,
+}
+
+impl DaemonUpgrade {
+    pub fn new(name: &str, source: DaemonSource) -> Self {
+        Self {
+            name: name.to_owned(),
+            source,
+        }
+    }
+}
+
+/// A step of a [DockerfileBuilder]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DockerfileStep {
+    Comment(String),
+    Env {
+        key: String,
+        value: String,
+    },
+    AddUrl {
+        url: String,
+        dst: String,
+    },
+    AddResource {
+        resource: String,
+        dst: String,
+    },
+    /// Rendered as an `ADD` of the URL until
+    /// [DockerfileBuilder::resolve_artifacts] replaces it with a
+    /// [DockerfileStep::CopyResource]
+    AddArtifact {
+        artifact: Artifact,
+        dst: String,
+    },
+    /// Unlike `ADD`, `COPY` does not unpack local archives, so cached tarballs
+    /// end up in the same place as downloaded ones
+    CopyResource {
+        resource: String,
+        dst: String,
+    },
+    Run(String),
+    /// Inserted verbatim
+    Raw(String),
+}
+
+impl DockerfileStep {
+    fn render(&self) -> String {
+        match self {
+            DockerfileStep::Comment(comment) => format!("# {comment}"),
+            DockerfileStep::Env { key, value } => format!("ENV {key}={value}"),
+            DockerfileStep::AddUrl { url, dst } => format!("ADD {url} {dst}"),
+            DockerfileStep::AddResource { resource, dst } => {
+                format!("ADD ./dockerfile_resources/{resource} {dst}")
+            }
+            DockerfileStep::AddArtifact { artifact, dst } => format!("ADD {} {dst}", artifact.url),
+            DockerfileStep::CopyResource { resource, dst } => {
+                format!("COPY ./dockerfile_resources/{resource} {dst}")
+            }
+            DockerfileStep::Run(cmd) => format!("RUN {cmd}"),
+            DockerfileStep::Raw(s) => s.trim_end().to_owned(),
+        }
+    }
+}
+
+/// A builder for the dockerfiles of our containers. All `dnf` packages are
+/// deduplicated and installed in one layer directly after the base image, the
+/// other steps are rendered in the order they are added.
+///
+/// ```text
+/// let dockerfile = DockerfileBuilder::onomy_std()
+///     .cosmovisor("v1.5.0")
+///     .cosmovisor_daemon("onomyd", ".onomy", "v1.1.4", &source)
+///     .render();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerfileBuilder {
+    base_image: String,
+    packages: Vec<String>,
+    steps: Vec<DockerfileStep>,
+}
+
+impl DockerfileBuilder {
+    pub fn new(base_image: &str) -> Self {
+        Self {
+            base_image: base_image.to_owned(),
+            packages: vec![],
+            steps: vec![],
+        }
+    }
+
+    /// [ONOMY_BASE] with [ONOMY_STD_PACKAGES]
+    pub fn onomy_std() -> Self {
+        Self::new(ONOMY_BASE).dnf_packages(ONOMY_STD_PACKAGES)
+    }
+
+    /// Adds packages to the `dnf install`, ignoring ones that are already
+    /// included
+    pub fn dnf_packages<I, S>(mut self, packages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for package in packages {
+            let package = package.as_ref();
+            if !self.packages.iter().any(|p| p == package) {
+                self.packages.push(package.to_owned());
+            }
+        }
+        self
+    }
+
+    pub fn step(mut self, step: DockerfileStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn comment(self, comment: &str) -> Self {
+        self.step(DockerfileStep::Comment(comment.to_owned()))
+    }
+
+    pub fn env(self, key: &str, value: &str) -> Self {
+        self.step(DockerfileStep::Env {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+
+    pub fn add_url(self, url: &str, dst: &str) -> Self {
+        self.step(DockerfileStep::AddUrl {
+            url: url.to_owned(),
+            dst: dst.to_owned(),
+        })
+    }
+
+    /// Adds `resource` from the `dockerfile_resources` directory
+    pub fn add_resource(self, resource: &str, dst: &str) -> Self {
+        self.step(DockerfileStep::AddResource {
+            resource: resource.to_owned(),
+            dst: dst.to_owned(),
+        })
+    }
+
+    /// If `dst` is a directory ending in '/', the file name from the URL is
+    /// appended, so that the file ends up at the same path whether or not it is
+    /// resolved from an [ArtifactCache]. If the artifact is pinned, this is
+    /// followed by a `sha256sum -c` check of the file.
+    pub fn add_artifact(self, artifact: &Artifact, dst: &str) -> Self {
+        let dst = if dst.ends_with('/') {
+            let file_name = artifact.url.rsplit('/').next().unwrap_or(&artifact.name);
+            format!("{dst}{file_name}")
+        } else {
+            dst.to_owned()
+        };
+        let builder = self.step(DockerfileStep::AddArtifact {
+            artifact: artifact.clone(),
+            dst: dst.clone(),
+        });
+        match artifact.sha256 {
+            Some(ref sha256) => builder.run(&format!("echo \"{sha256}  {dst}\" | sha256sum -c")),
+            None => builder,
+        }
+    }
+
+    pub fn add_source(self, source: &DaemonSource, dst: &str) -> Self {
+        match source {
+            DaemonSource::Url(url) => self.add_url(url, dst),
+            DaemonSource::Resource(resource) => self.add_resource(resource, dst),
+            DaemonSource::Artifact(artifact) => self.add_artifact(artifact, dst),
+        }
+    }
+
+    pub fn run(self, cmd: &str) -> Self {
+        self.step(DockerfileStep::Run(cmd.to_owned()))
+    }
+
+    /// For snippets that do not fit the other steps
+    pub fn raw(self, s: &str) -> Self {
+        self.step(DockerfileStep::Raw(s.to_owned()))
+    }
+
+    /// Installs the `version` release of cosmovisor
+    pub fn cosmovisor(self, version: &str) -> Self {
+        self.dnf_packages(["tar"])
+            .add_artifact(&Artifact::cosmovisor(version), "/tmp/cosmovisor.tar.gz")
+            .run(
+                "mkdir -p /root/go/bin && tar -xzf /tmp/cosmovisor.tar.gz -C /root/go/bin \
+                 cosmovisor && rm /tmp/cosmovisor.tar.gz",
+            )
+            .env("PATH", "$PATH:/root/go/bin")
+    }
+
+    /// Installs the `version` release of hermes at `$HERMES_HOME`
+    pub fn hermes(self, version: &str) -> Self {
+        self.dnf_packages(["tar"])
+            .add_artifact(&Artifact::hermes(version), "/root/.hermes/bin/")
+            .run("cd /root/.hermes/bin/ && tar -vxf *")
+            .env("PATH", "$PATH:/root/.hermes/bin")
+            .env("HERMES_HOME", "\"/root/.hermes\"")
+    }
+
+    /// Sets the `DAEMON_NAME`, `DAEMON_HOME`, and `DAEMON_VERSION` variables
+    /// that cosmovisor and our setups use
+    pub fn daemon_env(self, daemon_name: &str, daemon_dir_name: &str, version: &str) -> Self {
+        self.env("DAEMON_NAME", &format!("\"{daemon_name}\""))
+            .env("DAEMON_HOME", &format!("\"/root/{daemon_dir_name}\""))
+            .env("DAEMON_VERSION", version)
+    }
+
+    /// Sets up the cosmovisor symlinks for the genesis binary that should
+    /// already be placed at
+    /// `$DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}`
+    pub fn cosmovisor_init(self, daemon_name: &str) -> Self {
+        let genesis = format!("$DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}");
+        self.comment("for manual testing")
+            .run(&format!("chmod +x {genesis}"))
+            .comment("set up symbolic links")
+            .run(&format!("cosmovisor init {genesis}"))
+            .comment("some commands don't like if the data directory does not exist")
+            .run("mkdir $DAEMON_HOME/data")
+    }
+
+    /// [DockerfileBuilder::daemon_env], places the genesis binary from
+    /// `source`, and then [DockerfileBuilder::cosmovisor_init]
+    pub fn cosmovisor_daemon(
+        self,
+        daemon_name: &str,
+        daemon_dir_name: &str,
+        version: &str,
+        source: &DaemonSource,
+    ) -> Self {
+        self.daemon_env(daemon_name, daemon_dir_name, version)
+            .add_source(
+                source,
+                &format!("$DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}"),
+            )
+            .cosmovisor_init(daemon_name)
+    }
+
+    /// Places the binary of `upgrade` in the cosmovisor layout
+    pub fn cosmovisor_upgrade(self, daemon_name: &str, upgrade: &DaemonUpgrade) -> Self {
+        let dst = format!(
+            "$DAEMON_HOME/cosmovisor/upgrades/{}/bin/{daemon_name}",
+            upgrade.name
+        );
+        self.add_source(&upgrade.source, &dst)
+            .run(&format!("chmod +x {dst}"))
+    }
+
+    /// Returns the `dockerfile_resources` files that the steps use
+    pub fn resources(&self) -> Vec<&str> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                DockerfileStep::AddResource { resource, .. }
+                | DockerfileStep::CopyResource { resource, .. } => Some(resource.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the artifacts that are still downloaded at build time
+    pub fn artifacts(&self) -> Vec<&Artifact> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                DockerfileStep::AddArtifact { artifact, .. } => Some(artifact),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Replaces the artifacts that are in `cache` with copies of the cached
+    /// files, after checking them with [ArtifactCache::verify]. In
+    /// [ArtifactCacheMode::Offline], this returns an error if any artifact is
+    /// missing from the cache.
+    pub fn resolve_artifacts(mut self, cache: &ArtifactCache) -> Result<Self> {
+        if cache.mode == ArtifactCacheMode::Disabled {
+            return Ok(self)
+        }
+        let mut missing = vec![];
+        for step in &mut self.steps {
+            if let DockerfileStep::AddArtifact { artifact, dst } = step {
+                if cache.contains(artifact) {
+                    cache.verify(artifact).stack()?;
+                    *step = DockerfileStep::CopyResource {
+                        resource: cache.resource(artifact),
+                        dst: dst.clone(),
+                    };
+                } else {
+                    missing.push(artifact.cache_key());
+                }
+            }
+        }
+        if (cache.mode == ArtifactCacheMode::Offline) && !missing.is_empty() {
+            return Err(Error::from(format!(
+                "DockerfileBuilder::resolve_artifacts: {missing:?} are missing from the artifact \
+                 cache in {}, run the `fetch_artifacts` binary first",
+                cache.dockerfiles_dir
+            )))
+        }
+        Ok(self)
+    }
+
+    /// Returns an error listing the files of [DockerfileBuilder::resources]
+    /// that are missing from `{dockerfiles_dir}/dockerfile_resources/`. This
+    /// should be called after any resources have been copied or generated.
+    pub fn validate_resources(&self, dockerfiles_dir: &str) -> Result<()> {
+        validate_resources(&self.resources(), dockerfiles_dir).stack()
+    }
+
+    pub fn render(&self) -> String {
+        let mut s = format!("FROM {}\n", self.base_image);
+        if !self.packages.is_empty() {
+            s += &format!("RUN dnf install -y {}\n", self.packages.join(" "));
+        }
+        for step in &self.steps {
+            s += &step.render();
+            s.push('\n');
+        }
+        s
+    }
+
+    /// [DockerfileBuilder::resolve_artifacts] with [ArtifactCache::from_env]
+    /// and then [DockerfileBuilder::render]
+    pub fn build(&self) -> Result<String> {
+        for artifact in self.artifacts() {
+            if artifact.sha256.is_none() {
+                warn!(
+                    "{} {} is not pinned in {}, run `cargo r --bin fetch_artifacts -- \
+                     --update-manifest`",
+                    artifact.name,
+                    artifact.version,
+                    VersionManifest::path()
+                );
+            }
+        }
+        let cache = ArtifactCache::from_env().stack()?;
+        Ok(self.clone().resolve_artifacts(&cache).stack()?.render())
+    }
 }
 
-//ADD https://github.com/onomyprotocol/onomy/releases/download/$DAEMON_VERSION/{daemon_name}
-//$DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}
+/// Returns the `dockerfile_resources` files that a rendered dockerfile `ADD`s
+/// or `COPY`s
+pub fn dockerfile_resources(dockerfile: &str) -> Vec<&str> {
+    dockerfile
+        .lines()
+        .filter_map(|line| {
+            let rest = line
+                .strip_prefix("ADD ")
+                .or_else(|| line.strip_prefix("COPY "))?;
+            rest.trim_start()
+                .strip_prefix("./dockerfile_resources/")?
+                .split_whitespace()
+                .next()
+        })
+        .collect()
+}
+
+/// Returns an error listing the `resources` that are missing from
+/// `{dockerfiles_dir}/dockerfile_resources/`
+pub fn validate_resources(resources: &[&str], dockerfiles_dir: &str) -> Result<()> {
+    let missing: Vec<&str> = resources
+        .iter()
+        .copied()
+        .filter(|resource| {
+            !Path::new(&format!(
+                "{dockerfiles_dir}/dockerfile_resources/{resource}"
+            ))
+            .exists()
+        })
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::from(format!(
+            "validate_resources: {missing:?} are missing from \
+             {dockerfiles_dir}/dockerfile_resources/"
+        )))
+    }
+}
+
+pub fn dockerfile_hermes(config_resource: &str) -> Result<String> {
+    DockerfileBuilder::onomy_std()
+        .hermes(&versions().stack()?.hermes)
+        .add_resource(config_resource, "$HERMES_HOME/config.toml")
+        .build()
+}
 
 pub fn onomy_std_cosmos_daemon_with_arbitrary(
     daemon_name: &str,
     daemon_dir_name: &str,
     version: &str,
     arbitrary: &str,
-) -> String {
-    format!(
-        r#"{ONOMY_STD}
-{COSMOVISOR}
+) -> Result<String> {
+    DockerfileBuilder::onomy_std()
+        .cosmovisor(&versions().stack()?.cosmovisor)
+        .daemon_env(daemon_name, daemon_dir_name, version)
+        .raw(arbitrary)
+        .cosmovisor_init(daemon_name)
+        .build()
+}
 
-ENV DAEMON_NAME="{daemon_name}"
-ENV DAEMON_HOME="/root/{daemon_dir_name}"
-ENV DAEMON_VERSION={version}
+pub fn onomy_std_cosmos_daemon(
+    daemon_name: &str,
+    daemon_dir_name: &str,
+    version: &str,
+    dockerfile_resource: &str,
+) -> Result<String> {
+    DockerfileBuilder::onomy_std()
+        .cosmovisor(&versions().stack()?.cosmovisor)
+        .cosmovisor_daemon(
+            daemon_name,
+            daemon_dir_name,
+            version,
+            &DaemonSource::Resource(dockerfile_resource.to_owned()),
+        )
+        .build()
+}
 
-{arbitrary}
+/// Like [onomy_std_cosmos_daemon], but with the genesis binary from
+/// `genesis_source` and with the binaries of `upgrades` in the cosmovisor
+/// layout, in order, for multi-hop upgrade tests
+pub fn onomy_std_cosmos_daemon_with_upgrades(
+    daemon_name: &str,
+    daemon_dir_name: &str,
+    genesis_version: &str,
+    genesis_source: &DaemonSource,
+    upgrades: &[DaemonUpgrade],
+) -> Result<String> {
+    let mut builder = DockerfileBuilder::onomy_std()
+        .cosmovisor(&versions().stack()?.cosmovisor)
+        .cosmovisor_daemon(
+            daemon_name,
+            daemon_dir_name,
+            genesis_version,
+            genesis_source,
+        );
+    for upgrade in upgrades {
+        builder = builder.cosmovisor_upgrade(daemon_name, upgrade);
+    }
+    builder.build()
+}
 
-# for manual testing
-RUN chmod +x $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}
+pub fn onomyd_release(version: &str) -> DaemonSource {
+    DaemonSource::github_release("onomyprotocol/onomy", version, "onomyd")
+}
 
-# set up symbolic links
-RUN cosmovisor init $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}
+pub fn dockerfile_onomyd() -> Result<String> {
+    ChainSpec::onomy().stack()?.dockerfile()
+}
 
-# some commands don't like if the data directory does not exist
-RUN mkdir $DAEMON_HOME/data
-"#
+/// `onomyd` starting from `genesis_version` with the release binaries of
+/// `upgrade_versions`, where the upgrade names are the versions
+pub fn dockerfile_onomyd_upgrades(
+    genesis_version: &str,
+    upgrade_versions: &[&str],
+) -> Result<String> {
+    let upgrades: Vec<DaemonUpgrade> = upgrade_versions
+        .iter()
+        .map(|version| DaemonUpgrade::new(version, onomyd_release(version)))
+        .collect();
+    onomy_std_cosmos_daemon_with_upgrades(
+        "onomyd",
+        ".onomy",
+        genesis_version,
+        &onomyd_release(genesis_version),
+        &upgrades,
     )
 }
 
-#[rustfmt::skip]
-pub fn onomy_std_cosmos_daemon(
-    daemon_name: &str,
-    daemon_dir_name: &str,
-    version: &str,
-    dockerfile_resource: &str,
-) -> String {
-    let arbitrary = format!(
-        r#"ADD ./dockerfile_resources/{dockerfile_resource} $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}"#
-    );
-    onomy_std_cosmos_daemon_with_arbitrary(daemon_name, daemon_dir_name, version, &arbitrary)
+pub fn onexd_release(version: &str) -> DaemonSource {
+    DaemonSource::github_release("onomyprotocol/multiverse", version, "onexd")
 }
 
-pub const ONOMYD_VERSION: &str = "v1.1.4";
-pub const ONEXD_VERSION: &str = "v1.0.3-onex";
-pub const ONEXD_FH_VERSION: &str = "v1.0.3-onex-fh";
-pub const STANDALONE_ONEX_VERSION: &str = "v1.1.0";
-pub const STANDALONE_ONEX_FH_VERSION: &str = "v1.1.0-fh";
+pub fn dockerfile_onexd() -> Result<String> {
+    ChainSpec::onex().stack()?.dockerfile()
+}
 
-#[rustfmt::skip]
-pub const DOWNLOAD_ONOMYD: &str = r#"ADD https://github.com/onomyprotocol/onomy/releases/download/$DAEMON_VERSION/onomyd $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/onomyd"#;
+/// `onexd` starting from `genesis_version` with the release binaries of
+/// `upgrade_versions`, where the upgrade names are the versions
+pub fn dockerfile_onexd_upgrades(
+    genesis_version: &str,
+    upgrade_versions: &[&str],
+) -> Result<String> {
+    let upgrades: Vec<DaemonUpgrade> = upgrade_versions
+        .iter()
+        .map(|version| DaemonUpgrade::new(version, onexd_release(version)))
+        .collect();
+    onomy_std_cosmos_daemon_with_upgrades(
+        "onexd",
+        ".onomy_onex",
+        genesis_version,
+        &onexd_release(genesis_version),
+        &upgrades,
+    )
+}
 
-pub fn dockerfile_onomyd() -> String {
-    onomy_std_cosmos_daemon_with_arbitrary("onomyd", ".onomy", ONOMYD_VERSION, DOWNLOAD_ONOMYD)
+pub fn standalone_onexd_release(version: &str) -> DaemonSource {
+    DaemonSource::github_release("onomyprotocol/market", version, "marketd")
 }
 
-#[rustfmt::skip]
-pub const DOWNLOAD_ONEXD: &str = r#"ADD https://github.com/onomyprotocol/multiverse/releases/download/$DAEMON_VERSION/onexd $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/onexd"#;
+pub fn dockerfile_standalone_onexd() -> Result<String> {
+    ChainSpec::market_standalone().stack()?.dockerfile()
+}
+
+/// The release [Artifact] of one of our daemons by its binary name
+pub fn release_artifact(daemon_name: &str, version: &str) -> Result<Artifact> {
+    let repo = match daemon_name {
+        "onomyd" => "onomyprotocol/onomy",
+        "onexd" => "onomyprotocol/multiverse",
+        "marketd" => "onomyprotocol/market",
+        _ => {
+            return Err(Error::from(format!(
+                "release_artifact: unknown daemon {daemon_name}"
+            )))
+        }
+    };
+    Ok(Artifact::github_release(repo, version, daemon_name))
+}
 
-pub fn dockerfile_onexd() -> String {
-    onomy_std_cosmos_daemon_with_arbitrary("onexd", ".onomy_onex", ONEXD_VERSION, DOWNLOAD_ONEXD)
+/// The artifacts used by the standard dockerfiles in this module and by the
+/// upgrade tests, at the current [versions]
+pub fn std_artifacts() -> Result<Vec<Artifact>> {
+    let versions = versions().stack()?;
+    let mut artifacts = vec![
+        Artifact::cosmovisor(&versions.cosmovisor),
+        Artifact::hermes(&versions.hermes),
+    ];
+    for (daemon_name, version) in [
+        ("onomyd", &versions.onomyd),
+        ("onexd", &versions.onexd),
+        ("marketd", &versions.marketd),
+        ("onomyd", &versions.onomyd_upgrade_from),
+        ("onomyd", &versions.onomyd_upgrade_to),
+        ("onexd", &versions.onexd_upgrade_from),
+        ("onexd", &versions.onexd_upgrade_to),
+    ] {
+        let artifact = release_artifact(daemon_name, version).stack()?;
+        if !artifacts.contains(&artifact) {
+            artifacts.push(artifact);
+        }
+    }
+    Ok(artifacts)
 }
 
 #[rustfmt::skip]
-pub const DOWNLOAD_STANDALONE_ONEXD: &str = r#"ADD https://github.com/onomyprotocol/market/releases/download/$DAEMON_VERSION/marketd $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/marketd"#;
-
-pub fn dockerfile_standalone_onexd() -> String {
-    onomy_std_cosmos_daemon_with_arbitrary(
-        "marketd",
-        ".market",
-        STANDALONE_ONEX_VERSION,
-        DOWNLOAD_STANDALONE_ONEXD,
-    )
+#[test]
+fn test_dockerfile_builder() {
+    let builder = DockerfileBuilder::onomy_std()
+        .dnf_packages(["jq", "protobuf", "git"])
+        .cosmovisor("v1.5.0")
+        .cosmovisor_daemon(
+            "onomyd",
+            ".onomy",
+            "v1.1.2",
+            &onomyd_release("v1.1.2"),
+        )
+        .cosmovisor_upgrade(
+            "onomyd",
+            &DaemonUpgrade::new("next", DaemonSource::Resource("onomyd".to_owned())),
+        );
+    assert_eq!(builder.resources(), ["onomyd"]);
+    assert_eq!(dockerfile_resources(&builder.render()), ["onomyd"]);
+    assert!(builder
+        .validate_resources("./nonexistent_dockerfiles")
+        .is_err());
+    assert_eq!(
+        builder.render(),
+        r#"FROM fedora:39
+RUN dnf install -y git rust cargo make cmake gcc gcc-c++ golang perl npm which iproute iputils net-tools procps-ng vim-minimal tmux htop tar jq openssl-devel pass protobuf
+ADD https://github.com/cosmos/cosmos-sdk/releases/download/cosmovisor%2Fv1.5.0/cosmovisor-v1.5.0-linux-amd64.tar.gz /tmp/cosmovisor.tar.gz
+RUN mkdir -p /root/go/bin && tar -xzf /tmp/cosmovisor.tar.gz -C /root/go/bin cosmovisor && rm /tmp/cosmovisor.tar.gz
+ENV PATH=$PATH:/root/go/bin
+ENV DAEMON_NAME="onomyd"
+ENV DAEMON_HOME="/root/.onomy"
+ENV DAEMON_VERSION=v1.1.2
+ADD https://github.com/onomyprotocol/onomy/releases/download/v1.1.2/onomyd $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/onomyd
+# for manual testing
+RUN chmod +x $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/onomyd
+# set up symbolic links
+RUN cosmovisor init $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/onomyd
+# some commands don't like if the data directory does not exist
+RUN mkdir $DAEMON_HOME/data
+ADD ./dockerfile_resources/onomyd $DAEMON_HOME/cosmovisor/upgrades/next/bin/onomyd
+RUN chmod +x $DAEMON_HOME/cosmovisor/upgrades/next/bin/onomyd
+"#
+    );
+}
+
+#[test]
+fn test_resolve_artifacts() {
+    let dockerfiles_dir = std::env::temp_dir()
+        .join("onomy_test_resolve_artifacts")
+        .to_str()
+        .unwrap()
+        .to_owned();
+    // the hash of an empty file
+    let empty_sha256 = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+    let release =
+        Artifact::github_release("onomyprotocol/onomy", "v1.1.4", "onomyd").sha256(empty_sha256);
+    assert_eq!(
+        release.cache_key(),
+        format!("onomyd-v1.1.4-{}", empty_sha256.to_lowercase())
+    );
+    let offline = ArtifactCache::new(&dockerfiles_dir, ArtifactCacheMode::Offline);
+    std::fs::create_dir_all(format!(
+        "{dockerfiles_dir}/dockerfile_resources/{ARTIFACT_CACHE_SUBDIR}"
+    ))
+    .unwrap();
+    std::fs::write(offline.path(&release), b"").unwrap();
+    assert!(offline.contains(&release));
+    offline.verify(&release).unwrap();
+
+    let builder = DockerfileBuilder::new(ONOMY_BASE)
+        .cosmovisor("v1.5.0")
+        .add_source(&DaemonSource::Artifact(release.clone()), "/root/onomyd");
+    assert_eq!(builder.artifacts().len(), 2);
+    assert!(builder.clone().resolve_artifacts(&offline).is_err());
+    let prefer = ArtifactCache::new(&dockerfiles_dir, ArtifactCacheMode::Prefer);
+    let resolved = builder.clone().resolve_artifacts(&prefer).unwrap();
+    assert_eq!(resolved.artifacts(), [&Artifact::cosmovisor("v1.5.0")]);
+    let resource = format!("artifact_cache/{}", release.cache_key());
+    assert_eq!(resolved.resources(), [resource.as_str()]);
+    let rendered = resolved.render();
+    assert!(rendered.contains(&format!(
+        "COPY ./dockerfile_resources/{resource} /root/onomyd\n"
+    )));
+    assert!(rendered.contains(&format!(
+        "RUN echo \"{}  /root/onomyd\" | sha256sum -c\n",
+        empty_sha256.to_lowercase()
+    )));
+
+    // a cached file that does not match its pin
+    std::fs::write(offline.path(&release), b"reuploaded").unwrap();
+    assert!(offline.verify(&release).is_err());
+    assert!(builder.resolve_artifacts(&prefer).is_err());
+}
+
+#[test]
+fn test_version_manifest() {
+    assert!(VersionManifest::std()
+        .to_toml()
+        .and_then(|s| VersionManifest::from_toml(&s))
+        .is_ok());
+
+    let dockerfiles_dir = std::env::temp_dir()
+        .join("onomy_test_version_manifest")
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let cache = ArtifactCache::new(&dockerfiles_dir, ArtifactCacheMode::Offline);
+    std::fs::create_dir_all(format!(
+        "{dockerfiles_dir}/dockerfile_resources/{ARTIFACT_CACHE_SUBDIR}"
+    ))
+    .unwrap();
+    let mut release = Artifact::github_release("onomyprotocol/market", "v0.0.0-test", "marketd");
+    release.sha256 = None;
+    std::fs::write(cache.path(&release), b"").unwrap();
+    let mut manifest = VersionManifest::default();
+    let pinned = cache.pin(&release, &mut manifest).unwrap();
+    assert!(!cache.contains(&release));
+    cache.verify(&pinned).unwrap();
+
+    let manifest = VersionManifest::from_toml(&manifest.to_toml().unwrap()).unwrap();
+    assert_eq!(
+        manifest.get("marketd", "v0.0.0-test"),
+        Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+    );
+    assert_eq!(manifest.get("marketd", "v0.0.1-test"), None);
+
+    // once pins are committed, every artifact of the versions manifest must be
+    // pinned
+    let embedded = VersionManifest::embedded();
+    let unpinned: Vec<String> = std_artifacts()
+        .unwrap()
+        .into_iter()
+        .filter(|artifact| embedded.get(&artifact.name, &artifact.version).is_none())
+        .map(|artifact| artifact.cache_key())
+        .collect();
+    assert!(
+        embedded.sha256.is_empty() || unpinned.is_empty(),
+        "{unpinned:?} are not pinned in {SHA256_MANIFEST_PATH}"
+    );
 }
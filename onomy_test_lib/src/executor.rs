@@ -0,0 +1,240 @@
+//! Pluggable execution of the external commands run by library functions
+//!
+//! `CosmovisorCmd`, `sh_hermes*` and friends run their commands through the
+//! [CommandExecutor] of the current task. Normally this is the
+//! [RealExecutor], but a [ReplayExecutor] can be used with
+//! [with_command_executor] to test output parsing without a daemon.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use super_orchestrator::{
+    stacked_errors::{Error, Result, StackableErr},
+    Command,
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The result of running a command
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// A successful output with `stdout`
+    pub fn success(stdout: &str) -> Self {
+        Self {
+            success: true,
+            stdout: stdout.to_owned(),
+            stderr: String::new(),
+        }
+    }
+
+    /// An unsuccessful output with `stderr`
+    pub fn failure(stderr: &str) -> Self {
+        Self {
+            success: false,
+            stdout: String::new(),
+            stderr: stderr.to_owned(),
+        }
+    }
+
+    pub fn assert_success(&self, argv: &[String]) -> Result<()> {
+        if self.success {
+            Ok(())
+        } else {
+            Err(Error::from(format!(
+                "command {argv:?} was unsuccessful, stderr: {}",
+                self.stderr
+            )))
+        }
+    }
+}
+
+pub trait CommandExecutor: Send + Sync {
+    /// Runs `argv`, where `argv[0]` is the program. If `debug`, the output
+    /// should also be forwarded to stdout.
+    fn execute<'a>(
+        &'a self,
+        argv: &'a [String],
+        debug: bool,
+    ) -> BoxFuture<'a, Result<CommandOutput>>;
+}
+
+/// Runs commands with `super_orchestrator::Command`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealExecutor;
+
+impl CommandExecutor for RealExecutor {
+    fn execute<'a>(
+        &'a self,
+        argv: &'a [String],
+        debug: bool,
+    ) -> BoxFuture<'a, Result<CommandOutput>> {
+        Box::pin(async move {
+            let (program, args) = argv.split_first().stack_err(|| "empty argv")?;
+            let comres = Command::new(program)
+                .args(args)
+                .debug(debug)
+                .run_to_completion()
+                .await
+                .stack()?;
+            Ok(CommandOutput {
+                success: comres.assert_success().is_ok(),
+                stdout: comres
+                    .stdout_as_utf8()
+                    .stack_err_locationless(|| "`Command` output was not UTF-8")?
+                    .to_owned(),
+                stderr: comres
+                    .stderr_as_utf8()
+                    .stack_err_locationless(|| "`Command` output was not UTF-8")?
+                    .to_owned(),
+            })
+        })
+    }
+}
+
+/// Returns canned outputs keyed on the exact argv. If multiple outputs are
+/// registered for the same argv they are returned in order, with the last one
+/// repeating (which is useful for functions that poll).
+#[derive(Debug, Default)]
+pub struct ReplayExecutor {
+    responses: Mutex<BTreeMap<Vec<String>, VecDeque<CommandOutput>>>,
+    calls: Mutex<Vec<Vec<String>>>,
+}
+
+impl ReplayExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `output` for `argv`
+    pub fn respond_argv<I, S>(&self, argv: I, output: CommandOutput)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let argv = argv.into_iter().map(|s| s.as_ref().to_owned()).collect();
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(argv)
+            .or_default()
+            .push_back(output);
+    }
+
+    /// Registers `output` for `command`, which is split by whitespace into the
+    /// argv
+    pub fn respond(&self, command: &str, output: CommandOutput) {
+        self.respond_argv(command.split_whitespace(), output)
+    }
+
+    /// Returns all argvs that have been executed so far
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl CommandExecutor for ReplayExecutor {
+    fn execute<'a>(
+        &'a self,
+        argv: &'a [String],
+        _debug: bool,
+    ) -> BoxFuture<'a, Result<CommandOutput>> {
+        Box::pin(async move {
+            self.calls.lock().unwrap().push(argv.to_vec());
+            let mut responses = self.responses.lock().unwrap();
+            let queue = responses
+                .get_mut(argv)
+                .stack_err(|| format!("ReplayExecutor has no response for {argv:?}"))?;
+            let output = if queue.len() > 1 {
+                queue.pop_front().unwrap()
+            } else {
+                queue.front().unwrap().clone()
+            };
+            Ok(output)
+        })
+    }
+}
+
+/// Runs `f` with a [ReplayExecutor] that returns a successful `hermes --json`
+/// output with the `result` of each of `responses`
+#[cfg(test)]
+pub(crate) async fn with_hermes_replay<I, S, F>(responses: I, f: F) -> F::Output
+where
+    I: IntoIterator<Item = (S, serde_json::Value)>,
+    S: AsRef<str>,
+    F: Future,
+{
+    let replay = Arc::new(ReplayExecutor::new());
+    for (command, result) in responses {
+        replay.respond(
+            command.as_ref(),
+            CommandOutput::success(&format!(
+                "{}\n",
+                serde_json::json!({"result": result, "status": "success"})
+            )),
+        );
+    }
+    with_command_executor(replay, f).await
+}
+
+tokio::task_local! {
+    static EXECUTOR: Arc<dyn CommandExecutor>;
+}
+
+/// Runs `f` with all library commands executed by `executor`. Note that this
+/// only applies to the current task, tasks spawned from within `f` use the
+/// [RealExecutor].
+pub async fn with_command_executor<F: Future>(
+    executor: Arc<dyn CommandExecutor>,
+    f: F,
+) -> F::Output {
+    EXECUTOR.scope(executor, f).await
+}
+
+/// Returns the executor of the current task
+pub fn command_executor() -> Arc<dyn CommandExecutor> {
+    EXECUTOR
+        .try_with(|executor| executor.clone())
+        .unwrap_or_else(|_| Arc::new(RealExecutor))
+}
+
+/// Builds an argv from the first item of `program_with_args` prefixed with
+/// `prefix` and split by whitespace, followed by the rest of the items
+/// unchanged. This matches how `super_orchestrator::Command::new` treats its
+/// argument.
+pub fn argv_with_prefix<I, S>(prefix: &str, program_with_args: I) -> Result<Vec<String>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut argv = vec![];
+    for (i, part) in program_with_args.into_iter().enumerate() {
+        if i == 0 {
+            let s = format!("{prefix} {}", part.as_ref());
+            argv.extend(s.split_whitespace().map(|s| s.to_owned()));
+        } else {
+            argv.push(part.as_ref().to_owned());
+        }
+    }
+    if argv.is_empty() {
+        return Err(Error::from("called with an empty iterator"))
+    }
+    Ok(argv)
+}
+
+/// Runs `argv` with the [command_executor] of the current task
+pub async fn execute(argv: &[String], debug: bool) -> Result<CommandOutput> {
+    command_executor()
+        .execute(argv, debug)
+        .await
+        .stack_err(|| format!("execute({argv:?})"))
+}
@@ -0,0 +1,391 @@
+//! Typed editing of `genesis.json` files
+
+use serde_json::{json, Value};
+use super_orchestrator::{
+    stacked_errors::{Error, Result, StackableErr},
+    FileOptions,
+};
+
+/// A wrapper around a genesis `Value` with typed methods for the edits that
+/// our chain setups make. Every method checks that the path it edits exists
+/// beforehand, so that genesis layout changes between Cosmos-SDK versions
+/// result in an error instead of silently adding unused fields.
+///
+/// Where the SDK has moved a field between versions (e.x. the gov params
+/// moving from `deposit_params`/`voting_params` into `params` with gov v1),
+/// both layouts are handled.
+#[derive(Debug, Clone)]
+pub struct GenesisBuilder {
+    pub genesis: Value,
+}
+
+impl GenesisBuilder {
+    pub fn new(genesis: Value) -> Self {
+        Self { genesis }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(genesis_s: &str) -> Result<Self> {
+        let genesis: Value = serde_json::from_str(genesis_s).stack()?;
+        Ok(Self::new(genesis))
+    }
+
+    pub async fn from_file(genesis_file_path: &str) -> Result<Self> {
+        let genesis_s = FileOptions::read_to_string(genesis_file_path)
+            .await
+            .stack()?;
+        Self::from_str(&genesis_s).stack_err(|| format!("from_file({genesis_file_path})"))
+    }
+
+    pub fn to_json_string(&self) -> Result<String> {
+        serde_json::to_string(&self.genesis).stack()
+    }
+
+    pub async fn write_to_file(&self, genesis_file_path: &str) -> Result<()> {
+        FileOptions::write_str(genesis_file_path, &self.to_json_string().stack()?)
+            .await
+            .stack()
+    }
+
+    /// Returns the value at `path`, or an error naming the full path if any
+    /// part of it does not exist
+    pub fn get(&self, path: &[&str]) -> Result<&Value> {
+        let mut v = &self.genesis;
+        for (i, key) in path.iter().enumerate() {
+            v = index(v, key).stack_err(|| missing(path, i))?;
+        }
+        Ok(v)
+    }
+
+    /// Same as [GenesisBuilder::get] but mutable
+    pub fn get_mut(&mut self, path: &[&str]) -> Result<&mut Value> {
+        let mut v = &mut self.genesis;
+        for (i, key) in path.iter().enumerate() {
+            v = index_mut(v, key).stack_err(|| missing(path, i))?;
+        }
+        Ok(v)
+    }
+
+    /// Sets the value at an existing `path`
+    pub fn set(&mut self, path: &[&str], value: impl Into<Value>) -> Result<&mut Self> {
+        *self.get_mut(path).stack()? = value.into();
+        Ok(self)
+    }
+
+    /// Returns if `path` exists
+    pub fn contains(&self, path: &[&str]) -> bool {
+        self.get(path).is_ok()
+    }
+
+    /// Replaces every string value that exactly equals `from` with `to`. This
+    /// is used to rename the default "stake" denom that `init` puts
+    /// everywhere.
+    pub fn rename_denom(&mut self, from: &str, to: &str) -> &mut Self {
+        fn recurse(v: &mut Value, from: &str, to: &str) {
+            match v {
+                Value::String(s) if s == from => *s = to.to_owned(),
+                Value::Array(a) => {
+                    for v in a {
+                        recurse(v, from, to);
+                    }
+                }
+                Value::Object(o) => {
+                    for v in o.values_mut() {
+                        recurse(v, from, to);
+                    }
+                }
+                _ => (),
+            }
+        }
+        recurse(&mut self.genesis, from, to);
+        self
+    }
+
+    pub fn chain_id(&mut self, chain_id: &str) -> Result<&mut Self> {
+        self.set(&["chain_id"], chain_id)
+    }
+
+    pub fn genesis_time(&mut self, genesis_time: &str) -> Result<&mut Self> {
+        self.set(&["genesis_time"], genesis_time)
+    }
+
+    /// Sets the gov voting period (e.x. "800ms")
+    pub fn gov_voting_period(&mut self, period: &str) -> Result<&mut Self> {
+        let path = self
+            .gov_path(&["voting_params", "voting_period"], "voting_period")
+            .stack()?;
+        self.set(&as_strs(&path), period)
+    }
+
+    /// Sets the gov max deposit period (e.x. "800ms")
+    pub fn gov_max_deposit_period(&mut self, period: &str) -> Result<&mut Self> {
+        let path = self
+            .gov_path(
+                &["deposit_params", "max_deposit_period"],
+                "max_deposit_period",
+            )
+            .stack()?;
+        self.set(&as_strs(&path), period)
+    }
+
+    /// Sets the gov min deposit to a single coin
+    pub fn gov_min_deposit(&mut self, amount: &str, denom: &str) -> Result<&mut Self> {
+        let path = self
+            .gov_path(&["deposit_params", "min_deposit"], "min_deposit")
+            .stack()?;
+        self.set(&as_strs(&path), json!([{"denom": denom, "amount": amount}]))
+    }
+
+    pub fn bond_denom(&mut self, denom: &str) -> Result<&mut Self> {
+        self.set(&["app_state", "staking", "params", "bond_denom"], denom)
+    }
+
+    pub fn community_tax(&mut self, community_tax: &str) -> Result<&mut Self> {
+        self.set(
+            &["app_state", "distribution", "params", "community_tax"],
+            community_tax,
+        )
+    }
+
+    /// Onomy provider only
+    pub fn min_global_self_delegation(&mut self, amount: &str) -> Result<&mut Self> {
+        self.set(
+            &[
+                "app_state",
+                "staking",
+                "params",
+                "min_global_self_delegation",
+            ],
+            amount,
+        )
+    }
+
+    /// Replaces the bank denom metadata, `denom_metadata` should be an array
+    /// such as what [crate::nom_denom] returns
+    pub fn denom_metadata(&mut self, denom_metadata: Value) -> Result<&mut Self> {
+        if !denom_metadata.is_array() {
+            return Err(Error::from("denom_metadata should be an array"))
+        }
+        self.set(&["app_state", "bank", "denom_metadata"], denom_metadata)
+    }
+
+    /// Adds a `BaseAccount` with no pubkey to `auth.accounts`. Does nothing if
+    /// the account already exists.
+    pub fn add_account(&mut self, address: &str) -> Result<&mut Self> {
+        let accounts = self
+            .get_mut(&["app_state", "auth", "accounts"])
+            .stack()?
+            .as_array_mut()
+            .stack_err(|| "auth.accounts is not an array")?;
+        if !accounts
+            .iter()
+            .any(|a| a.get("address").and_then(|a| a.as_str()) == Some(address))
+        {
+            accounts.push(json!({
+                "@type": "/cosmos.auth.v1beta1.BaseAccount",
+                "address": address,
+                "pub_key": null,
+                "account_number": "0",
+                "sequence": "0"
+            }));
+        }
+        Ok(self)
+    }
+
+    /// Sets the bank balance of `denom` for `address` to `amount`, creating the
+    /// balance entry if needed and keeping the coins sorted.
+    ///
+    /// Note: this does not update `bank.supply`, which recent SDK versions
+    /// recompute if it is left empty.
+    pub fn add_balance(&mut self, address: &str, amount: &str, denom: &str) -> Result<&mut Self> {
+        let balances = self
+            .get_mut(&["app_state", "bank", "balances"])
+            .stack()?
+            .as_array_mut()
+            .stack_err(|| "bank.balances is not an array")?;
+        let coin = json!({"denom": denom, "amount": amount});
+        if let Some(balance) = balances
+            .iter_mut()
+            .find(|b| b.get("address").and_then(|a| a.as_str()) == Some(address))
+        {
+            let coins = balance
+                .get_mut("coins")
+                .and_then(|c| c.as_array_mut())
+                .stack_err(|| format!("balance of {address} has no coins array"))?;
+            if let Some(existing) = coins
+                .iter_mut()
+                .find(|c| c.get("denom").and_then(|d| d.as_str()) == Some(denom))
+            {
+                *existing = coin;
+            } else {
+                coins.push(coin);
+                // the SDK requires coins to be sorted by denom
+                coins.sort_by(|a, b| a["denom"].as_str().cmp(&b["denom"].as_str()));
+            }
+        } else {
+            balances.push(json!({"address": address, "coins": [coin]}));
+        }
+        Ok(self)
+    }
+
+    /// Adds both the account and its balance
+    pub fn add_account_with_balance(
+        &mut self,
+        address: &str,
+        amount: &str,
+        denom: &str,
+    ) -> Result<&mut Self> {
+        self.add_account(address).stack()?;
+        self.add_balance(address, amount, denom).stack()
+    }
+
+    /// Sets the consumer state of a consumer chain, `ccvconsumer_state_s` is
+    /// typically what `cosmovisor_add_consumer` returns
+    pub fn ccvconsumer_state(&mut self, ccvconsumer_state_s: &str) -> Result<&mut Self> {
+        let state: Value = serde_json::from_str(ccvconsumer_state_s).stack()?;
+        self.set(&["app_state", "ccvconsumer"], state)
+    }
+
+    /// Onomy provider only, sets the DAO treasury to a single coin
+    pub fn dao_treasury_balance(&mut self, amount: &str, denom: &str) -> Result<&mut Self> {
+        self.set(
+            &["app_state", "dao", "treasury_balance"],
+            json!([{"denom": denom, "amount": amount}]),
+        )
+    }
+
+    /// Returns the path to a gov param, choosing between the v1beta1 layout
+    /// `v1beta1_path` and the v1 `params.{v1_key}` layout depending on which
+    /// exists
+    fn gov_path(&self, v1beta1_path: &[&str], v1_key: &str) -> Result<Vec<String>> {
+        let v1beta1: Vec<&str> = ["app_state", "gov"]
+            .iter()
+            .chain(v1beta1_path)
+            .copied()
+            .collect();
+        let v1 = ["app_state", "gov", "params", v1_key];
+        let path: &[&str] = if self.contains(&v1beta1) {
+            &v1beta1
+        } else if self.contains(&v1) {
+            &v1
+        } else {
+            return Err(Error::from(format!(
+                "neither gov layout {v1beta1:?} nor {v1:?} exists in genesis"
+            )))
+        };
+        Ok(path.iter().map(|s| s.to_string()).collect())
+    }
+}
+
+fn as_strs(path: &[String]) -> Vec<&str> {
+    path.iter().map(|s| s.as_str()).collect()
+}
+
+fn index<'a>(v: &'a Value, key: &str) -> Option<&'a Value> {
+    match v {
+        Value::Array(a) => a.get(key.parse::<usize>().ok()?),
+        _ => v.get(key),
+    }
+}
+
+fn index_mut<'a>(v: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    match v {
+        Value::Array(a) => a.get_mut(key.parse::<usize>().ok()?),
+        _ => v.get_mut(key),
+    }
+}
+
+fn missing(path: &[&str], i: usize) -> String {
+    format!(
+        "genesis path {} does not exist (failed at \"{}\")",
+        path.join("."),
+        path[i]
+    )
+}
+
+#[test]
+fn test_genesis_builder_v1beta1() {
+    let mut genesis =
+        GenesisBuilder::from_str(include_str!("../test_resources/genesis_v1beta1.json")).unwrap();
+    genesis.rename_denom("stake", "anom");
+    genesis
+        .chain_id("onomy")
+        .unwrap()
+        .gov_voting_period("800ms")
+        .unwrap()
+        .gov_max_deposit_period("800ms")
+        .unwrap()
+        .gov_min_deposit("500", "anom")
+        .unwrap()
+        .dao_treasury_balance("100", "anom")
+        .unwrap()
+        .add_account_with_balance("onomy1abc", "1337", "anom")
+        .unwrap()
+        .add_balance("onomy1abc", "1", "afootoken")
+        .unwrap();
+    let g = &genesis.genesis;
+    assert_eq!(g["chain_id"], "onomy");
+    assert_eq!(g["app_state"]["staking"]["params"]["bond_denom"], "anom");
+    assert_eq!(g["app_state"]["crisis"]["constant_fee"]["denom"], "anom");
+    assert_eq!(
+        g["app_state"]["gov"]["voting_params"]["voting_period"],
+        "800ms"
+    );
+    assert_eq!(
+        g["app_state"]["gov"]["deposit_params"]["min_deposit"],
+        json!([{"denom": "anom", "amount": "500"}])
+    );
+    assert_eq!(
+        g["app_state"]["auth"]["accounts"][0]["address"],
+        "onomy1abc"
+    );
+    assert_eq!(
+        g["app_state"]["bank"]["balances"][0]["coins"],
+        json!([{"denom": "afootoken", "amount": "1"}, {"denom": "anom", "amount": "1337"}])
+    );
+    // adding again should not duplicate
+    genesis.add_account("onomy1abc").unwrap();
+    assert_eq!(
+        genesis
+            .get(&["app_state", "auth", "accounts"])
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+    // the fixture has no `ccvconsumer` module
+    assert!(genesis.ccvconsumer_state("{}").is_err());
+}
+
+#[test]
+fn test_genesis_builder_v1() {
+    let mut genesis =
+        GenesisBuilder::from_str(include_str!("../test_resources/genesis_v1.json")).unwrap();
+    genesis
+        .gov_voting_period("800ms")
+        .unwrap()
+        .gov_max_deposit_period("800ms")
+        .unwrap()
+        .gov_min_deposit("500", "anative")
+        .unwrap()
+        .ccvconsumer_state(r#"{"params": {"enabled": true}}"#)
+        .unwrap();
+    let g = &genesis.genesis;
+    assert_eq!(g["app_state"]["gov"]["params"]["voting_period"], "800ms");
+    assert_eq!(
+        g["app_state"]["gov"]["params"]["max_deposit_period"],
+        "800ms"
+    );
+    assert_eq!(
+        g["app_state"]["gov"]["params"]["min_deposit"][0]["denom"],
+        "anative"
+    );
+    assert_eq!(
+        g["app_state"]["ccvconsumer"]["params"]["enabled"],
+        json!(true)
+    );
+    // not an onomy provider genesis
+    assert!(genesis.dao_treasury_balance("100", "anom").is_err());
+    assert!(genesis.min_global_self_delegation("1").is_err());
+}
@@ -0,0 +1,520 @@
+//! Governance module functions
+
+use std::{fmt, time::Duration};
+
+use bech32::ToBase32;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use super_orchestrator::{
+    stacked_errors::{Error, Result, StackableErr},
+    wait_for_ok, FileOptions,
+};
+use u64_array_bigints::U256;
+
+use crate::{
+    cosmovisor::CosmovisorCmd,
+    json_inner,
+    tx::{FeePolicy, TxResponse},
+};
+
+/// The options that can be used with `tx gov vote`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteOption {
+    Yes,
+    No,
+    Abstain,
+    NoWithVeto,
+}
+
+impl VoteOption {
+    /// The name that the CLI accepts
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VoteOption::Yes => "yes",
+            VoteOption::No => "no",
+            VoteOption::Abstain => "abstain",
+            VoteOption::NoWithVeto => "no_with_veto",
+        }
+    }
+}
+
+impl fmt::Display for VoteOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses a nonnegative decimal string into an integer with 18 decimal
+/// places, the precision of `sdk.Dec`
+fn dec18(s: &str) -> Option<u128> {
+    let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if fraction.len() > 18 {
+        return None
+    }
+    let integer: u128 = integer.parse().ok()?;
+    let fraction: u128 = format!("{fraction:0<18}").parse().ok()?;
+    integer.checked_mul(10u128.pow(18))?.checked_add(fraction)
+}
+
+/// Formats `options` into the `yes=0.6,no=0.4` form that `tx gov
+/// weighted-vote` takes. Like the chain, this requires the options to be
+/// distinct and the weights to be positive and to add up to exactly 1 (as
+/// 18 decimal place numbers).
+pub fn weighted_vote_options(options: &[(VoteOption, f64)]) -> Result<String> {
+    if options.is_empty() {
+        return Err(Error::from("weighted_vote_options: no options given"))
+    }
+    let mut s = String::new();
+    let mut total = 0u128;
+    for (i, (option, weight)) in options.iter().enumerate() {
+        if !(weight.is_finite() && (*weight > 0.0) && (*weight <= 1.0)) {
+            return Err(Error::from(format!(
+                "weighted_vote_options: weight {weight} of {option} is not in (0, 1]"
+            )))
+        }
+        if options[..i].iter().any(|(prev, _)| prev == option) {
+            return Err(Error::from(format!(
+                "weighted_vote_options: option {option} is given more than once"
+            )))
+        }
+        let weight_s = format!("{weight}");
+        let weight_dec = dec18(&weight_s).stack_err(|| {
+            format!("weighted_vote_options: weight {weight_s} has more than 18 decimal places")
+        })?;
+        total = total.checked_add(weight_dec).stack()?;
+        if i != 0 {
+            s.push(',');
+        }
+        s.push_str(&format!("{option}={weight_s}"));
+    }
+    if total != 10u128.pow(18) {
+        return Err(Error::from(format!(
+            "weighted_vote_options: the weights of {s} do not add up to 1"
+        )))
+    }
+    Ok(s)
+}
+
+/// Votes `option` on `proposal_id` from the `from_key` account
+pub async fn cosmovisor_gov_vote(
+    from_key: &str,
+    proposal_id: u64,
+    option: VoteOption,
+    fee: &FeePolicy,
+) -> Result<TxResponse> {
+    CosmovisorCmd::tx("gov vote")
+        .args([proposal_id.to_string().as_str(), option.as_str()])
+        .fee_policy(fee)
+        .from(from_key)
+        .run_tx()
+        .await
+        .stack_err(|| format!("cosmovisor_gov_vote({from_key}, {proposal_id}, {option})"))
+}
+
+/// Splits the vote of the `from_key` account on `proposal_id` across
+/// `options`, see [weighted_vote_options]
+pub async fn cosmovisor_gov_weighted_vote(
+    from_key: &str,
+    proposal_id: u64,
+    options: &[(VoteOption, f64)],
+    fee: &FeePolicy,
+) -> Result<TxResponse> {
+    let options_s = weighted_vote_options(options).stack()?;
+    CosmovisorCmd::tx("gov weighted-vote")
+        .args([proposal_id.to_string(), options_s.clone()])
+        .fee_policy(fee)
+        .from(from_key)
+        .run_tx()
+        .await
+        .stack_err(|| {
+            format!("cosmovisor_gov_weighted_vote({from_key}, {proposal_id}, {options_s})")
+        })
+}
+
+/// Deposits `deposit` (e.x. "2000anom") on `proposal_id` from the `from_key`
+/// account
+pub async fn cosmovisor_gov_deposit(
+    from_key: &str,
+    proposal_id: u64,
+    deposit: &str,
+    fee: &FeePolicy,
+) -> Result<TxResponse> {
+    CosmovisorCmd::tx("gov deposit")
+        .args([proposal_id.to_string().as_str(), deposit])
+        .fee_policy(fee)
+        .from(from_key)
+        .run_tx()
+        .await
+        .stack_err(|| format!("cosmovisor_gov_deposit({from_key}, {proposal_id}, {deposit})"))
+}
+
+/// Returns the ID of the proposal created by a `tx gov submit-proposal`
+/// transaction
+pub fn proposal_id_from_events(res: &TxResponse) -> Result<u64> {
+    let id = res
+        .attribute("submit_proposal", "proposal_id")
+        .stack_err(|| format!("could not find a \"proposal_id\" event attribute in {res:?}"))?;
+    id.trim_matches('"').parse::<u64>().stack()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProposalStatus {
+    Unspecified,
+    DepositPeriod,
+    VotingPeriod,
+    Passed,
+    Rejected,
+    Failed,
+}
+
+impl ProposalStatus {
+    /// Parses the "PROPOSAL_STATUS_*" form
+    pub fn from_str_name(s: &str) -> Result<Self> {
+        Ok(match s {
+            "PROPOSAL_STATUS_UNSPECIFIED" => ProposalStatus::Unspecified,
+            "PROPOSAL_STATUS_DEPOSIT_PERIOD" => ProposalStatus::DepositPeriod,
+            "PROPOSAL_STATUS_VOTING_PERIOD" => ProposalStatus::VotingPeriod,
+            "PROPOSAL_STATUS_PASSED" => ProposalStatus::Passed,
+            "PROPOSAL_STATUS_REJECTED" => ProposalStatus::Rejected,
+            "PROPOSAL_STATUS_FAILED" => ProposalStatus::Failed,
+            _ => return Err(Error::from(format!("unknown proposal status {s:?}"))),
+        })
+    }
+
+    /// If the proposal can no longer change status
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            ProposalStatus::Passed | ProposalStatus::Rejected | ProposalStatus::Failed
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TallyResult {
+    pub yes: U256,
+    pub abstain: U256,
+    pub no: U256,
+    pub no_with_veto: U256,
+}
+
+impl TallyResult {
+    /// Handles both the v1beta1 ("yes") and v1 ("yes_count") field names
+    pub fn from_value(tally: &Value) -> Result<Self> {
+        let get = |name: &str| -> Result<U256> {
+            let v = tally
+                .get(name)
+                .or_else(|| tally.get(format!("{name}_count")))
+                .stack_err(|| format!("tally {tally} is missing \"{name}\""))?;
+            U256::from_dec_or_hex_str(&json_inner(v)).stack()
+        };
+        Ok(TallyResult {
+            yes: get("yes").stack()?,
+            abstain: get("abstain").stack()?,
+            no: get("no").stack()?,
+            no_with_veto: get("no_with_veto").stack()?,
+        })
+    }
+}
+
+/// The parts of `query gov proposal` that tests commonly check, the full
+/// output is in `raw`
+#[derive(Debug, Clone)]
+pub struct Proposal {
+    pub id: u64,
+    pub status: ProposalStatus,
+    /// Note that this is all zeros until the voting period ends
+    pub final_tally_result: TallyResult,
+    pub submit_time: String,
+    pub voting_end_time: Option<String>,
+    pub raw: Value,
+}
+
+impl Proposal {
+    /// Parses the JSON output of `query gov proposal`, handling both the gov
+    /// v1beta1 and v1 layouts
+    pub fn from_value(raw: Value) -> Result<Self> {
+        // newer versions wrap the proposal
+        let p = raw.get("proposal").unwrap_or(&raw);
+        let id = p
+            .get("proposal_id")
+            .or_else(|| p.get("id"))
+            .stack_err(|| format!("proposal {p} has no ID"))?;
+        let id = json_inner(id).parse::<u64>().stack()?;
+        let status = ProposalStatus::from_str_name(
+            p.get("status")
+                .and_then(|s| s.as_str())
+                .stack_err(|| format!("proposal {p} has no status"))?,
+        )
+        .stack()?;
+        let final_tally_result = TallyResult::from_value(
+            p.get("final_tally_result")
+                .stack_err(|| format!("proposal {p} has no final_tally_result"))?,
+        )
+        .stack()?;
+        let submit_time = json_inner(
+            p.get("submit_time")
+                .stack_err(|| format!("proposal {p} has no submit_time"))?,
+        );
+        let voting_end_time = p
+            .get("voting_end_time")
+            .filter(|t| !t.is_null())
+            .map(json_inner);
+        Ok(Proposal {
+            id,
+            status,
+            final_tally_result,
+            submit_time,
+            voting_end_time,
+            raw,
+        })
+    }
+}
+
+pub async fn cosmovisor_get_proposal(proposal_id: u64) -> Result<Proposal> {
+    let raw = CosmovisorCmd::query("gov proposal")
+        .arg(proposal_id.to_string())
+        .output_json()
+        .run_json()
+        .await
+        .stack()?;
+    Proposal::from_value(raw).stack()
+}
+
+/// Waits until proposal `proposal_id` has `status`. Returns an error early if
+/// the proposal ends up in a different final status.
+pub async fn wait_for_proposal_status(
+    num_tries: u64,
+    delay: Duration,
+    proposal_id: u64,
+    status: ProposalStatus,
+) -> Result<Proposal> {
+    let proposal = wait_for_ok(num_tries, delay, || async {
+        let proposal = cosmovisor_get_proposal(proposal_id).await.stack()?;
+        if (proposal.status == status) || proposal.status.is_final() {
+            Ok(proposal)
+        } else {
+            Err(Error::from(format!(
+                "proposal {proposal_id} is {:?}",
+                proposal.status
+            )))
+        }
+    })
+    .await
+    .stack_err(|| format!("wait_for_proposal_status({proposal_id}, {status:?})"))?;
+    if proposal.status != status {
+        return Err(Error::from(format!(
+            "wait_for_proposal_status: proposal {proposal_id} ended up {:?} instead of \
+             {status:?}, {proposal:?}",
+            proposal.status
+        )))
+    }
+    Ok(proposal)
+}
+
+/// Returns the bech32 address of the module account named `module_name`
+/// (e.x. "gov" for the authority of gov v1 proposal messages)
+pub fn module_address(module_name: &str, prefix: &str) -> Result<String> {
+    // `authtypes.NewModuleAddress`, the first 20 bytes of the sha256 hash
+    let hash = Sha256::digest(module_name.as_bytes());
+    bech32::encode(prefix, (&hash[..20]).to_base32(), bech32::Variant::Bech32).stack()
+}
+
+/// Returns the bech32 address of the gov module account, which is the
+/// `authority` that most messages in gov v1 proposals need
+pub fn gov_module_address(prefix: &str) -> Result<String> {
+    module_address("gov", prefix).stack()
+}
+
+/// A builder for the gov v1 (Cosmos-SDK v0.46+) proposal JSON file that `tx gov
+/// submit-proposal [path]` takes. The proposal can contain any number of
+/// messages, with the exception that their signer must be the gov module
+/// account (see [gov_module_address]).
+///
+/// ```text
+/// let authority = gov_module_address("onomy")?;
+/// let proposal = GovProposal::new("upgrade v1.1.5", "upgrade to v1.1.5")
+///     .message(msg_software_upgrade(&authority, "v1.1.5", 100, ""))
+///     .deposit("2000000000000000000000anom");
+/// let proposal_id =
+///     cosmovisor_submit_gov_v1_proposal(daemon_home, "validator", &proposal, &fee).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GovProposal {
+    messages: Vec<Value>,
+    metadata: String,
+    deposit: String,
+    title: String,
+    summary: String,
+}
+
+impl GovProposal {
+    pub fn new(title: &str, summary: &str) -> Self {
+        Self {
+            title: title.to_owned(),
+            summary: summary.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Appends a message, which should be a JSON object with the "@type" of the
+    /// message and its fields
+    pub fn message(mut self, message: Value) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    /// Arbitrary metadata, usually an IPFS link or base64 JSON
+    pub fn metadata(mut self, metadata: &str) -> Self {
+        self.metadata = metadata.to_owned();
+        self
+    }
+
+    /// The initial deposit (e.x. "2000anom")
+    pub fn deposit(mut self, deposit: &str) -> Self {
+        self.deposit = deposit.to_owned();
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "messages": self.messages,
+            "metadata": self.metadata,
+            "deposit": self.deposit,
+            "title": self.title,
+            "summary": self.summary,
+        })
+    }
+}
+
+/// A `MsgSoftwareUpgrade` for [GovProposal::message]
+pub fn msg_software_upgrade(authority: &str, name: &str, height: u64, info: &str) -> Value {
+    json!({
+        "@type": "/cosmos.upgrade.v1beta1.MsgSoftwareUpgrade",
+        "authority": authority,
+        "plan": {
+            "name": name,
+            "height": height.to_string(),
+            "info": info,
+        }
+    })
+}
+
+/// A `MsgUpdateParams` of some module for [GovProposal::message]. `type_url`
+/// is the module specific type (e.x. "/cosmos.gov.v1.MsgUpdateParams"), and
+/// `params` needs to be the _complete_ set of params for the module.
+pub fn msg_update_params(type_url: &str, authority: &str, params: Value) -> Value {
+    json!({
+        "@type": type_url,
+        "authority": authority,
+        "params": params,
+    })
+}
+
+/// Writes `proposal` at `{daemon_home}/config/proposal.json` and submits it
+/// from the `from_key` account with `tx gov submit-proposal`. Returns the
+/// proposal ID.
+pub async fn cosmovisor_submit_gov_v1_proposal(
+    daemon_home: &str,
+    from_key: &str,
+    proposal: &GovProposal,
+    fee: &FeePolicy,
+) -> Result<u64> {
+    let proposal_s = proposal.to_json().to_string();
+    let proposal_file_path = format!("{daemon_home}/config/proposal.json");
+    FileOptions::write_str(&proposal_file_path, &proposal_s)
+        .await
+        .stack()?;
+    let res = CosmovisorCmd::tx("gov submit-proposal")
+        .arg(&proposal_file_path)
+        .fee_policy(fee)
+        .from(from_key)
+        .run_tx()
+        .await
+        .stack_err(|| {
+            format!(
+                "cosmovisor_submit_gov_v1_proposal(from_key: {from_key}, proposal: {proposal_s})"
+            )
+        })?;
+    proposal_id_from_events(&res).stack()
+}
+
+#[test]
+fn test_weighted_vote_options() {
+    assert_eq!(
+        weighted_vote_options(&[(VoteOption::Yes, 0.6), (VoteOption::NoWithVeto, 0.4)]).unwrap(),
+        "yes=0.6,no_with_veto=0.4"
+    );
+    assert_eq!(
+        weighted_vote_options(&[(VoteOption::Abstain, 1.0)]).unwrap(),
+        "abstain=1"
+    );
+    assert_eq!(
+        weighted_vote_options(&[
+            (VoteOption::Yes, 0.1),
+            (VoteOption::No, 0.2),
+            (VoteOption::Abstain, 0.7)
+        ])
+        .unwrap(),
+        "yes=0.1,no=0.2,abstain=0.7"
+    );
+    assert!(weighted_vote_options(&[]).is_err());
+    assert!(weighted_vote_options(&[(VoteOption::No, 0.0)]).is_err());
+    assert!(weighted_vote_options(&[(VoteOption::Yes, 0.5), (VoteOption::No, -0.5)]).is_err());
+    // does not add up to 1
+    assert!(weighted_vote_options(&[(VoteOption::Yes, 0.6), (VoteOption::No, 0.3)]).is_err());
+    assert!(weighted_vote_options(&[(VoteOption::Yes, 0.6), (VoteOption::No, 0.6)]).is_err());
+    assert!(weighted_vote_options(&[(VoteOption::Yes, 0.5), (VoteOption::Yes, 0.5)]).is_err());
+    assert!(weighted_vote_options(&[(VoteOption::Yes, 1e-19), (VoteOption::No, 1.0)]).is_err());
+}
+
+#[test]
+fn test_proposal_parsing() {
+    use serde_json::json;
+
+    let v1beta1 = json!({
+        "proposal_id": "3",
+        "status": "PROPOSAL_STATUS_PASSED",
+        "final_tally_result": {
+            "yes": "1000", "abstain": "0", "no": "7", "no_with_veto": "0"
+        },
+        "submit_time": "2023-06-01T00:00:00Z",
+        "voting_end_time": "2023-06-01T00:00:08Z"
+    });
+    let p = Proposal::from_value(v1beta1).unwrap();
+    assert_eq!(p.id, 3);
+    assert_eq!(p.status, ProposalStatus::Passed);
+    assert_eq!(p.final_tally_result.no, U256::from_u64(7));
+    assert_eq!(p.voting_end_time.as_deref(), Some("2023-06-01T00:00:08Z"));
+
+    let v1 = json!({"proposal": {
+        "id": "4",
+        "status": "PROPOSAL_STATUS_VOTING_PERIOD",
+        "final_tally_result": {
+            "yes_count": "0", "abstain_count": "0", "no_count": "0", "no_with_veto_count": "0"
+        },
+        "submit_time": "2023-06-01T00:00:00Z",
+        "voting_end_time": null
+    }});
+    let p = Proposal::from_value(v1).unwrap();
+    assert_eq!(p.id, 4);
+    assert!(!p.status.is_final());
+    assert_eq!(p.voting_end_time, None);
+}
+
+#[test]
+fn test_gov_proposal() {
+    assert_eq!(
+        gov_module_address("cosmos").unwrap(),
+        "cosmos10d07y265gmmuvt4z0w9aw880jnsr700j6zn9kn"
+    );
+    let authority = gov_module_address("onomy").unwrap();
+    let proposal = GovProposal::new("upgrade", "upgrade to v1.1.5")
+        .message(msg_software_upgrade(&authority, "v1.1.5", 100, ""))
+        .deposit("2000anom")
+        .to_json();
+    assert_eq!(proposal["messages"][0]["authority"], authority.as_str());
+    assert_eq!(proposal["messages"][0]["plan"]["height"], "100");
+    assert_eq!(proposal["deposit"], "2000anom");
+    assert_eq!(proposal["metadata"], "");
+}
@@ -2,11 +2,14 @@ use std::time::Duration;
 
 use serde_json::Value;
 use super_orchestrator::{
-    stacked_errors::{Result, StackableErr},
-    stacked_get, stacked_get_mut, Command, CommandRunner, FileOptions,
+    stacked_errors::{Error, Result, StackableErr},
+    stacked_get, wait_for_ok, Command, CommandRunner, FileOptions,
 };
 
-use crate::json_inner;
+use crate::{
+    executor::{argv_with_prefix, execute},
+    json_inner, STD_DELAY, STD_TRIES,
+};
 pub use crate::{hermes_config::*, ibc::IbcPair};
 
 /// A wrapper around `super_orchestrator::sh` that prefixes "hermes --json". The
@@ -16,29 +19,9 @@ where
     I: IntoIterator<Item = S>,
     S: AsRef<str>,
 {
-    let mut command = None;
-    for (i, part) in program_with_args.into_iter().enumerate() {
-        if i == 0 {
-            let s = format!("hermes --json {}", part.as_ref());
-            command = Some(Command::new(s));
-        } else {
-            command = Some(command.unwrap().arg(part.as_ref()));
-        }
-    }
-    let comres = command
-        .stack_err(|| "`sh_hermes` called with an empty iterator")?
-        .debug(true)
-        .run_to_completion()
-        .await?;
-    comres.assert_success()?;
-    let stdout = comres
-        .stdout_as_utf8()
-        .map(|s| s.to_owned())
-        .stack_err_locationless(|| "`Command` output was not UTF-8")?;
-    let res = stdout.lines().last().stack()?;
-    let res: Value = serde_json::from_str(res).stack()?;
-    let res = res.get("result").stack()?.to_owned();
-    Ok(res)
+    let argv = argv_with_prefix("hermes --json", program_with_args)
+        .stack_err(|| "`sh_hermes` called with an empty iterator")?;
+    hermes_result(&argv, true).await
 }
 
 pub async fn sh_hermes_no_debug<I, S>(program_with_args: I) -> Result<Value>
@@ -46,25 +29,15 @@ where
     I: IntoIterator<Item = S>,
     S: AsRef<str>,
 {
-    let mut command = None;
-    for (i, part) in program_with_args.into_iter().enumerate() {
-        if i == 0 {
-            let s = format!("hermes --json {}", part.as_ref());
-            command = Some(Command::new(s));
-        } else {
-            command = Some(command.unwrap().arg(part.as_ref()));
-        }
-    }
-    let comres = command
-        .stack_err(|| "`sh_hermes_no_debug` called with an empty iterator")?
-        .run_to_completion()
-        .await?;
-    comres.assert_success()?;
-    let stdout = comres
-        .stdout_as_utf8()
-        .map(|s| s.to_owned())
-        .stack_err_locationless(|| "`Command` output was not UTF-8")?;
-    let res = stdout.lines().last().stack()?;
+    let argv = argv_with_prefix("hermes --json", program_with_args)
+        .stack_err(|| "`sh_hermes_no_debug` called with an empty iterator")?;
+    hermes_result(&argv, false).await
+}
+
+async fn hermes_result(argv: &[String], debug: bool) -> Result<Value> {
+    let output = execute(argv, debug).await.stack()?;
+    output.assert_success(argv)?;
+    let res = output.stdout.lines().last().stack()?;
     let res: Value = serde_json::from_str(res).stack()?;
     let res = res.get("result").stack()?.to_owned();
     Ok(res)
@@ -110,9 +83,9 @@ pub async fn create_connection_pair(
         "create connection --a-chain",
         a_chain,
         "--a-client",
-        &a_client,
+        a_client,
         "--b-client",
-        &b_client,
+        b_client,
     ])
     .await
     .stack()?;
@@ -134,6 +107,19 @@ pub async fn create_channel_pair(
     a_port: &str,
     b_port: &str,
     ordered: bool,
+) -> Result<(String, String)> {
+    create_channel_pair_with_version(a_chain, a_connection, a_port, b_port, ordered, None).await
+}
+
+/// [create_channel_pair] with a custom channel version, e.x. the metadata of an
+/// ICA channel. If `version` is `None`, the version is chosen by the chains.
+pub async fn create_channel_pair_with_version(
+    a_chain: &str,
+    a_connection: &str,
+    a_port: &str,
+    b_port: &str,
+    ordered: bool,
+    version: Option<&str>,
 ) -> Result<(String, String)> {
     let mut args = vec![
         "create channel --a-chain",
@@ -148,6 +134,9 @@ pub async fn create_channel_pair(
     if ordered {
         args.extend(&["--order", "ordered"])
     }
+    if let Some(version) = version {
+        args.extend(&["--channel-version", version])
+    }
     let res = &sh_hermes(args).await.stack()?;
     Ok((
         json_inner(stacked_get!(res["a_side"]["channel_id"])),
@@ -155,49 +144,386 @@ pub async fn create_channel_pair(
     ))
 }
 
-impl IbcPair {
-    pub async fn hermes_check_acks(&self) -> Result<()> {
-        // check all channels on both sides
-        sh_hermes_no_debug([
-            "query packet acks --chain",
-            &self.b.chain_id,
-            "--port",
-            "transfer",
-            "--channel",
-            &self.a.transfer_channel,
-        ])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    Uninitialized,
+    Init,
+    TryOpen,
+    Open,
+    Closed,
+}
+
+impl ChannelState {
+    /// Parses the state as hermes outputs it, e.x. "TryOpen" or "STATE_TRYOPEN"
+    pub fn from_hermes(s: &str) -> Result<Self> {
+        let lowercase = s.to_lowercase().replace('_', "");
+        let state = lowercase.strip_prefix("state").unwrap_or(&lowercase);
+        Ok(match state {
+            "uninitialized" | "uninitializedunspecified" => Self::Uninitialized,
+            "init" => Self::Init,
+            "tryopen" => Self::TryOpen,
+            "open" => Self::Open,
+            "closed" => Self::Closed,
+            _ => {
+                return Err(Error::from(format!(
+                    "ChannelState::from_hermes unrecognized state \"{s}\""
+                )))
+            }
+        })
+    }
+}
+
+/// One end of a channel, as reported by the chain that hosts it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelEnd {
+    pub port_id: String,
+    pub channel_id: String,
+    pub state: ChannelState,
+    pub connection_hops: Vec<String>,
+    pub counterparty_port_id: String,
+    /// `None` until the counterparty end has been created
+    pub counterparty_channel_id: Option<String>,
+}
+
+impl ChannelEnd {
+    /// Parses the "channel_end" object of hermes output
+    fn from_value(port_id: &str, channel_id: &str, channel_end: &Value) -> Result<Self> {
+        let state = ChannelState::from_hermes(
+            stacked_get!(channel_end["state"])
+                .as_str()
+                .stack_err(|| "ChannelEnd state is not a string")?,
+        )
+        .stack()?;
+        let mut connection_hops = vec![];
+        for hop in stacked_get!(channel_end["connection_hops"])
+            .as_array()
+            .stack()?
+        {
+            connection_hops.push(json_inner(hop));
+        }
+        let remote = stacked_get!(channel_end["remote"]);
+        let counterparty_channel_id = match stacked_get!(remote["channel_id"]) {
+            Value::Null => None,
+            Value::String(channel_id) if channel_id.is_empty() => None,
+            channel_id => Some(json_inner(channel_id)),
+        };
+        Ok(Self {
+            port_id: port_id.to_owned(),
+            channel_id: channel_id.to_owned(),
+            state,
+            connection_hops,
+            counterparty_port_id: json_inner(stacked_get!(remote["port_id"])),
+            counterparty_channel_id,
+        })
+    }
+
+    /// If the first connection hop of the channel is `connection`
+    pub fn is_on_connection(&self, connection: &str) -> bool {
+        self.connection_hops.first().map(|s| s.as_str()) == Some(connection)
+    }
+}
+
+/// Returns all the channel ends hosted on `chain`
+pub async fn hermes_query_channels(chain: &str) -> Result<Vec<ChannelEnd>> {
+    let res = sh_hermes_no_debug(["query channels --verbose --chain", chain])
+        .await
+        .stack()?;
+    let mut channels = vec![];
+    for channel in res.as_array().stack()? {
+        channels.push(
+            ChannelEnd::from_value(
+                &json_inner(stacked_get!(channel["port_id"])),
+                &json_inner(stacked_get!(channel["channel_id"])),
+                stacked_get!(channel["channel_end"]),
+            )
+            .stack()?,
+        );
+    }
+    Ok(channels)
+}
+
+pub async fn hermes_query_channel_end(
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<ChannelEnd> {
+    let res = sh_hermes_no_debug([
+        "query channel end --chain",
+        chain,
+        "--port",
+        port_id,
+        "--channel",
+        channel_id,
+    ])
+    .await
+    .stack()?;
+    ChannelEnd::from_value(port_id, channel_id, &res)
+        .stack_err(|| format!("hermes_query_channel_end({chain}, {port_id}, {channel_id})"))
+}
+
+/// Returns the single channel end on `chain` over `connection` with `port_id`
+/// that is in `state`, returning an error if there is not exactly one
+pub async fn hermes_find_channel(
+    chain: &str,
+    connection: &str,
+    port_id: &str,
+    state: ChannelState,
+) -> Result<ChannelEnd> {
+    let mut found: Vec<ChannelEnd> = hermes_query_channels(chain)
+        .await
+        .stack()?
+        .into_iter()
+        .filter(|c| c.port_id == port_id && c.state == state && c.is_on_connection(connection))
+        .collect();
+    if found.len() != 1 {
+        return Err(Error::from(format!(
+            "hermes_find_channel expected one {port_id} channel in state {state:?} on \
+             {connection} of {chain}, found {found:?}"
+        )))
+    }
+    Ok(found.remove(0))
+}
+
+/// Returns an error if the channel end is not open
+pub async fn hermes_ensure_channel_open(
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<()> {
+    let end = hermes_query_channel_end(chain, port_id, channel_id)
+        .await
+        .stack()?;
+    if end.state != ChannelState::Open {
+        return Err(Error::from(format!(
+            "channel {port_id}/{channel_id} on {chain} is in state {:?} instead of Open",
+            end.state
+        )))
+    }
+    Ok(())
+}
+
+/// Parses a list of packet sequences, which hermes outputs as numbers
+fn parse_sequences(seqs: &Value) -> Result<Vec<u64>> {
+    let mut res = vec![];
+    for seq in seqs
+        .as_array()
+        .stack_err(|| "packet sequences are not an array")?
+    {
+        let parsed = match seq {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        };
+        res.push(parsed.stack_err(|| format!("could not parse packet sequence {seq}"))?);
+    }
+    Ok(res)
+}
+
+async fn hermes_query_packets(
+    subcommand: &str,
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<Value> {
+    sh_hermes_no_debug([
+        subcommand,
+        "--chain",
+        chain,
+        "--port",
+        port_id,
+        "--channel",
+        channel_id,
+    ])
+    .await
+    .stack_err(|| format!("hermes {subcommand} on {chain} {port_id}/{channel_id}"))
+}
+
+/// Returns the sequences of packets sent from the channel end on `chain` whose
+/// commitments have not been cleared by an acknowledgement or a timeout yet
+pub async fn hermes_query_packet_commitments(
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<Vec<u64>> {
+    let res = hermes_query_packets("query packet commitments", chain, port_id, channel_id)
+        .await
+        .stack()?;
+    parse_sequences(stacked_get!(res["seqs"])).stack()
+}
+
+/// Returns the sequences of packets received by the channel end on `chain` that
+/// it has written acknowledgements for
+pub async fn hermes_query_packet_acks(
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<Vec<u64>> {
+    let res = hermes_query_packets("query packet acks", chain, port_id, channel_id)
         .await
         .stack()?;
-        sh_hermes_no_debug([
-            "query packet acks --chain",
-            &self.a.chain_id,
-            "--port",
-            "transfer",
-            "--channel",
-            &self.b.transfer_channel,
-        ])
+    parse_sequences(stacked_get!(res["seqs"])).stack()
+}
+
+/// Returns the sequences of packets sent by the counterparty that have not
+/// been received by the channel end on `chain`
+pub async fn hermes_query_unreceived_packets(
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<Vec<u64>> {
+    let res = hermes_query_packets(
+        "query packet unreceived-packets",
+        chain,
+        port_id,
+        channel_id,
+    )
+    .await
+    .stack()?;
+    parse_sequences(&res).stack()
+}
+
+/// Returns the sequences of packets sent from the channel end on `chain` whose
+/// acknowledgements have not been relayed back to it
+pub async fn hermes_query_unreceived_acks(
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<Vec<u64>> {
+    let res = hermes_query_packets("query packet unreceived-acks", chain, port_id, channel_id)
         .await
         .stack()?;
-        sh_hermes_no_debug([
-            "query packet acks --chain",
-            &self.b.chain_id,
-            "--port",
-            "provider",
-            "--channel",
-            &self.a.ics_channel,
-        ])
+    parse_sequences(&res).stack()
+}
+
+/// The packets sent in one direction of a channel that have not finished their
+/// lifecycle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingPackets {
+    /// Sent but not yet received or timed out on the counterparty
+    pub unreceived_packets: Vec<u64>,
+    /// Received by the counterparty, but the acknowledgement has not been
+    /// relayed back
+    pub unreceived_acks: Vec<u64>,
+}
+
+impl PendingPackets {
+    fn from_value(pending: &Value) -> Result<Self> {
+        Ok(Self {
+            unreceived_packets: parse_sequences(stacked_get!(pending["unreceived_packets"]))
+                .stack()?,
+            unreceived_acks: parse_sequences(stacked_get!(pending["unreceived_acks"])).stack()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unreceived_packets.is_empty() && self.unreceived_acks.is_empty()
+    }
+}
+
+/// The pending packets of both directions of a channel
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelPending {
+    /// Packets sent from the queried channel end
+    pub src: PendingPackets,
+    /// Packets sent from the counterparty channel end
+    pub dst: PendingPackets,
+}
+
+impl ChannelPending {
+    /// If every packet in both directions has been received and acknowledged
+    /// (or timed out)
+    pub fn is_drained(&self) -> bool {
+        self.src.is_empty() && self.dst.is_empty()
+    }
+}
+
+/// Queries the pending packets in both directions of the channel with the end
+/// `port_id/channel_id` on `chain`
+pub async fn hermes_query_pending_packets(
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<ChannelPending> {
+    let res = hermes_query_packets("query packet pending", chain, port_id, channel_id)
         .await
         .stack()?;
-        sh_hermes_no_debug([
-            "query packet acks --chain",
-            &self.a.chain_id,
-            "--port",
-            "consumer",
-            "--channel",
-            &self.b.ics_channel,
-        ])
+    Ok(ChannelPending {
+        src: PendingPackets::from_value(stacked_get!(res["src"])).stack()?,
+        dst: PendingPackets::from_value(stacked_get!(res["dst"])).stack()?,
+    })
+}
+
+/// Waits until no packets are pending in either direction of the channel. Note
+/// that this requires a running relayer.
+pub async fn hermes_wait_for_drained(chain: &str, port_id: &str, channel_id: &str) -> Result<()> {
+    wait_for_ok(STD_TRIES, STD_DELAY, || async {
+        let pending = hermes_query_pending_packets(chain, port_id, channel_id)
+            .await
+            .stack()?;
+        if pending.is_drained() {
+            Ok(())
+        } else {
+            Err(Error::from(format!(
+                "channel {port_id}/{channel_id} on {chain} has pending packets {pending:?}"
+            )))
+        }
+    })
+    .await
+    .stack_err(|| format!("hermes_wait_for_drained({chain}, {port_id}, {channel_id})"))
+}
+
+/// Returns an error unless packet `sequence` sent from the channel end on
+/// `src_chain` timed out, meaning that its commitment has been cleared while
+/// the counterparty end on `dst_chain` never acknowledged it. The relayer must
+/// have relayed the timeout first, see [hermes_wait_for_drained].
+pub async fn hermes_assert_packet_timed_out(
+    src_chain: &str,
+    port_id: &str,
+    channel_id: &str,
+    dst_chain: &str,
+    sequence: u64,
+) -> Result<()> {
+    let end = hermes_query_channel_end(src_chain, port_id, channel_id)
+        .await
+        .stack()?;
+    let dst_channel = end
+        .counterparty_channel_id
+        .as_deref()
+        .stack_err(|| format!("channel {port_id}/{channel_id} has no counterparty"))?;
+    let commitments = hermes_query_packet_commitments(src_chain, port_id, channel_id)
+        .await
+        .stack()?;
+    if commitments.contains(&sequence) {
+        return Err(Error::from(format!(
+            "packet {sequence} sent from {port_id}/{channel_id} on {src_chain} still has a \
+             commitment, it has neither timed out nor been acknowledged"
+        )))
+    }
+    let acks = hermes_query_packet_acks(dst_chain, &end.counterparty_port_id, dst_channel)
         .await
         .stack()?;
+    if acks.contains(&sequence) {
+        return Err(Error::from(format!(
+            "packet {sequence} sent from {port_id}/{channel_id} on {src_chain} was received and \
+             acknowledged on {dst_chain} instead of timing out"
+        )))
+    }
+    Ok(())
+}
+
+impl IbcPair {
+    /// Waits until the transfer channel and (if this is an ICS pair) the
+    /// consumer-provider channel have no pending packets in either direction.
+    /// Note that this requires a running relayer.
+    pub async fn hermes_check_acks(&self) -> Result<()> {
+        hermes_wait_for_drained(&self.a.chain_id, "transfer", &self.a.transfer_channel)
+            .await
+            .stack()?;
+        if let Some(a_ics_channel) = &self.a.ics_channel {
+            hermes_wait_for_drained(&self.a.chain_id, "consumer", a_ics_channel)
+                .await
+                .stack()?;
+        }
         Ok(())
     }
 }
@@ -225,31 +551,172 @@ pub async fn hermes_start(log_file: &str) -> Result<HermesRunner> {
     })
 }
 
+/// Reads the config at "{hermes_home}/config.toml", applies `f` to it, and
+/// writes it back. Hermes needs to be restarted for changes to take effect.
+pub async fn edit_hermes_config<F>(hermes_home: &str, f: F) -> Result<()>
+where
+    F: FnOnce(&mut HermesConfig) -> Result<()>,
+{
+    let config_path = format!("{hermes_home}/config.toml");
+    let mut config = HermesConfig::read(&config_path).await.stack()?;
+    f(&mut config).stack()?;
+    config.write(&config_path).await.stack()
+}
+
 /// Note: uses "price = 1.0"
 pub async fn hermes_set_gas_price_denom(
     hermes_home: &str,
     chain_id: &str,
     gas_price_denom: &str,
 ) -> Result<()> {
-    // can't get simpler than this without importing a lot of stuff
-    let outer_table: toml::Value = toml::from_str(&format!(
-        "gas-price = {{ price = 1.0, denom = '{gas_price_denom}' }}"
-    ))
-    .unwrap();
-    let inner_table = stacked_get!(outer_table["gas-price"]).clone();
+    edit_hermes_config(hermes_home, |config| {
+        config.chain_config_mut(chain_id).stack()?.gas_price = GasPrice {
+            price: 1.0,
+            denom: gas_price_denom.to_owned(),
+        };
+        Ok(())
+    })
+    .await
+    .stack()
+}
 
-    let config_path = format!("{hermes_home}/config.toml");
-    let config_s = FileOptions::read_to_string(&config_path).await.stack()?;
-    let mut config: toml::Value = toml::from_str(&config_s).stack()?;
-    for chain in stacked_get_mut!(config["chains"]).as_array_mut().stack()? {
-        if stacked_get!(chain["id"]).as_str().stack()? == chain_id {
-            *stacked_get_mut!(chain["gas_price"]) = inner_table;
-            break
-        }
+#[tokio::test]
+async fn test_hermes_output_parsing() {
+    use std::sync::Arc;
+
+    use crate::executor::{with_command_executor, CommandOutput, ReplayExecutor};
+
+    let replay = Arc::new(ReplayExecutor::new());
+    for (host, reference, client) in [
+        ("onomy", "consumer", "07-tendermint-1"),
+        ("consumer", "onomy", "07-tendermint-0"),
+    ] {
+        replay.respond(
+            &format!(
+                "hermes --json create client --host-chain {host} --reference-chain {reference}"
+            ),
+            CommandOutput::success(&format!(
+                "{{\"level\":\"INFO\",\"fields\":{{\"message\":\"running \
+                 Hermes\"}}}}\n{{\"result\":{{\"CreateClient\":{{\"client_id\":\"{client}\",\"\
+                 client_type\":\"Tendermint\"}}}},\"status\":\"success\"}}\n"
+            )),
+        );
     }
-    let config_s = toml::to_string_pretty(&config).stack()?;
-    FileOptions::write_str(&config_path, &config_s)
+    let clients = with_command_executor(replay, create_client_pair("onomy", "consumer"))
         .await
-        .stack()?;
-    Ok(())
+        .unwrap();
+    assert_eq!(
+        clients,
+        ("07-tendermint-1".to_owned(), "07-tendermint-0".to_owned())
+    );
+}
+
+#[tokio::test]
+async fn test_hermes_find_channel() {
+    use serde_json::json;
+
+    use crate::executor::with_hermes_replay;
+
+    let channels = json!([
+        {
+            "channel_end": {
+                "connection_hops": ["connection-0"],
+                "ordering": "Ordered",
+                "remote": {"channel_id": "channel-0", "port_id": "provider"},
+                "state": "Open",
+                "version": "1"
+            },
+            "channel_id": "channel-0",
+            "port_id": "consumer"
+        },
+        {
+            "channel_end": {
+                "connection_hops": ["connection-1"],
+                "ordering": "Unordered",
+                "remote": {"channel_id": null, "port_id": "transfer"},
+                "state": "Init",
+                "version": "ics20-1"
+            },
+            "channel_id": "channel-3",
+            "port_id": "transfer"
+        }
+    ]);
+    let responses = [(
+        "hermes --json query channels --verbose --chain consumer",
+        channels,
+    )];
+    let (init, open) = with_hermes_replay(responses, async {
+        (
+            hermes_find_channel("consumer", "connection-1", "transfer", ChannelState::Init).await,
+            hermes_find_channel("consumer", "connection-1", "transfer", ChannelState::Open).await,
+        )
+    })
+    .await;
+    let init = init.unwrap();
+    assert_eq!(init.channel_id, "channel-3");
+    assert_eq!(init.counterparty_port_id, "transfer");
+    assert_eq!(init.counterparty_channel_id, None);
+    assert!(open.is_err());
+    assert_eq!(
+        ChannelState::from_hermes("STATE_TRYOPEN").unwrap(),
+        ChannelState::TryOpen
+    );
+}
+
+#[tokio::test]
+async fn test_hermes_packet_queries() {
+    use serde_json::json;
+
+    use crate::executor::with_hermes_replay;
+
+    let pending =
+        "hermes --json query packet pending --chain onomy --port transfer --channel channel-1";
+    let pending_acks = |acks: &[u64]| {
+        json!({
+            "dst": {"unreceived_acks": [], "unreceived_packets": []},
+            "src": {"unreceived_acks": acks, "unreceived_packets": []}
+        })
+    };
+    let height =
+        |revision_height: u64| json!({"revision_height": revision_height, "revision_number": 0});
+    let responses = [
+        (pending, pending_acks(&[3])),
+        (pending, pending_acks(&[])),
+        (
+            "hermes --json query channel end --chain onomy --port transfer --channel channel-1",
+            json!({
+                "connection_hops": ["connection-1"],
+                "ordering": "Unordered",
+                "remote": {"channel_id": "channel-0", "port_id": "transfer"},
+                "state": "Open",
+                "version": "ics20-1"
+            }),
+        ),
+        (
+            "hermes --json query packet commitments --chain onomy --port transfer --channel \
+             channel-1",
+            json!({"height": height(50), "seqs": [2]}),
+        ),
+        (
+            "hermes --json query packet acks --chain market --port transfer --channel channel-0",
+            json!({"height": height(48), "seqs": [1]}),
+        ),
+    ];
+    let (drained, timed_out, pending, acked) = with_hermes_replay(responses, async {
+        (
+            hermes_wait_for_drained("onomy", "transfer", "channel-1").await,
+            hermes_assert_packet_timed_out("onomy", "transfer", "channel-1", "market", 3).await,
+            hermes_assert_packet_timed_out("onomy", "transfer", "channel-1", "market", 2).await,
+            hermes_assert_packet_timed_out("onomy", "transfer", "channel-1", "market", 1).await,
+        )
+    })
+    .await;
+    drained.unwrap();
+    timed_out.unwrap();
+    assert!(pending.is_err());
+    assert!(acked.is_err());
+    assert_eq!(
+        parse_sequences(&serde_json::json!([1, "2"])).unwrap(),
+        vec![1, 2]
+    );
 }
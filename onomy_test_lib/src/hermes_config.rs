@@ -1,312 +1,361 @@
-use super_orchestrator::{stacked_errors::Result, FileOptions};
+//! A typed model of the Hermes `config.toml`
+//!
+//! The structs mirror the sections of the config that our tests use, see
+//! https://hermes.informal.systems/documentation/configuration/description.html
+//! for what each field does. Fields that the model does not know about are
+//! dropped when a config is parsed and written back.
+
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+use super_orchestrator::{
+    stacked_errors::{Error, Result, StackableErr},
+    FileOptions,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HermesConfig {
+    pub global: GlobalConfig,
+    pub mode: ModeConfig,
+    pub rest: RestConfig,
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub chains: Vec<ChainConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlobalConfig {
+    pub log_level: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModeConfig {
+    pub clients: ClientsMode,
+    pub connections: ConnectionsMode,
+    pub channels: ChannelsMode,
+    pub packets: PacketsMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientsMode {
+    pub enabled: bool,
+    /// Periodically refresh clients that underlie an open channel
+    pub refresh: bool,
+    pub misbehaviour: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionsMode {
+    /// Enables the workers that complete connection handshakes
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelsMode {
+    /// Enables the workers that complete channel handshakes
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketsMode {
+    pub enabled: bool,
+    /// The interval in blocks at which pending packets are cleared, 0 disables
+    /// periodic clearing
+    pub clear_interval: u64,
+    pub clear_on_start: bool,
+    pub tx_confirmation: bool,
+    pub auto_register_counterparty_payee: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+/// A `[[chains]]` entry of the config. Durations are kept in the Hermes
+/// format, e.x. "10s" or "14days".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainConfig {
+    pub id: String,
+    #[serde(default)]
+    pub ccv_consumer_chain: bool,
+    pub rpc_addr: String,
+    pub grpc_addr: String,
+    pub event_source: EventSource,
+    pub rpc_timeout: String,
+    pub account_prefix: String,
+    pub key_name: String,
+    pub store_prefix: String,
+    pub default_gas: u64,
+    pub max_gas: u64,
+    pub gas_price: GasPrice,
+    pub gas_multiplier: f64,
+    pub max_msg_num: u64,
+    pub max_tx_size: u64,
+    pub clock_drift: String,
+    pub max_block_time: String,
+    pub trusting_period: String,
+    pub trust_threshold: TrustThreshold,
+    pub address_type: AddressType,
+    /// Prepended to the memo of every transaction that Hermes submits
+    #[serde(default)]
+    pub memo_prefix: String,
+    /// If set, transaction fees are paid from this fee granter's account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_granter: Option<String>,
+    /// If `None`, all channels are relayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packet_filter: Option<PacketFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventSource {
+    pub mode: String,
+    pub url: String,
+    pub batch_delay: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GasPrice {
+    pub price: f64,
+    pub denom: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustThreshold {
+    pub numerator: String,
+    pub denominator: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressType {
+    pub derivation: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PacketFilterPolicy {
+    /// Relay only on the listed channels
+    Allow,
+    /// Relay on every channel except the listed ones
+    Deny,
+}
+
+/// The `packet_filter` of a chain. Port and channel ids may use wildcards like
+/// "ica*" or "*".
+///
+/// ```text
+/// // a relayer that only relays the ICS channels
+/// PacketFilter::allow([("consumer", "*"), ("provider", "*")])
+/// // a relayer that only relays transfers with a fee of at least 20 anom
+/// PacketFilter::allow([("transfer", "*")]).min_recv_fee("*", 20, Some("anom"))
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketFilter {
+    /// If `None`, all channels are allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PacketFilterPolicy>,
+    /// (port id, channel id) pairs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub list: Vec<(String, String)>,
+    /// Only packets with at least one of the `recv` fees of the entry for their
+    /// channel id are relayed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub min_fees: BTreeMap<String, MinFees>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinFees {
+    pub recv: Vec<FeeAmount>,
+}
 
-#[rustfmt::skip]
-const HEADER: &str = r##"# The global section has parameters that apply globally to the relayer operation.
-[global]
-log_level = 'info'
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeAmount {
+    pub amount: u64,
+    /// If `None`, any denom is accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denom: Option<String>,
+}
 
-# Specify the mode to be used by the relayer. [Required]
-[mode]
+impl PacketFilter {
+    fn with_policy<I, P, C>(policy: PacketFilterPolicy, channels: I) -> Self
+    where
+        I: IntoIterator<Item = (P, C)>,
+        P: AsRef<str>,
+        C: AsRef<str>,
+    {
+        Self {
+            policy: Some(policy),
+            list: channels
+                .into_iter()
+                .map(|(port, channel)| (port.as_ref().to_owned(), channel.as_ref().to_owned()))
+                .collect(),
+            min_fees: BTreeMap::new(),
+        }
+    }
 
-# Specify the client mode.
-[mode.clients]
+    /// Relays only on the (port id, channel id) pairs of `channels`
+    pub fn allow<I, P, C>(channels: I) -> Self
+    where
+        I: IntoIterator<Item = (P, C)>,
+        P: AsRef<str>,
+        C: AsRef<str>,
+    {
+        Self::with_policy(PacketFilterPolicy::Allow, channels)
+    }
 
-# Whether or not to enable the client workers. [Required]
-enabled = true
+    /// Relays on every channel except the (port id, channel id) pairs of
+    /// `channels`
+    pub fn deny<I, P, C>(channels: I) -> Self
+    where
+        I: IntoIterator<Item = (P, C)>,
+        P: AsRef<str>,
+        C: AsRef<str>,
+    {
+        Self::with_policy(PacketFilterPolicy::Deny, channels)
+    }
 
-# Whether or not to enable periodic refresh of clients. [Default: true]
-# This feature only applies to clients that underlie an open channel.
-# For Tendermint clients, the frequency at which Hermes refreshes them is 2/3 of their
-# trusting period (e.g., refresh every ~9 days if the trusting period is 14 days).
-# Note: Even if this is disabled, clients will be refreshed automatically if
-#      there is activity on a connection or channel they are involved with.
-refresh = true
+    /// Adds `amount` of `denom` (or of any denom if `None`) as an accepted
+    /// minimum `recv` fee for packets on `channel_id`
+    pub fn min_recv_fee(mut self, channel_id: &str, amount: u64, denom: Option<&str>) -> Self {
+        self.min_fees
+            .entry(channel_id.to_owned())
+            .or_default()
+            .recv
+            .push(FeeAmount {
+                amount,
+                denom: denom.map(|d| d.to_owned()),
+            });
+        self
+    }
 
-# Whether or not to enable misbehaviour detection for clients. [Default: true]
-misbehaviour = true
+    /// Returns if packets on `port_id` and `channel_id` are relayed according
+    /// to the policy, ignoring fees. Only exact ids and `*` suffix wildcards
+    /// are supported.
+    pub fn allows(&self, port_id: &str, channel_id: &str) -> bool {
+        fn matches(pattern: &str, id: &str) -> bool {
+            if let Some(prefix) = pattern.strip_suffix('*') {
+                id.starts_with(prefix)
+            } else {
+                pattern == id
+            }
+        }
+        let listed = self
+            .list
+            .iter()
+            .any(|(port, channel)| matches(port, port_id) && matches(channel, channel_id));
+        match self.policy {
+            None => true,
+            Some(PacketFilterPolicy::Allow) => listed,
+            Some(PacketFilterPolicy::Deny) => !listed,
+        }
+    }
+}
+
+impl Default for HermesConfig {
+    fn default() -> Self {
+        Self {
+            global: GlobalConfig {
+                log_level: "info".to_owned(),
+            },
+            mode: ModeConfig {
+                clients: ClientsMode {
+                    enabled: true,
+                    refresh: true,
+                    misbehaviour: true,
+                },
+                connections: ConnectionsMode { enabled: false },
+                channels: ChannelsMode { enabled: false },
+                packets: PacketsMode {
+                    enabled: true,
+                    clear_interval: 0,
+                    clear_on_start: true,
+                    tx_confirmation: false,
+                    auto_register_counterparty_payee: false,
+                },
+            },
+            rest: RestConfig {
+                enabled: true,
+                host: "127.0.0.1".to_owned(),
+                port: 3000,
+            },
+            telemetry: TelemetryConfig {
+                enabled: false,
+                host: "127.0.0.1".to_owned(),
+                port: 3001,
+            },
+            chains: vec![],
+        }
+    }
+}
+
+impl HermesConfig {
+    /// The default config with an entry for each of `chain_configs`
+    pub fn new(chain_configs: &[HermesChainConfig]) -> Self {
+        Self {
+            chains: chain_configs.iter().map(|c| c.to_chain_config()).collect(),
+            ..Default::default()
+        }
+    }
 
-# Specify the connections mode.
-[mode.connections]
+    pub fn from_toml(s: &str) -> Result<Self> {
+        toml::from_str(s).stack_err(|| "HermesConfig::from_toml")
+    }
 
-# Whether or not to enable the connection workers for handshake completion. [Required]
-enabled = false
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).stack_err(|| "HermesConfig::to_toml")
+    }
 
-# Specify the channels mode.
-[mode.channels]
+    pub async fn read(path: &str) -> Result<Self> {
+        let s = FileOptions::read_to_string(path).await.stack()?;
+        Self::from_toml(&s).stack_err(|| format!("HermesConfig::read(path: {path})"))
+    }
 
-# Whether or not to enable the channel workers for handshake completion. [Required]
-enabled = false
+    pub async fn write(&self, path: &str) -> Result<()> {
+        FileOptions::write_str(path, &self.to_toml().stack()?)
+            .await
+            .stack()
+    }
 
-# Specify the packets mode.
-[mode.packets]
+    pub fn chain_config(&self, chain_id: &str) -> Result<&ChainConfig> {
+        self.chains
+            .iter()
+            .find(|chain| chain.id == chain_id)
+            .stack_err(|| format!("HermesConfig has no chain with id \"{chain_id}\""))
+    }
 
-# Whether or not to enable the packet workers. [Required]
-enabled = true
+    pub fn chain_config_mut(&mut self, chain_id: &str) -> Result<&mut ChainConfig> {
+        self.chains
+            .iter_mut()
+            .find(|chain| chain.id == chain_id)
+            .stack_err(|| format!("HermesConfig has no chain with id \"{chain_id}\""))
+    }
 
-# Parametrize the periodic packet clearing feature.
-# Interval (in number of blocks) at which pending packets
-# should be periodically cleared. A value of '0' will disable
-# periodic packet clearing. [Default: 100]
-clear_interval = 0
-
-# Whether or not to clear packets on start. [Default: true]
-clear_on_start = true
-
-# Toggle the transaction confirmation mechanism.
-# The tx confirmation mechanism periodically queries the `/tx_search` RPC
-# endpoint to check that previously-submitted transactions
-# (to any chain in this config file) have been successfully delivered.
-# If they have not been, and `clear_interval = 0`, then those packets are
-# queued up for re-submission.
-# If set to `false`, the following telemetry metrics will be disabled:
-# `acknowledgment_packets_confirmed`, `receive_packets_confirmed` and `timeout_packets_confirmed`.
-# [Default: false]
-tx_confirmation = false
-
-# Auto register the counterparty payee on a destination chain to
-# the relayer's address on the source chain. This can be used
-# for simple configuration of the relayer to receive fees for
-# relaying RecvPacket on fee-enabled channels.
-# For more complex configuration, turn this off and use the CLI
-# to manually register the payee addresses.
-# [Default: false]
-auto_register_counterparty_payee = false
-
-# The REST section defines parameters for Hermes' built-in RESTful API.
-# https://hermes.informal.systems/rest.html
-[rest]
-
-# Whether or not to enable the REST service. Default: false
-enabled = true
-
-# Specify the IPv4/6 host over which the built-in HTTP server will serve the RESTful
-# API requests. Default: 127.0.0.1
-host = '127.0.0.1'
-
-# Specify the port over which the built-in HTTP server will serve the restful API
-# requests. Default: 3000
-port = 3000
-
-
-# The telemetry section defines parameters for Hermes' built-in telemetry capabilities.
-# https://hermes.informal.systems/telemetry.html
-[telemetry]
-
-# Whether or not to enable the telemetry service. Default: false
-enabled = false
-
-# Specify the IPv4/6 host over which the built-in HTTP server will serve the metrics
-# gathered by the telemetry service. Default: 127.0.0.1
-host = '127.0.0.1'
-
-# Specify the port over which the built-in HTTP server will serve the metrics gathered
-# by the telemetry service. Default: 3001
-port = 3001
-
-"##;
-
-/*
-[[chains]]
-# Specify the chain ID. Required
-id = 'onomy'
-
-# Whether or not this is a CCV consumer chain. Default: false
-# Only specifiy true for CCV consumer chain, but NOT for sovereign chains.
-ccv_consumer_chain = false
-
-# Specify the RPC address and port where the chain RPC server listens on. Required
-rpc_addr = 'http://onomyd:26657'
-
-# Specify the GRPC address and port where the chain GRPC server listens on. Required
-grpc_addr = 'http://onomyd:9090'
-
-# Specify the WebSocket address and port where the chain WebSocket server
-# listens on. Required
-websocket_addr = 'ws://onomyd:26657/websocket'
-event_source = { mode = 'push', url = '$WS_URL', batch_delay = '200ms' }
-
-# Specify the maximum amount of time (duration) that the RPC requests should
-# take before timing out. Default: 10s (10 seconds)
-# Note: Hermes uses this parameter _only_ in `start` mode; for all other CLIs,
-# Hermes uses a large preconfigured timeout (on the order of minutes).
-rpc_timeout = '10s'
-
-# Specify the prefix used by the chain. Required
-account_prefix = 'onomy'
-
-# Specify the name of the private key to use for signing transactions. Required
-# See the Adding Keys chapter for more information about managing signing keys:
-#   https://hermes.informal.systems/commands/keys/index.html#adding-keys
-key_name = 'validator'
-
-# Specify the folder used to store the keys. Optional
-# If this is not specified then the hermes home folder is used.
-# key_store_folder = '$HOME/.hermes/keys'
-
-# Specify the address type which determines:
-# 1) address derivation;
-# 2) how to retrieve and decode accounts and pubkeys;
-# 3) the message signing method.
-# The current configuration options are for Cosmos SDK and Ethermint.
-#
-# Example configuration for chains based on Ethermint library:
-#
-# address_type = { derivation = 'ethermint', proto_type
-# = { pk_type = '/ethermint.crypto.v1.ethsecp256k1.PubKey' } }
-#
-# Default: { derivation = 'cosmos' }, i.e. address derivation as in Cosmos SDK.
-# Warning: This is an advanced feature! Modify with caution.
-address_type = { derivation = 'cosmos' }
-
-# Specify the store prefix used by the on-chain IBC modules. Required
-# Recommended value for Cosmos SDK: 'ibc'
-store_prefix = 'ibc'
-
-# Gas Parameters
-#
-# The term 'gas' is used to denote the amount of computation needed to execute
-# and validate a transaction on-chain. It can be thought of as fuel that gets
-# spent in order to power the on-chain execution of a transaction.
-#
-# Hermes attempts to simulate how much gas a transaction will expend on its
-# target chain. From that, it calculates the cost of that gas by multiplying the
-# amount of estimated gas by the `gas_multiplier` and the `gas_price`
-# (estimated gas * `gas_multiplier` * `gas_price`) in order to compute the
-# total fee to be deducted from the relayer's wallet.
-#
-# The `simulate_tx` operation does not always correctly estimate the appropriate
-# amount of gas that a transaction requires. In those cases when the operation
-# fails, Hermes will attempt to submit the transaction using the specified
-# `default_gas` and `max_gas` parameters. In the case that a transaction would
-# require more than `max_gas`, it doesn't get submitted and a
-# `TxSimulateGasEstimateExceeded` error is returned.
-
-# Specify the default amount of gas to be used in case the tx simulation fails,
-# and Hermes cannot estimate the amount of gas needed.
-# Default: 100 000
-default_gas = 100000
-
-# Specify the maximum amount of gas to be used as the gas limit for a transaction.
-# If `default_gas` is unspecified, then `max_gas` will be used as `default_gas`.
-# Default: 400 000
-max_gas = 400000
-
-# Specify the price per gas used of the fee to submit a transaction and
-# the denomination of the fee.
-#
-# The specified gas price should always be greater or equal to the `min-gas-price`
-# configured on the chain. This is to ensure that at least some minimal price is
-# paid for each unit of gas per transaction.
-#
-# Required
-gas_price = { price = 1, denom = 'anom' }
-
-# Multiply this amount with the gas estimate, used to compute the fee
-# and account for potential estimation error.
-#
-# The purpose of multiplying by `gas_multiplier` is to provide a bit of a buffer
-# to catch some of the cases when the gas estimation calculation is on the low
-# end.
-#
-# Example: With this setting set to 1.1, then if the estimated gas
-# is 80_000, then gas used to compute the fee will be adjusted to
-# 80_000 * 1.1 = 88_000.
-#
-# Default: 1.1, ie. the gas is increased by 10%
-# Minimum value: 1.0
-gas_multiplier = 1.1
-
-# Specify how many IBC messages at most to include in a single transaction.
-# Default: 30
-max_msg_num = 30
-
-# Specify the maximum size, in bytes, of each transaction that Hermes will submit.
-# Default: 2097152 (2 MiB)
-max_tx_size = 2097152
-
-# Specify the maximum amount of time to tolerate a clock drift.
-# The clock drift parameter defines how much new (untrusted) header's time
-# can drift into the future. Default: 5s
-clock_drift = '5s'
-
-# Specify the maximum time per block for this chain.
-# The block time together with the clock drift are added to the source drift to estimate
-# the maximum clock drift when creating a client on this chain. Default: 30s
-# For cosmos-SDK chains a good approximation is `timeout_propose` + `timeout_commit`
-# Note: This MUST be the same as the `max_expected_time_per_block`
-# genesis parameter for Tendermint chains.
-max_block_time = '2s'
-
-# Specify the amount of time to be used as the light client trusting period.
-# It should be significantly less than the unbonding period
-# (e.g. unbonding period = 3 weeks, trusting period = 2 weeks).
-# Default: 2/3 of the `unbonding period` for Cosmos SDK chains
-trusting_period = '14days'
-
-# Specify the trust threshold for the light client, ie. the minimum fraction of validators
-# which must overlap across two blocks during light client verification.
-# Default: { numerator = '2', denominator = '3' }, ie. 2/3.
-# Warning: This is an advanced feature! Modify with caution.
-trust_threshold = { numerator = '2', denominator = '3' }
-
-# Specify a string that Hermes will use as a memo for each transaction it submits
-# to this chain. The string is limited to 50 characters. Default: '' (empty).
-# Note: Hermes will append to the string defined here additional
-# operational debugging information, e.g., relayer build version.
-memo_prefix = ''
-
-# This section specifies the filters for policy based relaying.
-#
-# Default: no policy / filters, allow all packets on all channels.
-#
-# Only packet filtering based on channel identifier can be specified.
-# A channel filter has two fields:
-# 1. `policy` - one of two types are supported:
-#       - 'allow': permit relaying _only on_ the port/channel id in the list below,
-#       - 'deny': permit relaying on any channel _except for_ the list below.
-# 2. `list` - the list of channels specified by the port and channel identifiers.
-#             Optionally, each element may also contains wildcards, for eg. 'ica*'
-#             to match all identifiers starting with 'ica' or '*' to match all identifiers.
-#
-# Example configuration of a channel filter, only allowing packet relaying on
-# channel with port ID 'transfer' and channel ID 'channel-0', as well as on
-# all ICA channels.
-#
-# [chains.packet_filter]
-# policy = 'allow'
-# list = [
-#   ['ica*', '*'],
-#   ['transfer', 'channel-0'],
-# ]
-
-# This section specifies the filters for incentivized packet relaying.
-# Default: no filters, will relay all packets even if they
-# are not incentivized.
-#
-# It is possible to specify the channel or use wildcards for the
-# channels.
-# The only fee which can be parametrized is the `recv_fee`.
-#
-# Example configuration of a filter which will only relay incentivized
-# packets, with no regards for channel and amount.
-#
-# [chains.packet_filter.min_fees.'*']
-# recv = [ { amount = 0 } ]
-#
-# Example configuration of a filter which will only relay packets if they are
-# from the channel 'channel-0', and they have a `recv_fee` of at least 20 stake
-# or 10 uatom.
-#
-# [chains.packet_filter.min_fees.'channel-0']
-# recv = [ { amount = 20, denom = 'stake' }, { amount = 10, denom = 'uatom' } ]
-
-# Specify that the transaction fees should be payed from this fee granter's account.
-# Optional. If unspecified (the default behavior), then no fee granter is used, and
-# the account specified in `key_name` will pay the tx fees for all transactions
-# submitted to this chain.
-# fee_granter = ''
-*/
+    /// Adds an entry for `chain_config`, returning an error if there already is
+    /// one with the same id
+    pub fn add_chain(&mut self, chain_config: &HermesChainConfig) -> Result<()> {
+        if self.chain_config(&chain_config.chain_id).is_ok() {
+            return Err(Error::from(format!(
+                "HermesConfig already has a chain with id \"{}\"",
+                chain_config.chain_id
+            )))
+        }
+        self.chains.push(chain_config.to_chain_config());
+        Ok(())
+    }
+}
 
+/// The settings of a chain that differ between our tests, see
+/// [HermesChainConfig::to_chain_config] for the rest
 #[derive(Debug, Clone)]
 pub struct HermesChainConfig {
     pub chain_id: String,
@@ -318,6 +367,8 @@ pub struct HermesChainConfig {
     pub gas_denom: String,
     pub fast_block_times: bool,
     pub max_gas: u64,
+    /// If `None`, all channels are relayed
+    pub packet_filter: Option<PacketFilter>,
 }
 
 impl HermesChainConfig {
@@ -339,47 +390,57 @@ impl HermesChainConfig {
             gas_denom: gas_denom.to_owned(),
             fast_block_times,
             max_gas: 1000000,
+            packet_filter: None,
         }
     }
-}
 
-impl ToString for HermesChainConfig {
-    fn to_string(&self) -> String {
-        let chain_id = &self.chain_id;
-        let rpc_addr = &self.rpc_addr;
-        let grpc_addr = &self.grpc_addr;
-        let event_addr = &self.event_addr;
-        let account_prefix = &self.account_prefix;
-        let ccv_consumer_chain = self.ccv_consumer_chain;
-        let gas_denom = &self.gas_denom;
-        let max_block_time = if self.fast_block_times { "2s" } else { "30s" };
-        let max_gas = &format!("{}", self.max_gas);
-        format!(
-            r##"[[chains]]
-id = '{chain_id}'
-ccv_consumer_chain = {ccv_consumer_chain}
-rpc_addr = '{rpc_addr}'
-grpc_addr = '{grpc_addr}'
-event_source = {{ mode = 'push', url = '{event_addr}', batch_delay = '200ms' }}
-rpc_timeout = '10s'
-account_prefix = '{account_prefix}'
-key_name = 'validator'
-store_prefix = 'ibc'
-default_gas = 100000
-max_gas = {max_gas}
-# note: this is changed to IBC NOM during bootstrap
-gas_price = {{ price = 1, denom = '{gas_denom}' }}
-gas_multiplier = 1.1
-max_msg_num = 30
-max_tx_size = 2097152
-clock_drift = '5s'
-max_block_time = '{max_block_time}'
-trusting_period = '14days'
-trust_threshold = {{ numerator = '1', denominator = '3' }}
-address_type = {{ derivation = 'cosmos' }}
-
-"##
-        )
+    /// Restricts the channels that hermes relays on for this chain, e.x. to
+    /// run separate relayers for ICS and transfer channels
+    pub fn packet_filter(mut self, packet_filter: PacketFilter) -> Self {
+        self.packet_filter = Some(packet_filter);
+        self
+    }
+
+    /// The full `[[chains]]` entry with our standard settings
+    pub fn to_chain_config(&self) -> ChainConfig {
+        ChainConfig {
+            id: self.chain_id.clone(),
+            ccv_consumer_chain: self.ccv_consumer_chain,
+            rpc_addr: self.rpc_addr.clone(),
+            grpc_addr: self.grpc_addr.clone(),
+            event_source: EventSource {
+                mode: "push".to_owned(),
+                url: self.event_addr.clone(),
+                batch_delay: "200ms".to_owned(),
+            },
+            rpc_timeout: "10s".to_owned(),
+            account_prefix: self.account_prefix.clone(),
+            key_name: "validator".to_owned(),
+            store_prefix: "ibc".to_owned(),
+            default_gas: 100000,
+            max_gas: self.max_gas,
+            // note: this is changed to IBC NOM during bootstrap
+            gas_price: GasPrice {
+                price: 1.0,
+                denom: self.gas_denom.clone(),
+            },
+            gas_multiplier: 1.1,
+            max_msg_num: 30,
+            max_tx_size: 2097152,
+            clock_drift: "5s".to_owned(),
+            max_block_time: if self.fast_block_times { "2s" } else { "30s" }.to_owned(),
+            trusting_period: "14days".to_owned(),
+            trust_threshold: TrustThreshold {
+                numerator: "1".to_owned(),
+                denominator: "3".to_owned(),
+            },
+            address_type: AddressType {
+                derivation: "cosmos".to_owned(),
+            },
+            memo_prefix: String::new(),
+            fee_granter: None,
+            packet_filter: self.packet_filter.clone(),
+        }
     }
 }
 
@@ -387,9 +448,69 @@ pub async fn write_hermes_config(
     chain_configs: &[HermesChainConfig],
     write_dir: &str,
 ) -> Result<()> {
-    let mut s = HEADER.to_owned();
-    for config in chain_configs {
-        s += &config.to_string();
-    }
-    FileOptions::write_str(&format!("{write_dir}/__tmp_hermes_config.toml"), &s).await
+    HermesConfig::new(chain_configs)
+        .write(&format!("{write_dir}/__tmp_hermes_config.toml"))
+        .await
+        .stack()
+}
+
+#[test]
+fn test_hermes_config_round_trip() {
+    let mut config = HermesConfig::new(&[
+        HermesChainConfig::new("onomy", "onomyd_uuid", "onomy", false, "anom", true),
+        HermesChainConfig::new("onex", "onexd_uuid", "onomy", true, "aonex", false),
+    ]);
+    config.mode.channels.enabled = true;
+    config.chain_config_mut("onex").unwrap().packet_filter =
+        Some(PacketFilter::allow([("transfer", "channel-1")]));
+    let s = config.to_toml().unwrap();
+    assert!(s.contains("[[chains]]"));
+    assert!(s.contains("policy = \"allow\""));
+    let parsed = HermesConfig::from_toml(&s).unwrap();
+    assert_eq!(parsed, config);
+    let onex = parsed.chain_config("onex").unwrap();
+    assert_eq!(onex.event_source.url, "ws://onexd_uuid:26657/websocket");
+    assert_eq!(onex.max_block_time, "30s");
+    let onomy = parsed.chain_config("onomy").unwrap();
+    assert!(onomy.packet_filter.is_none());
+    assert!(parsed.chain_config("other").is_err());
+    let duplicate = HermesChainConfig::new("onomy", "h", "onomy", false, "anom", true);
+    assert!(config.add_chain(&duplicate).is_err());
+}
+
+#[test]
+fn test_packet_filter() {
+    let ics = PacketFilter::allow([("consumer", "*"), ("provider", "*")]);
+    assert!(ics.allows("consumer", "channel-0"));
+    assert!(!ics.allows("transfer", "channel-1"));
+    let unrelayed = PacketFilter::deny([("transfer", "channel-1")]);
+    assert!(unrelayed.allows("transfer", "channel-0"));
+    assert!(!unrelayed.allows("transfer", "channel-1"));
+    assert!(PacketFilter::default().allows("transfer", "channel-1"));
+
+    let transfer = PacketFilter::allow([("transfer", "*")])
+        .min_recv_fee("channel-0", 20, Some("anom"))
+        .min_recv_fee("channel-0", 10, None);
+    let onomy = HermesChainConfig::new("onomy", "onomyd_uuid", "onomy", false, "anom", true)
+        .packet_filter(transfer.clone());
+    let config = HermesConfig::new(&[onomy]);
+    let s = config.to_toml().unwrap();
+    assert!(s.contains("[[chains.packet_filter.min_fees.channel-0.recv]]"));
+    let parsed = HermesConfig::from_toml(&s).unwrap();
+    let filter = parsed.chain_config("onomy").unwrap().packet_filter.as_ref();
+    assert_eq!(filter, Some(&transfer));
+    assert_eq!(transfer.min_fees["channel-0"].recv[1].denom, None);
+
+    // the format from the Hermes docs
+    let filter: PacketFilter = toml::from_str(
+        r#"
+        policy = 'allow'
+        list = [['ica*', '*'], ['transfer', 'channel-0']]
+        [min_fees.'channel-0']
+        recv = [ { amount = 20, denom = 'stake' }, { amount = 10, denom = 'uatom' } ]
+        "#,
+    )
+    .unwrap();
+    assert!(filter.allows("icahost", "channel-5"));
+    assert_eq!(filter.min_fees["channel-0"].recv[0].amount, 20);
 }
@@ -1,11 +1,24 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use log::info;
 use serde_derive::{Deserialize, Serialize};
 pub use super_orchestrator::stacked_errors::Result;
-use super_orchestrator::{get_separated_val, stacked_errors::StackableErr};
+use super_orchestrator::{
+    stacked_errors::{Error, StackableErr},
+    wait_for_ok,
+};
+use u64_array_bigints::U256;
 
 use crate::{
-    cosmovisor::{sh_cosmovisor_no_debug, sh_cosmovisor_tx},
-    hermes::{create_channel_pair, create_connection_pair, sh_hermes},
+    cosmovisor::{cosmovisor_get_balances, CosmovisorCmd},
+    denom_trace::DenomTrace,
+    hermes::{
+        create_channel_pair, create_channel_pair_with_version, create_client_pair,
+        create_connection_pair, hermes_assert_packet_timed_out, hermes_ensure_channel_open,
+        hermes_find_channel, hermes_wait_for_drained, sh_hermes, ChannelState,
+    },
+    tx::{FeePolicy, TxResponse},
+    STD_DELAY, STD_TRIES,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,7 +26,8 @@ pub struct IbcSide {
     pub chain_id: String,
     pub connection: String,
     pub transfer_channel: String,
-    pub ics_channel: String,
+    /// The consumer or provider channel, `None` if the pair is not an ICS pair
+    pub ics_channel: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +36,94 @@ pub struct IbcPair {
     pub b: IbcSide,
 }
 
+/// The timeout flags of `tx ibc-transfer transfer`. Unset fields keep the CLI
+/// defaults of a relative timeout height of "0-1000" and a relative timeout
+/// timestamp of 10 minutes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketTimeout {
+    /// The revision number and revision height
+    pub height: Option<(u64, u64)>,
+    pub timestamp_ns: Option<u64>,
+    /// If the timeouts are absolute instead of relative to the latest
+    /// consensus state of the counterparty
+    pub absolute: bool,
+}
+
+impl PacketTimeout {
+    /// `--packet-timeout-height {revision_number}-{revision_height}`
+    pub fn height(mut self, revision_number: u64, revision_height: u64) -> Self {
+        self.height = Some((revision_number, revision_height));
+        self
+    }
+
+    /// `--packet-timeout-timestamp` in nanoseconds
+    pub fn timestamp(mut self, timestamp: Duration) -> Self {
+        self.timestamp_ns = Some(u64::try_from(timestamp.as_nanos()).unwrap_or(u64::MAX));
+        self
+    }
+
+    /// `--absolute-timeouts`
+    pub fn absolute(mut self) -> Self {
+        self.absolute = true;
+        self
+    }
+
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some((revision_number, revision_height)) = self.height {
+            args.push("--packet-timeout-height".to_owned());
+            args.push(format!("{revision_number}-{revision_height}"));
+        }
+        if let Some(timestamp_ns) = self.timestamp_ns {
+            args.push("--packet-timeout-timestamp".to_owned());
+            args.push(timestamp_ns.to_string());
+        }
+        if self.absolute {
+            args.push("--absolute-timeouts".to_owned());
+        }
+        args
+    }
+}
+
+/// Returns the sequence of the packet sent by a transaction
+pub fn sent_packet_sequence(tx: &TxResponse) -> Result<u64> {
+    tx.attribute("send_packet", "packet_sequence")
+        .stack_err(|| "transaction has no `send_packet` event with a `packet_sequence`")?
+        .parse()
+        .stack()
+}
+
+/// Waits until the `denom` balance of `addr` is refunded `amount` on top of
+/// `balance_after_send`, the balance right after sending a transfer that
+/// times out
+pub async fn cosmovisor_wait_for_refund(
+    addr: &str,
+    denom: &str,
+    balance_after_send: U256,
+    amount: U256,
+) -> Result<()> {
+    let expected = balance_after_send
+        .checked_add(amount)
+        .stack_err(|| "cosmovisor_wait_for_refund overflow")?;
+    wait_for_ok(STD_TRIES, STD_DELAY, || async {
+        let balance = cosmovisor_get_balances(addr)
+            .await
+            .stack()?
+            .get(denom)
+            .cloned()
+            .unwrap_or(U256::from_u64(0));
+        if balance == expected {
+            Ok(())
+        } else {
+            Err(Error::from(format!(
+                "{denom} balance of {addr} is {balance} instead of {expected}"
+            )))
+        }
+    })
+    .await
+    .stack_err(|| format!("cosmovisor_wait_for_refund({addr}, {denom})"))
+}
+
 impl IbcSide {
     /// This call needs to be made on the source side
     pub async fn cosmovisor_ibc_transfer_with_flags(
@@ -34,74 +136,137 @@ impl IbcSide {
         // tx ibc-transfer transfer transfer [channel to right chain]
         // [target cosmos addr] [coins to send] [gas flags] --from [source key name]
 
-        let mut args = vec![
-            "ibc-transfer transfer transfer",
-            &self.transfer_channel,
-            target_addr,
-            coins_to_send,
-        ];
-        args.extend(flags);
-        sh_cosmovisor_tx(args).await.stack()?;
+        CosmovisorCmd::tx("ibc-transfer transfer transfer")
+            .args([self.transfer_channel.as_str(), target_addr, coins_to_send])
+            .args(flags)
+            .run_tx()
+            .await
+            .stack()?;
 
         Ok(())
     }
 
-    /// Sends `denom` and uses same `denom` for gas. Uses the flags
-    /// "-b block --gas auto --gas-adjustment 1.3 --gas-prices 1{denom} --from
-    /// {from_key}"
+    /// Sends `{amount}{denom}` from `from_key`, paying with `fee`
     pub async fn cosmovisor_ibc_transfer(
         &self,
         from_key: &str,
         target_addr: &str,
         amount: &str,
         denom: &str,
+        fee: &FeePolicy,
     ) -> Result<()> {
         let coins_to_send = format!("{amount}{denom}");
-        let base = format!("1{denom}");
-        sh_cosmovisor_tx([
-            "ibc-transfer transfer transfer",
-            &self.transfer_channel,
-            target_addr,
-            &coins_to_send,
-            "-y",
-            "-b",
-            "block",
-            "--gas",
-            "auto",
-            "--gas-adjustment",
-            "1.3",
-            "--gas-prices",
-            &base,
-            "--from",
-            from_key,
-        ])
-        .await
-        .stack()?;
+        CosmovisorCmd::tx("ibc-transfer transfer transfer")
+            .args([self.transfer_channel.as_str(), target_addr, &coins_to_send])
+            .fee_policy(fee)
+            .from(from_key)
+            .run_tx()
+            .await
+            .stack()?;
 
         Ok(())
     }
 
+    /// Like [IbcSide::cosmovisor_ibc_transfer] but with the `timeout` flags,
+    /// returns the sequence of the sent packet
+    pub async fn cosmovisor_ibc_transfer_with_timeout(
+        &self,
+        from_key: &str,
+        target_addr: &str,
+        amount: &str,
+        denom: &str,
+        fee: &FeePolicy,
+        timeout: &PacketTimeout,
+    ) -> Result<u64> {
+        let coins_to_send = format!("{amount}{denom}");
+        let tx = CosmovisorCmd::tx("ibc-transfer transfer transfer")
+            .args([self.transfer_channel.as_str(), target_addr, &coins_to_send])
+            .args(timeout.to_args())
+            .fee_policy(fee)
+            .from(from_key)
+            .run_tx()
+            .await
+            .stack()?;
+        sent_packet_sequence(&tx).stack()
+    }
+
+    /// Returns the trace that `denom` (native to the other side) has on this
+    /// side after being received over the transfer channel
+    pub fn ibc_denom_trace(&self, denom: &str) -> DenomTrace {
+        DenomTrace::new(denom).prefix("transfer", &self.transfer_channel)
+    }
+
+    /// Returns the IBC denom of `leaf_denom` received over the transfer
+    /// channel, checking that the chain has the same trace for it
     pub async fn get_ibc_denom(&self, leaf_denom: &str) -> Result<String> {
-        let hash = sh_cosmovisor_no_debug([
-            "query ibc-transfer denom-hash",
-            &format!("transfer/{}/{}", self.transfer_channel, leaf_denom),
-        ])
-        .await
-        .stack()?;
-        let hash = get_separated_val(&hash, "\n", "hash", ":").stack()?;
-        Ok(format!("ibc/{hash}"))
+        let trace = self.ibc_denom_trace(leaf_denom);
+        trace.cosmovisor_verify().await.stack()?;
+        Ok(trace.ibc_denom())
     }
 }
 
 impl IbcPair {
+    /// Returns the side on `chain_id` and the other side
+    pub fn sides(&self, chain_id: &str) -> Result<(&IbcSide, &IbcSide)> {
+        if self.a.chain_id == chain_id {
+            Ok((&self.a, &self.b))
+        } else if self.b.chain_id == chain_id {
+            Ok((&self.b, &self.a))
+        } else {
+            Err(Error::from(format!(
+                "chain {chain_id} is not part of the pair {} <-> {}",
+                self.a.chain_id, self.b.chain_id
+            )))
+        }
+    }
+
+    /// Returns an error unless the transfer with packet `sequence` sent from
+    /// `src_chain_id` timed out, see [hermes_assert_packet_timed_out]
+    pub async fn hermes_assert_transfer_timed_out(
+        &self,
+        src_chain_id: &str,
+        sequence: u64,
+    ) -> Result<()> {
+        let (src, dst) = self.sides(src_chain_id).stack()?;
+        hermes_assert_packet_timed_out(
+            &src.chain_id,
+            "transfer",
+            &src.transfer_channel,
+            &dst.chain_id,
+            sequence,
+        )
+        .await
+        .stack()
+    }
+
+    /// Waits until the transfer channel has no pending packets, and then
+    /// checks that the `timed_out` transfers sent from `src_chain_id` timed out
+    pub async fn hermes_wait_for_transfers(
+        &self,
+        src_chain_id: &str,
+        timed_out: &[u64],
+    ) -> Result<()> {
+        let (src, _) = self.sides(src_chain_id).stack()?;
+        hermes_wait_for_drained(&src.chain_id, "transfer", &src.transfer_channel)
+            .await
+            .stack()?;
+        for sequence in timed_out {
+            self.hermes_assert_transfer_timed_out(src_chain_id, *sequence)
+                .await
+                .stack()?;
+        }
+        Ok(())
+    }
+
     /// Sets up consumer-provider and transfer-transfer IBC channels. This
     /// function assumes ICS setup has been performed, which creates a
     /// client pair automatically.
     ///
-    /// TODO this currently assumes that this is the first channel creation
-    /// function that happens on chain startup, it hard codes the transfer
-    /// channel number. Additionally, `hermes start` should be run _after_
-    /// this function is called.
+    /// The transfer channel that ICS creates in the INIT state is found by
+    /// querying the consumer for channels on the new connection, so this works
+    /// regardless of what channels already exist. Both ends of both channels
+    /// are verified to be OPEN before returning. Note that `hermes start`
+    /// should be run _after_ this function is called.
     pub async fn hermes_setup_ics_pair(
         consumer: &str,
         consumer_client: &str,
@@ -132,42 +297,32 @@ impl IbcPair {
 
         // ICS channel creation also automatically creates a transfer channel, but it
         // starts in the init state and we need to manually perform the 3 other steps.
-
-        //let transfer_channel_pair = create_channel_pair(&a_chain, &connection_pair.0,
-        // "transfer", "transfer", false).await.stack()?;
-
-        // the transfer channel should be the ICS channel plus one
-        let a_channel_i = ics_channel_pair
-            .0
-            .rsplit_once('-')
-            .stack()?
-            .1
-            .parse::<u64>()
-            .stack()?
-            .checked_add(1)
-            .stack()?;
-        let b_channel_i = ics_channel_pair
-            .1
-            .rsplit_once('-')
-            .stack()?
-            .1
-            .parse::<u64>()
-            .stack()?
-            .checked_add(1)
-            .stack()?;
-
-        // FIXME this is hard coded
-        let transfer_channel_pair = (
-            format!("channel-{a_channel_i}"),
-            format!("channel-{b_channel_i}"),
-        );
+        let a_transfer =
+            hermes_find_channel(&a_chain, &connection_pair.0, "transfer", ChannelState::Init)
+                .await
+                .stack_err(|| "could not find the transfer channel that ICS initialized")?;
         sh_hermes([format!(
             "tx chan-open-try --dst-chain {provider} --src-chain {consumer} --dst-connection {} \
              --dst-port transfer --src-port transfer --src-channel {}",
-            connection_pair.1, transfer_channel_pair.0
+            connection_pair.1, a_transfer.channel_id
         )])
         .await
         .stack()?;
+        let b_transfer = hermes_find_channel(
+            &b_chain,
+            &connection_pair.1,
+            "transfer",
+            ChannelState::TryOpen,
+        )
+        .await
+        .stack()?;
+        if b_transfer.counterparty_channel_id.as_ref() != Some(&a_transfer.channel_id) {
+            return Err(Error::from(format!(
+                "the TRYOPEN transfer channel {b_transfer:?} does not have {} as its counterparty",
+                a_transfer.channel_id
+            )))
+        }
+        let transfer_channel_pair = (a_transfer.channel_id, b_transfer.channel_id);
         sh_hermes([format!(
             "tx chan-open-ack --dst-chain {consumer} --src-chain {provider} --dst-connection {} \
              --dst-port transfer --src-port transfer --dst-channel {} --src-channel {}",
@@ -183,6 +338,17 @@ impl IbcPair {
         .await
         .stack()?;
 
+        for (chain, port, channel) in [
+            (&a_chain, "consumer", &ics_channel_pair.0),
+            (&b_chain, "provider", &ics_channel_pair.1),
+            (&a_chain, "transfer", &transfer_channel_pair.0),
+            (&b_chain, "transfer", &transfer_channel_pair.1),
+        ] {
+            hermes_ensure_channel_open(chain, port, channel)
+                .await
+                .stack()?;
+        }
+
         info!("{consumer} <-> {provider} consumer-provider and transfer channels have been set up");
 
         Ok(IbcPair {
@@ -190,14 +356,215 @@ impl IbcPair {
                 chain_id: a_chain,
                 connection: connection_pair.0,
                 transfer_channel: transfer_channel_pair.0,
-                ics_channel: ics_channel_pair.0,
+                ics_channel: Some(ics_channel_pair.0),
             },
             b: IbcSide {
                 chain_id: b_chain,
                 connection: connection_pair.1,
                 transfer_channel: transfer_channel_pair.1,
-                ics_channel: ics_channel_pair.1,
+                ics_channel: Some(ics_channel_pair.1),
+            },
+        })
+    }
+}
+
+/// A channel for [IbcLink::setup] to create
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSpec {
+    pub a_port: String,
+    pub b_port: String,
+    pub ordered: bool,
+    /// If `None`, the version is chosen by the chains
+    pub version: Option<String>,
+}
+
+impl ChannelSpec {
+    /// An unordered channel between `a_port` and `b_port`
+    pub fn new(a_port: &str, b_port: &str) -> Self {
+        Self {
+            a_port: a_port.to_owned(),
+            b_port: b_port.to_owned(),
+            ordered: false,
+            version: None,
+        }
+    }
+
+    /// The standard ICS-20 token transfer channel
+    pub fn transfer() -> Self {
+        Self::new("transfer", "transfer")
+    }
+
+    /// An ordered interchain accounts channel from the controller port
+    /// `controller_port` (e.x. "icacontroller-{owner}") to the "icahost" port,
+    /// `version` should be the JSON ICA metadata
+    pub fn ica(controller_port: &str, version: &str) -> Self {
+        Self::new(controller_port, "icahost")
+            .ordered()
+            .version(version)
+    }
+
+    pub fn ordered(mut self) -> Self {
+        self.ordered = true;
+        self
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_owned());
+        self
+    }
+}
+
+/// The channel ids of both ends of a channel created by [IbcLink::setup]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkChannel {
+    pub spec: ChannelSpec,
+    pub a_channel: String,
+    pub b_channel: String,
+}
+
+/// A client, connection, and channels between two chains that do not have an
+/// ICS relationship, see [IbcPair::hermes_setup_ics_pair] for ICS pairs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IbcLink {
+    pub a_chain: String,
+    pub b_chain: String,
+    pub a_client: String,
+    pub b_client: String,
+    pub a_connection: String,
+    pub b_connection: String,
+    /// The channels keyed by their port on the `a_chain` side
+    pub channels: BTreeMap<String, LinkChannel>,
+}
+
+impl IbcLink {
+    /// Creates a client pair and connection between `a_chain` and `b_chain`,
+    /// then creates each of `channels` over the connection. `hermes start`
+    /// should be run _after_ this function is called.
+    pub async fn setup(a_chain: &str, b_chain: &str, channels: &[ChannelSpec]) -> Result<Self> {
+        let mut a_ports: Vec<&str> = vec![];
+        for spec in channels {
+            if a_ports.contains(&spec.a_port.as_str()) {
+                return Err(Error::from(format!(
+                    "IbcLink::setup multiple channels use the port \"{}\" on {a_chain}",
+                    spec.a_port
+                )))
+            }
+            a_ports.push(&spec.a_port);
+        }
+
+        let (a_client, b_client) = create_client_pair(a_chain, b_chain).await.stack()?;
+        let (a_connection, b_connection) = create_connection_pair(a_chain, &a_client, &b_client)
+            .await
+            .stack()?;
+        let mut link = Self {
+            a_chain: a_chain.to_owned(),
+            b_chain: b_chain.to_owned(),
+            a_client,
+            b_client,
+            a_connection,
+            b_connection,
+            channels: BTreeMap::new(),
+        };
+        for spec in channels {
+            let (a_channel, b_channel) = create_channel_pair_with_version(
+                a_chain,
+                &link.a_connection,
+                &spec.a_port,
+                &spec.b_port,
+                spec.ordered,
+                spec.version.as_deref(),
+            )
+            .await
+            .stack_err(|| format!("IbcLink::setup creating channel {spec:?}"))?;
+            link.channels.insert(spec.a_port.clone(), LinkChannel {
+                spec: spec.clone(),
+                a_channel,
+                b_channel,
+            });
+        }
+        info!("{a_chain} <-> {b_chain} IBC link has been set up: {link:?}");
+        Ok(link)
+    }
+
+    /// Returns the channel with the port `a_port` on the `a_chain` side
+    pub fn channel(&self, a_port: &str) -> Result<&LinkChannel> {
+        self.channels
+            .get(a_port)
+            .stack_err(|| format!("IbcLink has no channel with the port \"{a_port}\""))
+    }
+
+    /// The [IbcPair] of the "transfer" channel, for using the transfer
+    /// helpers of [IbcSide]
+    pub fn transfer_pair(&self) -> Result<IbcPair> {
+        let transfer = self.channel("transfer").stack()?;
+        Ok(IbcPair {
+            a: IbcSide {
+                chain_id: self.a_chain.clone(),
+                connection: self.a_connection.clone(),
+                transfer_channel: transfer.a_channel.clone(),
+                ics_channel: None,
+            },
+            b: IbcSide {
+                chain_id: self.b_chain.clone(),
+                connection: self.b_connection.clone(),
+                transfer_channel: transfer.b_channel.clone(),
+                ics_channel: None,
             },
         })
     }
 }
+
+#[tokio::test]
+async fn test_ibc_link_setup() {
+    use serde_json::json;
+
+    use crate::executor::with_hermes_replay;
+
+    let mut responses = vec![];
+    for (host, reference) in [("onomy", "market"), ("market", "onomy")] {
+        responses.push((
+            format!(
+                "hermes --json create client --host-chain {host} --reference-chain {reference}"
+            ),
+            json!({"CreateClient": {"client_id": "07-tendermint-2"}}),
+        ));
+    }
+    let sides = |key: &str, a: &str, b: &str| json!({"a_side": {key: a}, "b_side": {key: b}});
+    responses.push((
+        "hermes --json create connection --a-chain onomy --a-client 07-tendermint-2 --b-client \
+         07-tendermint-2"
+            .to_owned(),
+        sides("connection_id", "connection-1", "connection-0"),
+    ));
+    responses.push((
+        "hermes --json create channel --a-chain onomy --a-connection connection-1 --a-port \
+         transfer --b-port transfer"
+            .to_owned(),
+        sides("channel_id", "channel-3", "channel-0"),
+    ));
+    responses.push((
+        "hermes --json create channel --a-chain onomy --a-connection connection-1 --a-port custom \
+         --b-port other --order ordered --channel-version custom-1"
+            .to_owned(),
+        sides("channel_id", "channel-4", "channel-1"),
+    ));
+
+    let channels = [
+        ChannelSpec::transfer(),
+        ChannelSpec::new("custom", "other")
+            .ordered()
+            .version("custom-1"),
+    ];
+    let link = with_hermes_replay(responses, IbcLink::setup("onomy", "market", &channels))
+        .await
+        .unwrap();
+    assert_eq!(link.a_connection, "connection-1");
+    assert_eq!(link.channel("custom").unwrap().b_channel, "channel-1");
+    let pair = link.transfer_pair().unwrap();
+    assert_eq!(pair.a.transfer_channel, "channel-3");
+    assert_eq!(pair.b.transfer_channel, "channel-0");
+    assert!(pair.b.ics_channel.is_none());
+
+    let duplicate = [ChannelSpec::transfer(), ChannelSpec::transfer()];
+    assert!(IbcLink::setup("onomy", "market", &duplicate).await.is_err());
+}
@@ -1,11 +1,21 @@
+pub mod chain;
 pub mod cosmovisor;
+pub mod denom_trace;
 pub mod dockerfiles;
+pub mod executor;
+pub mod genesis;
+pub mod gov;
 pub mod hermes;
 mod hermes_config;
 pub mod ibc;
 pub mod market;
 mod misc;
+pub mod setup_msg;
 pub mod setups;
+pub mod topology;
+pub mod tx;
+pub mod upgrade;
+pub mod versions;
 pub use misc::*;
 /// Reexported to reduce dependency wrangling
 pub use super_orchestrator;
@@ -4,8 +4,9 @@ use super_orchestrator::stacked_errors::{Error, StackableErr};
 use u64_array_bigints::{u256, U256};
 
 use crate::{
-    cosmovisor::{cosmovisor_get_balances, sh_cosmovisor, sh_cosmovisor_tx},
+    cosmovisor::{cosmovisor_get_balances, CosmovisorCmd},
     super_orchestrator::stacked_errors::Result,
+    tx::{FeePolicy, TxResponse},
 };
 
 pub struct CoinPair {
@@ -69,120 +70,120 @@ impl CoinPair {
     }
 }
 
-// probably how this will be extended in the future, is that this is returned by
-// reference from a `market()` function from some more general struct that
-// handles fees and stuff
 pub struct Market {
     pub account: String,
-    pub fees: String,
-    pub max_gas: Option<U256>,
+    pub fee_policy: FeePolicy,
 }
 
 impl Market {
+    /// The event type with the UID of a new drop, including the initial drop of
+    /// `create-pool`
+    pub const EVENT_CREATE_DROP: &'static str = "create_drop";
+    /// The event type with the UID of a new order
+    pub const EVENT_CREATE_ORDER: &'static str = "create_order";
     /// Max absolute coin amounts supported by the market module currently
     pub const MAX_COIN: U256 = u256!(5192296858534827628530496329220095);
     pub const MAX_COIN_SQUARED: U256 =
         u256!(26959946667150639794667015087019620289043427352885315420110951809025);
 
-    pub fn new(account: &str, fees: &str) -> Self {
+    pub fn new(account: &str, fee_policy: FeePolicy) -> Self {
         Market {
             account: account.to_owned(),
-            fees: fees.to_owned(),
-            max_gas: None,
+            fee_policy,
         }
     }
 
-    /// Adds on "-y", "-b", "block", "--from", self.account, "--fees", self.fees
-    pub async fn configured_tx<I, S>(&self, program_with_args: I) -> Result<()>
+    /// Adds on "--from", self.account, the flags of `self.fee_policy`, and
+    /// uses the broadcasting of [CosmovisorCmd::tx]
+    pub async fn configured_tx<I, S>(&self, program_with_args: I) -> Result<TxResponse>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<str>,
     {
-        let mut args: Vec<String> = program_with_args
-            .into_iter()
-            .map(|s| s.as_ref().to_string())
-            .collect();
-        args.extend(
-            [
-                "-y",
-                "-b",
-                "block",
-                "--from",
-                &self.account,
-                "--fees",
-                &self.fees,
-            ]
-            .iter()
-            .map(|s| s.to_string()),
-        );
-        let max_gas = self.max_gas.map(|x| format!("{x}"));
-        if let Some(ref max_gas) = max_gas {
-            args.push("--gas".to_string());
-            args.push(max_gas.clone());
-        }
-        sh_cosmovisor_tx(args)
+        let mut program_with_args = program_with_args.into_iter();
+        let first = program_with_args
+            .next()
+            .stack_err(|| "`configured_tx` called with an empty iterator")?;
+        CosmovisorCmd::tx(first.as_ref())
+            .args(program_with_args)
+            .from(&self.account)
+            .fee_policy(&self.fee_policy)
+            .run_tx()
             .await
-            .stack_err(|| "market module transaction error")?;
-        Ok(())
+            .stack_err(|| "market module transaction error")
+    }
+
+    /// Returns the "uid" attribute of the first `event_type` event of `res`.
+    /// Transactions such as `create-pool` or a matched order emit several
+    /// events with a "uid", so the event type has to be specific.
+    pub fn uid_from_events(res: &TxResponse, event_type: &str) -> Result<u64> {
+        let uid = res.attribute(event_type, "uid").stack_err(|| {
+            format!("could not find a \"uid\" attribute in a {event_type} event of {res:?}")
+        })?;
+        uid.trim_matches('"').parse::<u64>().stack()
     }
 
-    /// Initiates the pool with the given amounts
+    /// Initiates the pool with the given amounts. Returns the UID of the
+    /// initial drop.
     pub async fn create_pool(
         &self,
         coin_pair: &CoinPair,
         coin_a_amount: U256,
         coin_b_amount: U256,
-    ) -> Result<()> {
-        self.configured_tx([
-            "market create-pool",
-            &coin_pair.coin_a_amount(coin_a_amount),
-            &coin_pair.coin_b_amount(coin_b_amount),
-        ])
-        .await
-        .stack()?;
-        Ok(())
+    ) -> Result<u64> {
+        let res = self
+            .configured_tx([
+                "market create-pool",
+                &coin_pair.coin_a_amount(coin_a_amount),
+                &coin_pair.coin_b_amount(coin_b_amount),
+            ])
+            .await
+            .stack()?;
+        Self::uid_from_events(&res, Self::EVENT_CREATE_DROP).stack()
     }
 
     pub async fn show_pool(&self, coin_pair: &CoinPair) -> Result<String> {
-        sh_cosmovisor(["query market pool", &coin_pair.paired()])
+        CosmovisorCmd::query("market pool")
+            .arg(coin_pair.paired())
+            .debug(true)
+            .run()
             .await
             .stack()
     }
 
     pub async fn show_members(&self, coin_pair: &CoinPair) -> Result<(String, String)> {
-        let member_a = sh_cosmovisor([
-            "query market show-member",
-            coin_pair.coin_a(),
-            coin_pair.coin_b(),
-        ])
-        .await
-        .stack()?;
-        let member_b = sh_cosmovisor([
-            "query market show-member",
-            coin_pair.coin_b(),
-            coin_pair.coin_a(),
-        ])
-        .await
-        .stack()?;
+        let member_a = CosmovisorCmd::query("market show-member")
+            .args([coin_pair.coin_a(), coin_pair.coin_b()])
+            .debug(true)
+            .run()
+            .await
+            .stack()?;
+        let member_b = CosmovisorCmd::query("market show-member")
+            .args([coin_pair.coin_b(), coin_pair.coin_a()])
+            .debug(true)
+            .run()
+            .await
+            .stack()?;
         Ok((member_a, member_b))
     }
 
-    pub async fn create_drop(&self, coin_pair: &CoinPair, drops: U256) -> Result<()> {
-        self.configured_tx([
-            "market create-drop",
-            &coin_pair.paired(),
-            &format!("{}", drops),
-        ])
-        .await
-        .stack()?;
-        Ok(())
+    /// Returns the UID of the new drop
+    pub async fn create_drop(&self, coin_pair: &CoinPair, drops: U256) -> Result<u64> {
+        let res = self
+            .configured_tx([
+                "market create-drop",
+                &coin_pair.paired(),
+                &format!("{}", drops),
+            ])
+            .await
+            .stack()?;
+        Self::uid_from_events(&res, Self::EVENT_CREATE_DROP).stack()
     }
 
-    pub async fn redeem_drop(&self, uid: u64) -> Result<()> {
+    pub async fn redeem_drop(&self, uid: u64) -> Result<TxResponse> {
         self.configured_tx(["market redeem-drop", &format!("{}", uid)])
             .await
-            .stack()?;
-        Ok(())
+            .stack()
     }
 
     pub async fn market_order(
@@ -192,7 +193,7 @@ impl Market {
         coin_bid: &str,
         amount_bid: U256,
         slippage: u16,
-    ) -> Result<()> {
+    ) -> Result<TxResponse> {
         self.configured_tx([
             "market market-order",
             coin_ask,
@@ -202,10 +203,10 @@ impl Market {
             &format!("{}", slippage),
         ])
         .await
-        .stack()?;
-        Ok(())
+        .stack()
     }
 
+    /// Returns the UID of the new order
     pub async fn create_order(
         &self,
         coin_ask: &str,
@@ -214,26 +215,74 @@ impl Market {
         amount: U256,
         rate: (u64, u64),
         prev_next: (u64, u64),
-    ) -> Result<()> {
-        self.configured_tx([
-            "market create-order",
-            coin_ask,
-            coin_bid,
-            order_type,
-            &format!("{}", amount),
-            &format!("{},{}", rate.0, rate.1),
-            &format!("{}", prev_next.0),
-            &format!("{}", prev_next.1),
-        ])
-        .await
-        .stack()?;
-        Ok(())
+    ) -> Result<u64> {
+        let res = self
+            .configured_tx([
+                "market create-order",
+                coin_ask,
+                coin_bid,
+                order_type,
+                &format!("{}", amount),
+                &format!("{},{}", rate.0, rate.1),
+                &format!("{}", prev_next.0),
+                &format!("{}", prev_next.1),
+            ])
+            .await
+            .stack()?;
+        Self::uid_from_events(&res, Self::EVENT_CREATE_ORDER).stack()
     }
 
-    pub async fn cancel_order(&self, uid: u64) -> Result<()> {
+    pub async fn cancel_order(&self, uid: u64) -> Result<TxResponse> {
         self.configured_tx(["market cancel-order", &format!("{}", uid)])
             .await
-            .stack()?;
-        Ok(())
+            .stack()
     }
 }
+
+#[test]
+fn test_uid_from_events() {
+    use crate::yaml_str_to_json_value;
+
+    let create_pool = yaml_str_to_json_value(
+        r#"code: 0
+codespace: ""
+gas_used: "215530"
+gas_wanted: "300000"
+height: "31"
+logs:
+- events:
+  - attributes:
+    - key: action
+      value: create_pool
+    - key: module
+      value: market
+    type: message
+  - attributes:
+    - key: uid
+      value: "3"
+    - key: pair
+      value: afootoken,anative
+    type: create_pool
+  - attributes:
+    - key: uid
+      value: "7"
+    - key: pair
+      value: afootoken,anative
+    - key: drops
+      value: "1000"
+    type: create_drop
+  log: ""
+  msg_index: 0
+raw_log: '[]'
+txhash: 0F1E2D3C4B5A69788796A5B4C3D2E1F00F1E2D3C4B5A69788796A5B4C3D2E1F0
+"#,
+    )
+    .unwrap();
+    let res = TxResponse::from_value(create_pool).unwrap();
+    assert_eq!(
+        Market::uid_from_events(&res, Market::EVENT_CREATE_DROP).unwrap(),
+        7
+    );
+    assert_eq!(Market::uid_from_events(&res, "create_pool").unwrap(), 3);
+    assert!(Market::uid_from_events(&res, Market::EVENT_CREATE_ORDER).is_err());
+}
@@ -9,6 +9,8 @@ use super_orchestrator::{
 };
 use u64_array_bigints::{u256, U256};
 
+use crate::versions::init_versions;
+
 pub const TIMEOUT: Duration = Duration::from_secs(1000);
 pub const STD_DELAY: Duration = Duration::from_millis(300);
 pub const STD_TRIES: u64 = 300;
@@ -48,7 +50,11 @@ pub fn arc_test_denoms() -> Value {
     ])
 }
 
-/// IBC NOM denom for our Consumers
+/// IBC NOM denom for our Consumers, which is the denom of the trace
+/// "transfer/channel-1/anom". This is only kept to check
+/// [DenomTrace](crate::denom_trace::DenomTrace) against, use
+/// [IbcSide::ibc_denom_trace](crate::ibc::IbcSide::ibc_denom_trace) to get the
+/// denom of an actual channel.
 pub const ONOMY_IBC_NOM: &str =
     "ibc/5872224386C093865E42B18BDDA56BCB8CDE1E36B82B391E97697520053B0513";
 
@@ -103,10 +109,15 @@ pub struct Args {
     pub grpc: Option<String>,
     #[arg(long)]
     pub consumer_id: Option<String>,
+    /// The path of a `versions.toml` to use instead of the default one, see
+    /// [crate::versions]
+    #[arg(long, env = "ONOMY_VERSIONS")]
+    pub versions: Option<String>,
 }
 
 /// Calls [super_orchestrator::std_init] and returns the result of
-/// [crate::Args::parse]
+/// [crate::Args::parse], after calling [init_versions] if `--versions` was
+/// passed
 pub fn onomy_std_init() -> Result<Args> {
     std_init().stack_err(|| "onomy_std_init")?;
     let mut args = Args::parse();
@@ -117,6 +128,9 @@ pub fn onomy_std_init() -> Result<Args> {
         .last()
         .stack()?
         .to_owned();
+    if let Some(ref path) = args.versions {
+        init_versions(path).stack()?;
+    }
     Ok(args)
 }
 
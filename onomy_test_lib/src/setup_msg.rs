@@ -0,0 +1,229 @@
+//! The messages that test runners exchange while setting up and tearing down a
+//! test network
+//!
+//! Plain `NetMessenger::send`/`recv` sequences deadlock or fail to deserialize
+//! with no indication of where the two sides diverged when one side changes
+//! its order. A [SetupMessenger] sends and receives [SetupMsg]s, logs every
+//! step with the name of the peer, and [SetupMessenger::expect] reports which
+//! message arrived when it was not the expected one.
+//!
+//! ```text
+//! // onomyd side
+//! nm_hermes.send(HermesMnemonic(mnemonic)).await.stack()?;
+//! // hermes side
+//! let HermesMnemonic(mnemonic) = nm_onomyd.expect().await.stack()?;
+//! ```
+
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use super_orchestrator::{
+    net_message::NetMessenger,
+    stacked_errors::{Error, Result, StackableErr},
+};
+
+use crate::{ibc::IbcPair, STD_DELAY, STD_TRIES, TIMEOUT};
+
+/// The mnemonic of the account that hermes uses on every chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HermesMnemonic(pub String);
+
+/// The "ccvconsumer" state that the provider generated for the consumer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsumerGenesis(pub String);
+
+/// The contents of "node_key.json" and "priv_validator_key.json" of the
+/// provider validator, which the consumer must reuse to be a working validator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorKeys {
+    pub node_key: String,
+    pub priv_validator_key: String,
+}
+
+/// Sent once the relayer has connected the chains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainsConnected(pub IbcPair);
+
+/// The new gas denom (e.x. the IBC NOM on a consumer) that the relayer should
+/// restart with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GasDenomUpdate(pub String);
+
+/// Asks the relayer to wait until the transfer channel of the IBC pair has no
+/// pending packets and to check that the `timed_out` transfers sent from
+/// `chain_id` timed out, see `IbcPair::hermes_wait_for_transfers`. The relayer
+/// answers with [Ready].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WaitForTransfers {
+    pub chain_id: String,
+    pub timed_out: Vec<u64>,
+}
+
+impl WaitForTransfers {
+    pub fn new(chain_id: &str) -> Self {
+        Self {
+            chain_id: chain_id.to_owned(),
+            timed_out: vec![],
+        }
+    }
+
+    pub fn timed_out(mut self, sequence: u64) -> Self {
+        self.timed_out.push(sequence);
+        self
+    }
+}
+
+/// Signals that the sender has finished its current phase, e.x. that its node
+/// is online or that it has restarted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ready;
+
+/// Signals that the test is done and that the receiver should shut down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Terminate;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SetupMsg {
+    HermesMnemonic(HermesMnemonic),
+    ConsumerGenesis(ConsumerGenesis),
+    ValidatorKeys(ValidatorKeys),
+    ChainsConnected(ChainsConnected),
+    GasDenomUpdate(GasDenomUpdate),
+    WaitForTransfers(WaitForTransfers),
+    Ready(Ready),
+    Terminate(Terminate),
+}
+
+/// Implemented for the payload of every [SetupMsg] variant
+pub trait SetupMsgVariant: Into<SetupMsg> + Sized {
+    /// The name of the variant
+    const NAME: &'static str;
+
+    /// Returns the payload if `msg` is of this variant, else returns `msg`
+    /// back
+    fn from_msg(msg: SetupMsg) -> std::result::Result<Self, Box<SetupMsg>>;
+}
+
+macro_rules! impl_setup_msg_variant {
+    ($($variant:ident),*) => {
+        impl SetupMsg {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(SetupMsg::$variant(_) => <$variant as SetupMsgVariant>::NAME,)*
+                }
+            }
+        }
+
+        $(
+            impl From<$variant> for SetupMsg {
+                fn from(payload: $variant) -> Self {
+                    SetupMsg::$variant(payload)
+                }
+            }
+
+            impl SetupMsgVariant for $variant {
+                const NAME: &'static str = stringify!($variant);
+
+                fn from_msg(msg: SetupMsg) -> std::result::Result<Self, Box<SetupMsg>> {
+                    match msg {
+                        SetupMsg::$variant(payload) => Ok(payload),
+                        msg => Err(Box::new(msg)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_setup_msg_variant!(
+    HermesMnemonic,
+    ConsumerGenesis,
+    ValidatorKeys,
+    ChainsConnected,
+    GasDenomUpdate,
+    WaitForTransfers,
+    Ready,
+    Terminate
+);
+
+/// A `NetMessenger` that only exchanges [SetupMsg]s with the peer `peer_name`
+pub struct SetupMessenger {
+    pub peer_name: String,
+    pub nm: NetMessenger,
+}
+
+impl SetupMessenger {
+    pub fn new(peer_name: &str, nm: NetMessenger) -> Self {
+        Self {
+            peer_name: peer_name.to_owned(),
+            nm,
+        }
+    }
+
+    /// Connects to `host` with the standard tries and delay
+    pub async fn connect(peer_name: &str, host: &str) -> Result<Self> {
+        let nm = NetMessenger::connect(STD_TRIES, STD_DELAY, host)
+            .await
+            .stack_err(|| format!("SetupMessenger::connect to {peer_name} at {host}"))?;
+        Ok(Self::new(peer_name, nm))
+    }
+
+    /// Listens on `host` for a connection from `peer_name`
+    pub async fn listen(peer_name: &str, host: &str) -> Result<Self> {
+        let nm = NetMessenger::listen(host, TIMEOUT)
+            .await
+            .stack_err(|| format!("SetupMessenger::listen for {peer_name} on {host}"))?;
+        Ok(Self::new(peer_name, nm))
+    }
+
+    pub async fn send<M: Into<SetupMsg>>(&mut self, msg: M) -> Result<()> {
+        let msg = msg.into();
+        info!("sending SetupMsg::{} to {}", msg.name(), self.peer_name);
+        self.nm
+            .send::<SetupMsg>(&msg)
+            .await
+            .stack_err(|| format!("sending SetupMsg::{} to {}", msg.name(), self.peer_name))
+    }
+
+    pub async fn recv(&mut self) -> Result<SetupMsg> {
+        let msg = self
+            .nm
+            .recv::<SetupMsg>()
+            .await
+            .stack_err(|| format!("receiving a SetupMsg from {}", self.peer_name))?;
+        info!("received SetupMsg::{} from {}", msg.name(), self.peer_name);
+        Ok(msg)
+    }
+
+    /// Receives the next message and returns its payload, returning an error
+    /// naming the message that arrived if it is not of variant `M`
+    pub async fn expect<M: SetupMsgVariant>(&mut self) -> Result<M> {
+        info!("waiting for SetupMsg::{} from {}", M::NAME, self.peer_name);
+        let msg = self.recv().await.stack()?;
+        M::from_msg(msg).map_err(|msg| {
+            Error::from(format!(
+                "expected SetupMsg::{} from {}, but received SetupMsg::{}",
+                M::NAME,
+                self.peer_name,
+                msg.name()
+            ))
+        })
+    }
+}
+
+#[test]
+fn test_setup_msg_variant() {
+    let msg: SetupMsg = GasDenomUpdate("ibc/NOM".to_owned()).into();
+    assert_eq!(msg.name(), "GasDenomUpdate");
+    assert_eq!(
+        GasDenomUpdate::from_msg(msg.clone()).unwrap(),
+        GasDenomUpdate("ibc/NOM".to_owned())
+    );
+    assert_eq!(
+        Terminate::from_msg(msg).unwrap_err().name(),
+        "GasDenomUpdate"
+    );
+    assert_eq!(SetupMsg::from(Ready).name(), "Ready");
+    let wait = WaitForTransfers::new("onomy").timed_out(3);
+    assert_eq!(wait.timed_out, [3]);
+    assert_eq!(SetupMsg::from(wait).name(), "WaitForTransfers");
+}
@@ -1,16 +1,21 @@
-use serde_json::{json, Value};
+use serde_json::Value;
 use super_orchestrator::{
-    stacked_errors::{Result, StackableErr},
+    net_message::NetMessenger,
+    stacked_errors::{Error, Result, StackableErr},
     stacked_get, stacked_get_mut, Command, FileOptions,
 };
 use tokio::time::sleep;
 
 use crate::{
     cosmovisor::{
-        cosmovisor_gov_file_proposal, fast_block_times, force_chain_id, set_minimum_gas_price,
-        sh_cosmovisor, sh_cosmovisor_no_debug, sh_cosmovisor_tx, wait_for_num_blocks,
+        cosmovisor_get_addr, cosmovisor_gov_file_proposal, fast_block_times, force_chain_id,
+        get_self_peer_info, set_minimum_gas_price, set_persistent_peers, sh_cosmovisor,
+        sh_cosmovisor_no_debug, wait_for_num_blocks, CosmovisorCmd,
     },
-    nom_denom, token18, TEST_AMOUNT, TIMEOUT,
+    genesis::GenesisBuilder,
+    nom_denom, token18,
+    tx::FeePolicy,
+    STD_DELAY, STD_TRIES, TEST_AMOUNT, TIMEOUT,
 };
 
 // make sure some things are imported so we don't have to wrangle with this for
@@ -87,15 +92,22 @@ pub struct CosmosSetupResults {
     pub hermes_mnemonic: Option<String>,
 }
 
-/// NOTE: this is stuff you would not want to run in production.
-/// NOTE: this is intended to be run inside containers only
-///
-/// This additionally returns the single validator mnemonic
-pub async fn cosmovisor_setup(options: CosmosSetupOptions) -> Result<CosmosSetupResults> {
-    let daemon_home = &options.daemon_home;
-    let chain_id = &options.chain_id;
-    let gov_token = &options.gov_token;
-    let gas_token = &options.gas_token;
+/// The port that validators listen on for the coordinator of
+/// [cosmovisor_multi_validator_setup]
+pub const MULTI_VALIDATOR_SETUP_PORT: u16 = 26010;
+
+/// The role of a node in [cosmovisor_multi_validator_setup]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidatorRole {
+    /// Builds the genesis and collects the gentxs of the validators with these
+    /// hostnames
+    Coordinator { validator_hostnames: Vec<String> },
+    /// Sends a gentx to the coordinator
+    Validator,
+}
+
+/// `config` and `init` the node
+async fn cosmovisor_init(chain_id: &str) -> Result<()> {
     sh_cosmovisor(["config chain-id", chain_id]).await.stack()?;
     sh_cosmovisor(["config keyring-backend test"])
         .await
@@ -103,120 +115,97 @@ pub async fn cosmovisor_setup(options: CosmosSetupOptions) -> Result<CosmosSetup
     sh_cosmovisor_no_debug(["init --overwrite", chain_id])
         .await
         .stack()?;
+    Ok(())
+}
+
+/// Modifies the genesis created by `init`
+async fn setup_genesis(options: &CosmosSetupOptions) -> Result<()> {
+    let daemon_home = &options.daemon_home;
+    let chain_id = &options.chain_id;
+    let gov_token = &options.gov_token;
 
     let genesis_file_path = format!("{daemon_home}/config/genesis.json");
-    let genesis_s = FileOptions::read_to_string(&genesis_file_path)
+    let mut genesis = GenesisBuilder::from_file(&genesis_file_path)
         .await
         .stack()?;
 
     // rename all "stake" to "anom"
-    let genesis_s = genesis_s.replace("\"stake\"", &format!("\"{gov_token}\""));
-    let mut genesis: Value = serde_json::from_str(&genesis_s).stack()?;
+    genesis.rename_denom("stake", gov_token);
 
-    force_chain_id(daemon_home, &mut genesis, chain_id)
+    force_chain_id(daemon_home, &mut genesis.genesis, chain_id)
         .await
         .stack()?;
 
     // for consumer chains
     if let Some(ref ccvconsumer_state_s) = options.ccvconsumer_state {
-        let ccvconsumer_state: Value = serde_json::from_str(ccvconsumer_state_s).stack()?;
-        *stacked_get_mut!(genesis["app_state"]["ccvconsumer"]) = ccvconsumer_state;
+        genesis.ccvconsumer_state(ccvconsumer_state_s).stack()?;
     }
 
     // put in the test `footoken` and the staking `anom`
-    let denom_metadata = nom_denom();
-    *stacked_get_mut!(genesis["app_state"]["bank"]["denom_metadata"]) = denom_metadata;
+    genesis.denom_metadata(nom_denom()).stack()?;
 
     if options.onomy_special {
         // init DAO balance
-        let amount = token18(100.0e6, "");
-        let treasury_balance = json!([{"denom": "anom", "amount": amount}]);
-        *stacked_get_mut!(genesis["app_state"]["dao"]["treasury_balance"]) = treasury_balance;
+        genesis
+            .dao_treasury_balance(&token18(100.0e6, ""), "anom")
+            .stack()?;
     }
 
     // disable community_tax
-    *stacked_get_mut!(genesis["app_state"]["distribution"]["params"]["community_tax"]) = json!("0");
+    genesis.community_tax("0").stack()?;
 
-    let global_min_self_delegation = &token18(225.0e3, "");
     if options.onomy_special {
-        // min_global_self_delegation
-        *stacked_get_mut!(
-            genesis["app_state"]["staking"]["params"]["min_global_self_delegation"]
-        ) = global_min_self_delegation.to_owned().into();
+        genesis
+            .min_global_self_delegation(&global_min_self_delegation())
+            .stack()?;
     }
 
     // decrease the governing period for fast tests
     let gov_period = "800ms";
-    let gov_period: Value = gov_period.into();
-    *stacked_get_mut!(genesis["app_state"]["gov"]["voting_params"]["voting_period"]) =
-        gov_period.clone();
-    *stacked_get_mut!(genesis["app_state"]["gov"]["deposit_params"]["max_deposit_period"]) =
-        gov_period;
+    genesis
+        .gov_voting_period(gov_period)
+        .stack()?
+        .gov_max_deposit_period(gov_period)
+        .stack()?;
 
     // Set governance token
-    *stacked_get_mut!(genesis["app_state"]["gov"]["deposit_params"]["min_deposit"][0]["amount"]) =
-        token18(500.0, "").into();
-    *stacked_get_mut!(genesis["app_state"]["gov"]["deposit_params"]["min_deposit"][0]["denom"]) =
-        options.gov_token.as_str().into();
-    *stacked_get_mut!(genesis["app_state"]["staking"]["params"]["bond_denom"]) =
-        options.gov_token.as_str().into();
+    genesis
+        .gov_min_deposit(&token18(500.0, ""), gov_token)
+        .stack()?
+        .bond_denom(gov_token)
+        .stack()?;
 
     // write back genesis
-    let genesis_s = serde_json::to_string(&genesis).stack()?;
-    FileOptions::write_str(&genesis_file_path, &genesis_s)
-        .await
-        .stack()?;
+    genesis.write_to_file(&genesis_file_path).await.stack()?;
+    Ok(())
+}
 
+/// The config changes that every node of the chain needs
+async fn setup_node_config(options: &CosmosSetupOptions) -> Result<()> {
+    let daemon_home = &options.daemon_home;
     fast_block_times(daemon_home).await.stack()?;
-
-    set_minimum_gas_price(daemon_home, &format!("1{gas_token}"))
+    set_minimum_gas_price(daemon_home, &format!("1{}", options.gas_token))
         .await
         .stack()?;
+    Ok(())
+}
 
-    let validator_mnemonic = if let Some(ref mnemonic) = options.validator_mnemonic {
+/// Adds the key `key_name`, recovering it from `mnemonic` if it is set.
+/// Returns the mnemonic.
+async fn add_key(daemon_home: &str, key_name: &str, mnemonic: Option<&str>) -> Result<String> {
+    if let Some(mnemonic) = mnemonic {
         Command::new(format!(
-            "{daemon_home}/cosmovisor/current/bin/onomyd keys add validator --recover"
+            "{daemon_home}/cosmovisor/current/bin/onomyd keys add {key_name} --recover"
         ))
         .run_with_input_to_completion(mnemonic.as_bytes())
         .await
         .stack()?
         .assert_success()
         .stack()?;
-        Some(mnemonic.to_owned())
-    } else if options.ccvconsumer_state.as_deref().is_none() {
-        // we need the stderr to get the mnemonic
-        let comres = Command::new("cosmovisor run keys add validator")
-            .run_to_completion()
-            .await
-            .stack()?;
-        comres.assert_success().stack()?;
-        let mnemonic = comres
-            .stderr_as_utf8()
-            .stack()?
-            .trim()
-            .lines()
-            .last()
-            .stack_err(|| "no last line")?
-            .trim()
-            .to_owned();
-        Some(mnemonic)
+        Ok(mnemonic.to_owned())
     } else {
-        None
-    };
-
-    let hermes_mnemonic = if let Some(ref mnemonic) = options.hermes_mnemonic {
-        Command::new(format!(
-            "{daemon_home}/cosmovisor/current/bin/onomyd keys add hermes --recover"
-        ))
-        .run_with_input_to_completion(mnemonic.as_bytes())
-        .await
-        .stack()?
-        .assert_success()
-        .stack()?;
-        Some(mnemonic.to_owned())
-    } else if options.ccvconsumer_state.as_deref().is_none() {
         // we need the stderr to get the mnemonic
-        let comres = Command::new("cosmovisor run keys add hermes")
+        let comres = Command::new(format!("cosmovisor run keys add {key_name}"))
             .run_to_completion()
             .await
             .stack()?;
@@ -230,12 +219,18 @@ pub async fn cosmovisor_setup(options: CosmosSetupOptions) -> Result<CosmosSetup
             .stack_err(|| "no last line")?
             .trim()
             .to_owned();
-        Some(mnemonic)
-    } else {
-        None
-    };
+        Ok(mnemonic)
+    }
+}
 
-    let amount = if options.onex_testnet_amounts {
+fn global_min_self_delegation() -> String {
+    token18(225.0e3, "")
+}
+
+/// The genesis balance of each validator account
+fn validator_amount(options: &CosmosSetupOptions) -> String {
+    let gov_token = &options.gov_token;
+    if options.onex_testnet_amounts {
         "15000000000000000000000000abtc,100000000000000000000000000anom,\
          20000000000000000000000000000ausdc,20000000000000000000000000000ausdt,\
          20000000000000000000000000wei"
@@ -244,50 +239,98 @@ pub async fn cosmovisor_setup(options: CosmosSetupOptions) -> Result<CosmosSetup
         format!("{TEST_AMOUNT}{gov_token},{TEST_AMOUNT}afootoken")
     } else {
         format!("2000000000000000000000000{gov_token},2000000000000000000000000afootoken")
-    };
-
-    sh_cosmovisor(["add-genesis-account validator", &amount])
-        .await
-        .stack()?;
-    sh_cosmovisor([
-        "add-genesis-account hermes",
-        &format!("100000000000000000000{gas_token}"),
-    ])
-    .await
-    .stack()?;
+    }
+}
 
+/// Runs `gentx` for the "validator" key
+async fn validator_gentx(
+    options: &CosmosSetupOptions,
+    output_document: Option<&str>,
+) -> Result<()> {
     let self_delegate = if options.high_staking_level {
         /*sh_cosmovisor("keys add orchestrator", &[]).await.stack()?;
         sh_cosmovisor("add-genesis-account orchestrator", &[&nom(2.0e6)])
             .await
             .stack()?;*/
-        token18(1.99e6, gov_token)
+        token18(1.99e6, &options.gov_token)
     } else {
-        token18(1.0e6, gov_token)
+        token18(1.0e6, &options.gov_token)
     };
-
-    if options.ccvconsumer_state.is_none() {
-        sh_cosmovisor([
-            "gentx validator",
-            &self_delegate,
-            "--chain-id",
-            chain_id,
-            "--min-self-delegation",
-            global_min_self_delegation,
-        ])
-        .await
-        .stack()?;
-        sh_cosmovisor_no_debug(["collect-gentxs"]).await.stack()?;
+    let mut cmd = CosmovisorCmd::new("gentx validator")
+        .arg(&self_delegate)
+        .chain_id(&options.chain_id)
+        .args(["--min-self-delegation", &global_min_self_delegation()])
+        .debug(true);
+    if let Some(output_document) = output_document {
+        cmd = cmd.args(["--output-document", output_document]);
     }
+    cmd.run().await.stack()?;
+    Ok(())
+}
 
+async fn write_genesis_log(options: &CosmosSetupOptions) -> Result<()> {
     FileOptions::write_str(
-        format!("/logs/genesis_{chain_id}.json"),
-        &FileOptions::read_to_string(&genesis_file_path)
+        format!("/logs/genesis_{}.json", options.chain_id),
+        &FileOptions::read_to_string(&format!("{}/config/genesis.json", options.daemon_home))
             .await
             .stack()?,
     )
     .await
     .stack()?;
+    Ok(())
+}
+
+/// NOTE: this is stuff you would not want to run in production.
+/// NOTE: this is intended to be run inside containers only
+///
+/// This additionally returns the single validator mnemonic
+pub async fn cosmovisor_setup(options: CosmosSetupOptions) -> Result<CosmosSetupResults> {
+    let daemon_home = &options.daemon_home;
+    let gas_token = &options.gas_token;
+    cosmovisor_init(&options.chain_id).await.stack()?;
+    setup_genesis(&options).await.stack()?;
+    setup_node_config(&options).await.stack()?;
+
+    let is_consumer = options.ccvconsumer_state.is_some();
+    let validator_mnemonic = if options.validator_mnemonic.is_some() || !is_consumer {
+        Some(
+            add_key(
+                daemon_home,
+                "validator",
+                options.validator_mnemonic.as_deref(),
+            )
+            .await
+            .stack()?,
+        )
+    } else {
+        None
+    };
+    let hermes_mnemonic = if options.hermes_mnemonic.is_some() || !is_consumer {
+        Some(
+            add_key(daemon_home, "hermes", options.hermes_mnemonic.as_deref())
+                .await
+                .stack()?,
+        )
+    } else {
+        None
+    };
+
+    sh_cosmovisor(["add-genesis-account validator", &validator_amount(&options)])
+        .await
+        .stack()?;
+    sh_cosmovisor([
+        "add-genesis-account hermes",
+        &format!("100000000000000000000{gas_token}"),
+    ])
+    .await
+    .stack()?;
+
+    if !is_consumer {
+        validator_gentx(&options, None).await.stack()?;
+        sh_cosmovisor_no_debug(["collect-gentxs"]).await.stack()?;
+    }
+
+    write_genesis_log(&options).await.stack()?;
 
     Ok(CosmosSetupResults {
         validator_mnemonic,
@@ -295,6 +338,152 @@ pub async fn cosmovisor_setup(options: CosmosSetupOptions) -> Result<CosmosSetup
     })
 }
 
+/// NOTE: this is intended to be run inside containers only
+///
+/// Sets up a chain with multiple validators. Every node calls this with the
+/// same `options` (except for the mnemonics), and `hostname_of_self` is the
+/// hostname of the node's container.
+///
+/// The `Coordinator` does everything [cosmovisor_setup] does and adds an
+/// account for each validator. Each `Validator` listens on
+/// [MULTI_VALIDATOR_SETUP_PORT] and creates a gentx for the genesis sent to
+/// it, then the coordinator collects all the gentxs and distributes the final
+/// genesis. The persistent peers of every node are set to all the other nodes.
+///
+/// The hermes key is only created on the coordinator. Consumer chains are not
+/// supported since they do not use gentxs.
+pub async fn cosmovisor_multi_validator_setup(
+    options: CosmosSetupOptions,
+    hostname_of_self: &str,
+    role: ValidatorRole,
+) -> Result<CosmosSetupResults> {
+    if options.ccvconsumer_state.is_some() {
+        return Err(Error::from(
+            "cosmovisor_multi_validator_setup does not support consumer chains",
+        ))
+    }
+    let daemon_home = &options.daemon_home;
+    let genesis_file_path = format!("{daemon_home}/config/genesis.json");
+    cosmovisor_init(&options.chain_id).await.stack()?;
+    setup_node_config(&options).await.stack()?;
+    let peer_info = get_self_peer_info(hostname_of_self, "26656")
+        .await
+        .stack()?;
+    let validator_mnemonic = add_key(
+        daemon_home,
+        "validator",
+        options.validator_mnemonic.as_deref(),
+    )
+    .await
+    .stack()?;
+
+    let hermes_mnemonic = match role {
+        ValidatorRole::Coordinator {
+            validator_hostnames,
+        } => {
+            setup_genesis(&options).await.stack()?;
+            let hermes_mnemonic =
+                add_key(daemon_home, "hermes", options.hermes_mnemonic.as_deref())
+                    .await
+                    .stack()?;
+            let amount = validator_amount(&options);
+            sh_cosmovisor(["add-genesis-account validator", &amount])
+                .await
+                .stack()?;
+            sh_cosmovisor([
+                "add-genesis-account hermes",
+                &format!("100000000000000000000{}", options.gas_token),
+            ])
+            .await
+            .stack()?;
+
+            let mut nms = vec![];
+            for hostname in &validator_hostnames {
+                let mut nm = NetMessenger::connect(
+                    STD_TRIES,
+                    STD_DELAY,
+                    &format!("{hostname}:{MULTI_VALIDATOR_SETUP_PORT}"),
+                )
+                .await
+                .stack_err(|| format!("could not connect to validator {hostname}"))?;
+                let addr = nm.recv::<String>().await.stack()?;
+                sh_cosmovisor(["add-genesis-account", &addr, &amount])
+                    .await
+                    .stack()?;
+                nms.push(nm);
+            }
+
+            // the validators need their accounts in the genesis for their gentxs
+            let genesis_s = FileOptions::read_to_string(&genesis_file_path)
+                .await
+                .stack()?;
+            for nm in &mut nms {
+                nm.send::<String>(&genesis_s).await.stack()?;
+            }
+
+            validator_gentx(&options, None).await.stack()?;
+            let mut peers = vec![peer_info];
+            for (hostname, nm) in validator_hostnames.iter().zip(nms.iter_mut()) {
+                let (gentx, peer_info) = nm.recv::<(String, String)>().await.stack()?;
+                FileOptions::write_str(
+                    &format!("{daemon_home}/config/gentx/gentx-{hostname}.json"),
+                    &gentx,
+                )
+                .await
+                .stack()?;
+                peers.push(peer_info);
+            }
+            sh_cosmovisor_no_debug(["collect-gentxs"]).await.stack()?;
+
+            let genesis_s = FileOptions::read_to_string(&genesis_file_path)
+                .await
+                .stack()?;
+            for nm in &mut nms {
+                nm.send::<(String, Vec<String>)>(&(genesis_s.clone(), peers.clone()))
+                    .await
+                    .stack()?;
+            }
+            set_persistent_peers(daemon_home, &peers[1..])
+                .await
+                .stack()?;
+            write_genesis_log(&options).await.stack()?;
+            Some(hermes_mnemonic)
+        }
+        ValidatorRole::Validator => {
+            let addr = cosmovisor_get_addr("validator").await.stack()?;
+            let mut nm =
+                NetMessenger::listen(&format!("0.0.0.0:{MULTI_VALIDATOR_SETUP_PORT}"), TIMEOUT)
+                    .await
+                    .stack()?;
+            nm.send::<String>(&addr).await.stack()?;
+
+            let genesis_s = nm.recv::<String>().await.stack()?;
+            FileOptions::write_str(&genesis_file_path, &genesis_s)
+                .await
+                .stack()?;
+            let gentx_path = format!("{daemon_home}/config/gentx/gentx-{hostname_of_self}.json");
+            validator_gentx(&options, Some(&gentx_path)).await.stack()?;
+            let gentx = FileOptions::read_to_string(&gentx_path).await.stack()?;
+            nm.send::<(String, String)>(&(gentx, peer_info.clone()))
+                .await
+                .stack()?;
+
+            let (genesis_s, peers) = nm.recv::<(String, Vec<String>)>().await.stack()?;
+            FileOptions::write_str(&genesis_file_path, &genesis_s)
+                .await
+                .stack()?;
+            let peers: Vec<String> = peers.into_iter().filter(|p| *p != peer_info).collect();
+            set_persistent_peers(daemon_home, &peers).await.stack()?;
+            None
+        }
+    };
+
+    Ok(CosmosSetupResults {
+        validator_mnemonic: Some(validator_mnemonic),
+        hermes_mnemonic,
+    })
+}
+
 pub fn test_proposal(consumer_id: &str, reward_denom: &str) -> String {
     format!(
         r#"{{
@@ -327,8 +516,12 @@ pub fn test_proposal(consumer_id: &str, reward_denom: &str) -> String {
 /// Note: `sh_cosmovisor_tx("provider register-consumer-reward-denom
 /// [IBC-denom]` may need to be run afterwards if it is intended to receive
 /// consumer rewards
+///
+/// `from_key` submits and votes on the proposal, and it should be the key of
+/// the local validator since its consensus key is assigned for the consumer
 pub async fn cosmovisor_add_consumer(
     daemon_home: &str,
+    from_key: &str,
     consumer_id: &str,
     proposal_s: &str,
 ) -> Result<String> {
@@ -337,38 +530,37 @@ pub async fn cosmovisor_add_consumer(
     let tendermint_key = sh_cosmovisor(["tendermint show-validator"]).await.stack()?;
     let tendermint_key = tendermint_key.trim();
 
-    cosmovisor_gov_file_proposal(daemon_home, Some("consumer-addition"), proposal_s, "1anom")
-        .await
-        .stack()?;
+    cosmovisor_gov_file_proposal(
+        daemon_home,
+        from_key,
+        Some("consumer-addition"),
+        proposal_s,
+        &FeePolicy::chain_default("anom"),
+        &FeePolicy::chain_default("anom").with_auto_gas(2.3),
+    )
+    .await
+    .stack()?;
     wait_for_num_blocks(1).await.stack()?;
 
     // do this before getting the consumer-genesis
-    sh_cosmovisor_tx([
-        "provider assign-consensus-key",
-        consumer_id,
-        tendermint_key,
+    CosmovisorCmd::tx("provider assign-consensus-key")
+        .args([consumer_id, tendermint_key])
         // TODO for unknown reasons, `onomyd` with nonzero gas fee breaks non `--fees` usage
-        //"--gas",
-        //"auto",
-        //"--gas-adjustment",
-        //"1.3",
-        "--fees",
-        "1000000anom",
-        "-y",
-        "-b",
-        "block",
-        "--from",
-        "validator",
-    ])
-    .await
-    .stack()?;
+        //.fee_policy(&FeePolicy::auto_gas(1.3, ...))
+        .fee_policy(&FeePolicy::fixed_fees("1000000anom"))
+        .from(from_key)
+        .run_tx()
+        .await
+        .stack()?;
 
     // It appears we do not have to wait any blocks
 
-    let ccvconsumer_state =
-        sh_cosmovisor_no_debug(["query provider consumer-genesis", consumer_id, "-o", "json"])
-            .await
-            .stack()?;
+    let ccvconsumer_state = CosmovisorCmd::query("provider consumer-genesis")
+        .arg(consumer_id)
+        .output_json()
+        .run()
+        .await
+        .stack()?;
 
     let mut state: Value = serde_json::from_str(&ccvconsumer_state).stack()?;
 
@@ -0,0 +1,368 @@
+//! Declarative descriptions of the container networks that tests run
+//!
+//! A [Topology] lists the nodes that run the test binary (chains, relayers,
+//! and anything else with an `--entry-name`), the auxiliary services that run
+//! their own images, and the IBC links between chains. [Topology::run] either
+//! dispatches to the entry of the current container or builds the test binary,
+//! writes the hermes config for the declared IBC links, and runs the
+//! `ContainerNetwork`.
+
+use std::{future::Future, time::Duration};
+
+use super_orchestrator::{
+    docker::{Container, ContainerNetwork, Dockerfile},
+    remove_files_in_dir, sh,
+    stacked_errors::{Error, Result, StackableErr},
+};
+
+use crate::{
+    chain::ChainSpec,
+    dockerfiles::{dockerfile_hermes, dockerfile_resources, validate_resources, DOCKERFILES_DIR},
+    hermes::{write_hermes_config, HermesChainConfig},
+    versions::versions,
+    Args, TIMEOUT,
+};
+
+/// The keyring directory that is volumed into the home of every chain node
+pub const KEYRING_DIR: &str = "./tests/resources/keyring-test";
+pub const LOGS_DIR: &str = "./tests/logs";
+pub const CONTAINER_TARGET: &str = "x86_64-unknown-linux-gnu";
+/// The name of the hermes config that [dockerfile_hermes] is given
+const HERMES_CONFIG_NAME: &str = "__tmp_hermes_config.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// Runs the chain, gets the keyring volume, and can be part of IBC links
+    Chain(Box<ChainSpec>),
+    /// Runs hermes with the config generated for the IBC links
+    Hermes,
+    /// Runs an arbitrary dockerfile
+    Other,
+}
+
+/// A container that runs the test binary with `--entry-name {entry_name}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopologyNode {
+    pub entry_name: String,
+    /// The hostname of the node is `{container_name}_{uuid}`
+    pub container_name: String,
+    /// If `None`, the dockerfile of the chain or hermes is used
+    pub dockerfile: Option<String>,
+    pub kind: NodeKind,
+    pub volumes: Vec<(String, String)>,
+    /// Passed to the entrypoint after `--entry-name {entry_name}`
+    pub entrypoint_args: Vec<String>,
+}
+
+impl TopologyNode {
+    /// A node named `entry_name` with an arbitrary dockerfile
+    pub fn new(entry_name: &str, dockerfile: &str) -> Self {
+        Self {
+            entry_name: entry_name.to_owned(),
+            container_name: entry_name.to_owned(),
+            dockerfile: Some(dockerfile.to_owned()),
+            kind: NodeKind::Other,
+            volumes: vec![],
+            entrypoint_args: vec![],
+        }
+    }
+
+    /// A node running `chain` with [ChainSpec::dockerfile], the container is
+    /// named after the daemon
+    pub fn chain(entry_name: &str, chain: ChainSpec) -> Self {
+        Self {
+            entry_name: entry_name.to_owned(),
+            container_name: chain.daemon_name.clone(),
+            dockerfile: None,
+            kind: NodeKind::Chain(Box::new(chain)),
+            volumes: vec![],
+            entrypoint_args: vec![],
+        }
+    }
+
+    /// A hermes node using [dockerfile_hermes]
+    pub fn hermes(entry_name: &str) -> Self {
+        Self {
+            entry_name: entry_name.to_owned(),
+            container_name: entry_name.to_owned(),
+            dockerfile: None,
+            kind: NodeKind::Hermes,
+            volumes: vec![],
+            entrypoint_args: vec![],
+        }
+    }
+
+    pub fn container_name(mut self, container_name: &str) -> Self {
+        self.container_name = container_name.to_owned();
+        self
+    }
+
+    /// Replaces the dockerfile, e.x. with one that includes upgrade binaries
+    pub fn dockerfile(mut self, dockerfile: &str) -> Self {
+        self.dockerfile = Some(dockerfile.to_owned());
+        self
+    }
+
+    pub fn volume(mut self, host_path: &str, container_path: &str) -> Self {
+        self.volumes
+            .push((host_path.to_owned(), container_path.to_owned()));
+        self
+    }
+
+    pub fn entrypoint_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.entrypoint_args
+            .extend(args.into_iter().map(|s| s.as_ref().to_owned()));
+        self
+    }
+
+    /// The hostname of the node in the network with `uuid`
+    pub fn hostname(&self, uuid: &str) -> String {
+        format!("{}_{uuid}", self.container_name)
+    }
+
+    fn chain_spec(&self) -> Option<&ChainSpec> {
+        match self.kind {
+            NodeKind::Chain(ref chain) => Some(chain),
+            _ => None,
+        }
+    }
+
+    fn get_dockerfile(&self) -> Result<String> {
+        if let Some(ref dockerfile) = self.dockerfile {
+            return Ok(dockerfile.clone())
+        }
+        match self.kind {
+            NodeKind::Chain(ref chain) => chain.dockerfile().stack(),
+            NodeKind::Hermes => dockerfile_hermes(HERMES_CONFIG_NAME).stack(),
+            NodeKind::Other => Err(Error::from(format!(
+                "TopologyNode \"{}\" has no dockerfile",
+                self.entry_name
+            ))),
+        }
+    }
+}
+
+/// A test network, see the module level docs
+pub struct Topology {
+    pub network_name: String,
+    pub nodes: Vec<TopologyNode>,
+    /// Containers with their own entrypoints, added without the common
+    /// volumes and entrypoint arguments
+    pub services: Vec<Container>,
+    /// Pairs of entry names of chain nodes that the relayer connects
+    pub ibc_links: Vec<(String, String)>,
+    /// Additional arguments for `cargo build` of the test binary
+    pub build_args: Vec<String>,
+    pub timeout: Duration,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self {
+            network_name: "test".to_owned(),
+            nodes: vec![],
+            services: vec![],
+            ibc_links: vec![],
+            build_args: vec![],
+            timeout: TIMEOUT,
+        }
+    }
+
+    pub fn node(mut self, node: TopologyNode) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    pub fn service(mut self, container: Container) -> Self {
+        self.services.push(container);
+        self
+    }
+
+    /// Declares an IBC link between the chain nodes with entry names `a` and
+    /// `b`, the chains of all links are put in the hermes config
+    pub fn ibc_link(mut self, a: &str, b: &str) -> Self {
+        self.ibc_links.push((a.to_owned(), b.to_owned()));
+        self
+    }
+
+    pub fn build_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.build_args
+            .extend(args.into_iter().map(|s| s.as_ref().to_owned()));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn get_node(&self, entry_name: &str) -> Result<&TopologyNode> {
+        self.nodes
+            .iter()
+            .find(|node| node.entry_name == entry_name)
+            .stack_err(|| format!("Topology has no node with entry name \"{entry_name}\""))
+    }
+
+    /// The hermes configs of the chains in the IBC links, in order of first
+    /// appearance
+    pub fn hermes_configs(&self, uuid: &str) -> Result<Vec<HermesChainConfig>> {
+        let mut entry_names: Vec<&str> = vec![];
+        for (a, b) in &self.ibc_links {
+            for entry_name in [a, b] {
+                if !entry_names.contains(&entry_name.as_str()) {
+                    entry_names.push(entry_name);
+                }
+            }
+        }
+        let mut configs = vec![];
+        for entry_name in entry_names {
+            let node = self.get_node(entry_name).stack()?;
+            let chain = node
+                .chain_spec()
+                .stack_err(|| format!("IBC link node \"{entry_name}\" is not a chain node"))?;
+            configs.push(chain.hermes_config_for_host(&node.hostname(uuid)));
+        }
+        Ok(configs)
+    }
+
+    /// If `args.entry_name` is set, this calls `entry` with it (after checking
+    /// that it is the entry name of a node), else this runs
+    /// [Topology::run_containers]
+    pub async fn run<F, Fut>(self, args: &Args, entry: F) -> Result<()>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        if let Some(ref entry_name) = args.entry_name {
+            self.get_node(entry_name).stack()?;
+            entry(entry_name.clone()).await
+        } else {
+            self.run_containers(args).await.stack()
+        }
+    }
+
+    /// Builds the test binary, writes the hermes config, and runs the
+    /// `ContainerNetwork` until all nodes are done
+    pub async fn run_containers(mut self, args: &Args) -> Result<()> {
+        let bin_entrypoint = &args.bin_name;
+
+        // build internal runner with `--release`
+        sh([
+            "cargo build --release --bin",
+            bin_entrypoint,
+            "--target",
+            CONTAINER_TARGET,
+        ]
+        .into_iter()
+        .chain(self.build_args.iter().map(|s| s.as_str())))
+        .await
+        .stack()?;
+
+        // prepare volumed resources
+        remove_files_in_dir(&format!("{KEYRING_DIR}/"), &[".address", ".info"])
+            .await
+            .stack()?;
+
+        let entrypoint = &format!("./target/{CONTAINER_TARGET}/release/{bin_entrypoint}");
+        // so that `versions()` in the containers matches the images built here
+        let version_vars = versions().stack()?.to_env_vars().stack()?;
+
+        let mut containers = vec![];
+        let mut resources = vec![];
+        for node in &self.nodes {
+            let mut entrypoint_args = vec!["--entry-name".to_owned(), node.entry_name.clone()];
+            entrypoint_args.extend(node.entrypoint_args.iter().cloned());
+            let dockerfile = node.get_dockerfile().stack()?;
+            resources.extend(
+                dockerfile_resources(&dockerfile)
+                    .into_iter()
+                    .map(|resource| resource.to_owned()),
+            );
+            let mut container =
+                Container::new(&node.container_name, Dockerfile::contents(dockerfile))
+                    .external_entrypoint(entrypoint, entrypoint_args)
+                    .await
+                    .stack()?
+                    .environment_vars(version_vars.clone());
+            if let Some(chain) = node.chain_spec() {
+                container =
+                    container.volume(KEYRING_DIR, format!("{}/keyring-test", chain.daemon_home()));
+            }
+            for (host_path, container_path) in &node.volumes {
+                container = container.volume(host_path, container_path);
+            }
+            containers.push(container);
+        }
+
+        let mut cn = ContainerNetwork::new(
+            &self.network_name,
+            containers,
+            Some(DOCKERFILES_DIR),
+            true,
+            LOGS_DIR,
+        )
+        .stack()?;
+        cn.add_common_volumes([(LOGS_DIR, "/logs")]);
+        let uuid = cn.uuid_as_string();
+        cn.add_common_entrypoint_args(["--uuid", &uuid]);
+        for service in std::mem::take(&mut self.services) {
+            cn.add_container(service).stack()?;
+        }
+
+        // prepare hermes config
+        if self
+            .nodes
+            .iter()
+            .any(|node| matches!(node.kind, NodeKind::Hermes))
+        {
+            write_hermes_config(
+                &self.hermes_configs(&uuid).stack()?,
+                &format!("{DOCKERFILES_DIR}/dockerfile_resources"),
+            )
+            .await
+            .stack()?;
+        }
+
+        // all resources should exist before any images are built
+        let resources: Vec<&str> = resources.iter().map(|s| s.as_str()).collect();
+        validate_resources(&resources, DOCKERFILES_DIR).stack()?;
+
+        cn.run_all(true).await.stack()?;
+        cn.wait_with_timeout_all(true, self.timeout).await.stack()?;
+        cn.terminate_all().await;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_topology_hermes_configs() {
+    let topology = Topology::new()
+        .node(TopologyNode::hermes("hermes"))
+        .node(TopologyNode::chain("onomyd", ChainSpec::onomy().unwrap()))
+        .node(
+            TopologyNode::chain("consumer", ChainSpec::onex().unwrap()).container_name("consumer"),
+        )
+        .ibc_link("consumer", "onomyd");
+    let configs = topology.hermes_configs("uuid").unwrap();
+    assert_eq!(configs.len(), 2);
+    assert_eq!(configs[0].chain_id, "onex");
+    assert_eq!(configs[0].rpc_addr, "http://consumer_uuid:26657");
+    assert_eq!(configs[1].rpc_addr, "http://onomyd_uuid:26657");
+
+    let topology = topology.ibc_link("hermes", "onomyd");
+    assert!(topology.hermes_configs("uuid").is_err());
+}
@@ -0,0 +1,455 @@
+//! Fee configuration and structured results of Cosmos-SDK transactions
+
+use std::fmt;
+
+use serde_json::Value;
+use super_orchestrator::stacked_errors::{Error, Result, StackableErr};
+
+use crate::json_inner;
+
+/// The gas limit of a transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gas {
+    /// `--gas auto --gas-adjustment {adjustment}`
+    Auto { adjustment: f64 },
+    /// `--gas {limit}`
+    Fixed(u64),
+}
+
+/// How gas and fees are specified for a transaction. Note that the SDK does
+/// not allow both `gas_prices` and `fees` to be set.
+///
+/// ```text
+/// // `--gas auto --gas-adjustment 1.3 --gas-prices 1anom`
+/// FeePolicy::chain_default("anom")
+/// // `--gas 1000000 --fees 1000000anative`
+/// FeePolicy::fixed_fees("1000000anative").with_fixed_gas(1000000)
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeePolicy {
+    /// If `None`, the daemon default gas limit is used
+    pub gas: Option<Gas>,
+    /// e.x. "1anom" for `--gas-prices 1anom`
+    pub gas_prices: Option<String>,
+    /// e.x. "1000000anom" for `--fees 1000000anom`
+    pub fees: Option<String>,
+    /// `--fee-granter {fee_granter}`
+    pub fee_granter: Option<String>,
+}
+
+impl FeePolicy {
+    /// Automatic gas with `adjustment`, paying with `gas_prices`
+    pub fn auto_gas(adjustment: f64, gas_prices: &str) -> Self {
+        Self {
+            gas: Some(Gas::Auto { adjustment }),
+            gas_prices: Some(gas_prices.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// Fixed `fees` with the default gas limit
+    pub fn fixed_fees(fees: &str) -> Self {
+        Self {
+            fees: Some(fees.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// The default for a chain with `gas_denom`, automatic gas with an
+    /// adjustment of 1.3 and a gas price of `1{gas_denom}`
+    pub fn chain_default(gas_denom: &str) -> Self {
+        Self::auto_gas(1.3, &format!("1{gas_denom}"))
+    }
+
+    pub fn with_auto_gas(mut self, adjustment: f64) -> Self {
+        self.gas = Some(Gas::Auto { adjustment });
+        self
+    }
+
+    pub fn with_fixed_gas(mut self, limit: u64) -> Self {
+        self.gas = Some(Gas::Fixed(limit));
+        self
+    }
+
+    /// Sets the gas prices and removes any fixed fees
+    pub fn with_gas_prices(mut self, gas_prices: &str) -> Self {
+        self.gas_prices = Some(gas_prices.to_owned());
+        self.fees = None;
+        self
+    }
+
+    /// Sets fixed fees and removes any gas prices
+    pub fn with_fees(mut self, fees: &str) -> Self {
+        self.fees = Some(fees.to_owned());
+        self.gas_prices = None;
+        self
+    }
+
+    pub fn with_fee_granter(mut self, fee_granter: &str) -> Self {
+        self.fee_granter = Some(fee_granter.to_owned());
+        self
+    }
+
+    /// Returns the flags for a `tx` command, or an error if both `gas_prices`
+    /// and `fees` are set
+    pub fn to_args(&self) -> Result<Vec<String>> {
+        if self.gas_prices.is_some() && self.fees.is_some() {
+            return Err(Error::from(format!(
+                "FeePolicy::to_args: both gas prices and fees are set in {self:?}, the SDK only \
+                 allows one of them"
+            )))
+        }
+        let mut args = vec![];
+        match self.gas {
+            Some(Gas::Auto { adjustment }) => {
+                args.extend([
+                    "--gas".to_owned(),
+                    "auto".to_owned(),
+                    "--gas-adjustment".to_owned(),
+                    format!("{adjustment}"),
+                ]);
+            }
+            Some(Gas::Fixed(limit)) => {
+                args.extend(["--gas".to_owned(), format!("{limit}")]);
+            }
+            None => (),
+        }
+        if let Some(ref gas_prices) = self.gas_prices {
+            args.extend(["--gas-prices".to_owned(), gas_prices.clone()]);
+        }
+        if let Some(ref fees) = self.fees {
+            args.extend(["--fees".to_owned(), fees.clone()]);
+        }
+        if let Some(ref fee_granter) = self.fee_granter {
+            args.extend(["--fee-granter".to_owned(), fee_granter.clone()]);
+        }
+        Ok(args)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxAttribute {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxEvent {
+    pub ty: String,
+    pub attributes: Vec<TxAttribute>,
+}
+
+impl TxEvent {
+    /// Returns the value of the first attribute with `key`
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.key == key)
+            .map(|attr| attr.value.as_str())
+    }
+}
+
+/// The parsed output of a `tx` command. Note that this can be constructed
+/// from an unsuccessful transaction, use [TxResponse::into_result] to check.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxResponse {
+    pub txhash: String,
+    pub height: u64,
+    pub code: u32,
+    pub codespace: String,
+    pub gas_wanted: u64,
+    pub gas_used: u64,
+    pub raw_log: String,
+    /// The events of all messages. These are taken from "logs" if it is
+    /// nonempty, otherwise from the top level "events" (newer SDK versions no
+    /// longer populate "logs").
+    pub events: Vec<TxEvent>,
+    /// The whole response
+    pub raw: Value,
+}
+
+impl TxResponse {
+    /// Parses the YAML or JSON output of a `tx` command that was converted
+    /// to a `Value`
+    pub fn from_value(raw: Value) -> Result<Self> {
+        let mut events = vec![];
+        if let Some(logs) = raw.get("logs").and_then(|logs| logs.as_array()) {
+            for log in logs {
+                if let Some(log_events) = log.get("events") {
+                    events.extend(parse_events(log_events).stack_err(|| "parsing logs")?);
+                }
+            }
+        }
+        if events.is_empty() {
+            if let Some(top_events) = raw.get("events") {
+                events = parse_events(top_events).stack_err(|| "parsing events")?;
+            }
+        }
+        Ok(Self {
+            txhash: str_field(&raw, "txhash"),
+            height: u64_field(&raw, "height").stack()?,
+            code: u32::try_from(u64_field(&raw, "code").stack()?)
+                .ok()
+                .stack_err(|| "code does not fit in a u32")?,
+            codespace: str_field(&raw, "codespace"),
+            gas_wanted: u64_field(&raw, "gas_wanted").stack()?,
+            gas_used: u64_field(&raw, "gas_used").stack()?,
+            raw_log: str_field(&raw, "raw_log"),
+            events,
+            raw,
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.code == 0
+    }
+
+    /// Returns a [TxError] if the code is nonzero
+    pub fn into_result(self) -> core::result::Result<Self, TxError> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(TxError {
+                txhash: self.txhash,
+                height: self.height,
+                codespace: self.codespace,
+                code: self.code,
+                raw_log: self.raw_log,
+            })
+        }
+    }
+
+    /// Returns all events with type `ty`
+    pub fn events_of_type<'a>(&'a self, ty: &'a str) -> impl Iterator<Item = &'a TxEvent> {
+        self.events.iter().filter(move |event| event.ty == ty)
+    }
+
+    /// Returns the value of the first attribute `key` in an event of type
+    /// `ty`
+    pub fn attribute(&self, ty: &str, key: &str) -> Option<&str> {
+        self.events
+            .iter()
+            .filter(|event| event.ty == ty)
+            .find_map(|event| event.attribute(key))
+    }
+
+    /// Returns the values of all attributes `key` in events of type `ty`
+    pub fn attributes(&self, ty: &str, key: &str) -> Vec<&str> {
+        self.events
+            .iter()
+            .filter(|event| event.ty == ty)
+            .flat_map(|event| event.attributes.iter())
+            .filter(|attr| attr.key == key)
+            .map(|attr| attr.value.as_str())
+            .collect()
+    }
+
+    /// Returns the values of all attributes `key` in events of any type
+    pub fn attributes_any(&self, key: &str) -> Vec<&str> {
+        self.events
+            .iter()
+            .flat_map(|event| event.attributes.iter())
+            .filter(|attr| attr.key == key)
+            .map(|attr| attr.value.as_str())
+            .collect()
+    }
+}
+
+/// A transaction that was included or checked but failed with a nonzero code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxError {
+    pub txhash: String,
+    pub height: u64,
+    pub codespace: String,
+    pub code: u32,
+    pub raw_log: String,
+}
+
+impl TxError {
+    pub fn is_sdk_error(&self, code: u32) -> bool {
+        (self.codespace == "sdk") && (self.code == code)
+    }
+
+    /// `sdkerrors.ErrUnauthorized`
+    pub fn is_unauthorized(&self) -> bool {
+        self.is_sdk_error(4)
+    }
+
+    /// `sdkerrors.ErrInsufficientFunds`
+    pub fn is_insufficient_funds(&self) -> bool {
+        self.is_sdk_error(5)
+    }
+
+    /// `sdkerrors.ErrOutOfGas`
+    pub fn is_out_of_gas(&self) -> bool {
+        self.is_sdk_error(11)
+    }
+
+    /// `sdkerrors.ErrInsufficientFee`
+    pub fn is_insufficient_fee(&self) -> bool {
+        self.is_sdk_error(13)
+    }
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction {} failed with codespace \"{}\" code {}, raw_log: {}",
+            self.txhash, self.codespace, self.code, self.raw_log
+        )
+    }
+}
+
+impl std::error::Error for TxError {}
+
+fn str_field(v: &Value, key: &str) -> String {
+    match v.get(key) {
+        None | Some(Value::Null) => String::new(),
+        Some(inner) => json_inner(inner),
+    }
+}
+
+/// The SDK encodes 64 bit integers as strings, but some fields are plain
+/// numbers
+fn u64_field(v: &Value, key: &str) -> Result<u64> {
+    match v.get(key) {
+        None | Some(Value::Null) => Ok(0),
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .stack_err(|| format!("field \"{key}\" is not a u64")),
+        Some(Value::String(s)) if s.is_empty() => Ok(0),
+        Some(Value::String(s)) => s
+            .parse::<u64>()
+            .stack_err(|| format!("field \"{key}\" is not a u64")),
+        Some(other) => Err(Error::from(format!(
+            "field \"{key}\" has unexpected value {other}"
+        ))),
+    }
+}
+
+fn parse_events(events: &Value) -> Result<Vec<TxEvent>> {
+    let mut res = vec![];
+    let events = match events {
+        Value::Null => return Ok(res),
+        Value::Array(events) => events,
+        _ => return Err(Error::from(format!("events are not an array: {events}"))),
+    };
+    for event in events {
+        let mut attributes = vec![];
+        if let Some(attrs) = event.get("attributes").and_then(|attrs| attrs.as_array()) {
+            for attr in attrs {
+                attributes.push(TxAttribute {
+                    key: str_field(attr, "key"),
+                    value: str_field(attr, "value"),
+                });
+            }
+        }
+        res.push(TxEvent {
+            ty: str_field(event, "type"),
+            attributes,
+        });
+    }
+    Ok(res)
+}
+
+#[test]
+fn test_fee_policy() {
+    assert_eq!(
+        FeePolicy::chain_default("anom")
+            .to_args()
+            .unwrap()
+            .join(" "),
+        "--gas auto --gas-adjustment 1.3 --gas-prices 1anom"
+    );
+    assert_eq!(
+        FeePolicy::auto_gas(2.3, "1anom")
+            .with_fees("1000000anom")
+            .with_fee_granter("onomy1abc")
+            .to_args()
+            .unwrap()
+            .join(" "),
+        "--gas auto --gas-adjustment 2.3 --fees 1000000anom --fee-granter onomy1abc"
+    );
+    assert_eq!(
+        FeePolicy::fixed_fees("1000000anative")
+            .with_fixed_gas(1000000)
+            .to_args()
+            .unwrap()
+            .join(" "),
+        "--gas 1000000 --fees 1000000anative"
+    );
+    let both = FeePolicy {
+        fees: Some("1000000anom".to_owned()),
+        ..FeePolicy::chain_default("anom")
+    };
+    assert!(both.to_args().is_err());
+}
+
+#[test]
+fn test_tx_response() {
+    use crate::yaml_str_to_json_value;
+
+    let success = yaml_str_to_json_value(
+        r#"code: 0
+codespace: ""
+data: 0A1E0A1C2F636F736D6F732E62616E6B2E763162657461312E4D736753656E64
+gas_used: "62349"
+gas_wanted: "78366"
+height: "12"
+info: ""
+logs:
+- events:
+  - attributes:
+    - key: receiver
+      value: onomy1yks83spz6lvrrys8kh0untt22399tskk6jafcv
+    - key: amount
+      value: 1337anom
+    type: coin_received
+  - attributes:
+    - key: action
+      value: /cosmos.bank.v1beta1.MsgSend
+    - key: sender
+      value: onomy1p8zpd5ulqgzj5sq5k8hdksm0w2e7avvq8k7qd0
+    - key: module
+      value: bank
+    type: message
+  log: ""
+  msg_index: 0
+raw_log: '[]'
+timestamp: ""
+tx: null
+txhash: 5D5E1A5C4F1DB2E0C4A0B1F4C5D0B8F3C2B6C1D4E8F7A6B5C4D3E2F1A0B9C8D7
+"#,
+    )
+    .unwrap();
+    let res = TxResponse::from_value(success).unwrap();
+    assert!(res.is_success());
+    assert_eq!(res.height, 12);
+    assert_eq!(res.gas_used, 62349);
+    assert_eq!(res.gas_wanted, 78366);
+    assert_eq!(res.events.len(), 2);
+    assert_eq!(res.attribute("coin_received", "amount"), Some("1337anom"));
+    assert_eq!(res.attributes("message", "module"), vec!["bank"]);
+    assert_eq!(res.attribute("coin_received", "module"), None);
+    assert!(res.into_result().is_ok());
+
+    // newer SDK versions with JSON output
+    let failure = serde_json::json!({
+        "height": "0",
+        "txhash": "ABCD",
+        "codespace": "sdk",
+        "code": 13,
+        "raw_log": "insufficient fees; got: 1anom required: 1000anom: insufficient fee",
+        "logs": [],
+        "gas_wanted": "200000",
+        "gas_used": "40000",
+        "events": [{"type": "tx", "attributes": [{"key": "fee", "value": "1anom", "index": true}]}]
+    });
+    let res = TxResponse::from_value(failure).unwrap();
+    assert_eq!(res.attribute("tx", "fee"), Some("1anom"));
+    let e = res.into_result().unwrap_err();
+    assert!(e.is_insufficient_fee());
+    assert!(!e.is_out_of_gas());
+    assert!(e.to_string().contains("insufficient fee"));
+}
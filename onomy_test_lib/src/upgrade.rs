@@ -0,0 +1,171 @@
+//! Orchestration of software upgrades through governance and cosmovisor
+
+use std::time::Duration;
+
+use log::info;
+use super_orchestrator::{
+    stacked_errors::{Error, Result, StackableErr},
+    wait_for_ok,
+};
+
+use crate::{
+    cosmovisor::{
+        cosmovisor_gov_proposal, get_block_height, get_cosmovisor_subprocess_path, sh_cosmovisor,
+        wait_for_height,
+    },
+    tx::FeePolicy,
+    STD_DELAY, STD_TRIES,
+};
+
+/// A software upgrade that has been passed to governance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradePlan {
+    /// The name of the upgrade, which is also the name of the directory
+    /// `cosmovisor/upgrades/{name}` that the new binary needs to be placed in
+    pub name: String,
+    /// The old binary halts at this height, and the new binary produces it
+    pub height: u64,
+    pub info: String,
+    pub proposal_id: u64,
+}
+
+impl UpgradePlan {
+    /// If `subprocess_path` (as returned by [get_cosmovisor_subprocess_path])
+    /// is the binary of this upgrade
+    pub fn is_upgrade_binary(&self, subprocess_path: &str) -> bool {
+        subprocess_path.contains(&format!("cosmovisor/upgrades/{}/", self.name))
+    }
+}
+
+/// Submits, deposits `deposit`, and votes yes on a `software-upgrade` proposal
+/// from the `from_key` account, with the upgrade height at `height_offset`
+/// blocks after the current height. `height_offset` needs to be large enough
+/// for the voting period to end before the upgrade height. See
+/// [cosmovisor_gov_proposal] for the fees.
+pub async fn schedule_upgrade(
+    from_key: &str,
+    name: &str,
+    height_offset: u64,
+    info: &str,
+    deposit: &str,
+    submit_fee: &FeePolicy,
+    vote_fee: &FeePolicy,
+) -> Result<UpgradePlan> {
+    let height = get_block_height()
+        .await
+        .stack()?
+        .checked_add(height_offset)
+        .stack()?;
+    let height_s = format!("{height}");
+    let description = format!("upgrade {name}");
+    let mut args = vec![
+        name,
+        "--title",
+        &description,
+        "--description",
+        &description,
+        "--upgrade-height",
+        &height_s,
+    ];
+    if !info.is_empty() {
+        args.extend(["--upgrade-info", info]);
+    }
+    let proposal_id = cosmovisor_gov_proposal(
+        from_key,
+        "software-upgrade",
+        &args,
+        deposit,
+        submit_fee,
+        vote_fee,
+    )
+    .await
+    .stack_err(|| format!("schedule_upgrade(name: {name}, height: {height})"))?;
+    info!("scheduled upgrade {name} at height {height} with proposal {proposal_id}");
+    Ok(UpgradePlan {
+        name: name.to_owned(),
+        height,
+        info: info.to_owned(),
+        proposal_id,
+    })
+}
+
+/// Waits for the chain to reach the last block before the upgrade height, and
+/// then for cosmovisor to handle the halt by switching its `current` symlink to
+/// the upgrade binary
+pub async fn wait_for_upgrade_halt(
+    num_tries: u64,
+    delay: Duration,
+    plan: &UpgradePlan,
+) -> Result<()> {
+    wait_for_height(num_tries, delay, plan.height.saturating_sub(1))
+        .await
+        .stack_err(|| format!("wait_for_upgrade_halt({plan:?}) never reached the halt height"))?;
+    wait_for_ok(num_tries, delay, || verify_cosmovisor_upgraded(plan))
+        .await
+        .stack_err(|| format!("wait_for_upgrade_halt({plan:?}) cosmovisor never switched"))
+}
+
+/// Returns an error if the `current` cosmovisor symlink does not point to the
+/// upgrade binary
+pub async fn verify_cosmovisor_upgraded(plan: &UpgradePlan) -> Result<()> {
+    let path = get_cosmovisor_subprocess_path().await.stack()?;
+    if plan.is_upgrade_binary(&path) {
+        Ok(())
+    } else {
+        Err(Error::from(format!(
+            "cosmovisor is running {path} instead of the binary for upgrade {}",
+            plan.name
+        )))
+    }
+}
+
+/// Waits for the new binary to produce `num_blocks` blocks past the upgrade
+/// height
+pub async fn wait_for_upgrade_resume(
+    num_tries: u64,
+    delay: Duration,
+    plan: &UpgradePlan,
+    num_blocks: u64,
+) -> Result<()> {
+    wait_for_height(num_tries, delay, plan.height + num_blocks)
+        .await
+        .stack_err(|| format!("chain did not resume after upgrade {plan:?}"))
+}
+
+/// Waits through the whole upgrade with [wait_for_upgrade_halt] and
+/// [wait_for_upgrade_resume], and then checks that `version` reports
+/// `expected_version`
+pub async fn wait_for_upgrade(plan: &UpgradePlan, expected_version: &str) -> Result<()> {
+    wait_for_upgrade_halt(STD_TRIES, STD_DELAY, plan)
+        .await
+        .stack()?;
+    wait_for_upgrade_resume(STD_TRIES, STD_DELAY, plan, 1)
+        .await
+        .stack()?;
+    let version = sh_cosmovisor(["version"]).await.stack()?;
+    if version.trim() != expected_version {
+        return Err(Error::from(format!(
+            "after upgrade {}, version is {} instead of {expected_version}",
+            plan.name,
+            version.trim()
+        )))
+    }
+    info!(
+        "upgrade {} to version {expected_version} succeeded",
+        plan.name
+    );
+    Ok(())
+}
+
+#[test]
+fn test_upgrade_plan() {
+    let plan = UpgradePlan {
+        name: "v1.1.2".to_owned(),
+        height: 10,
+        info: String::new(),
+        proposal_id: 1,
+    };
+    assert!(plan.is_upgrade_binary("/root/.onomy/cosmovisor/upgrades/v1.1.2/bin/onomyd"));
+    assert!(!plan.is_upgrade_binary("/root/.onomy/cosmovisor/genesis/bin/onomyd"));
+    assert!(!plan.is_upgrade_binary("/root/.onomy/cosmovisor/upgrades/v1.1.2-rc/bin/onomyd"));
+}
@@ -0,0 +1,177 @@
+//! The versions of binaries that our dockerfiles download
+//!
+//! These are read from `onomy_test_lib/versions.toml` when the program runs, so
+//! that a release candidate can be tested without recompiling. A different file
+//! can be used with `--versions` or the `ONOMY_VERSIONS` environment variable,
+//! and single entries can be overridden with `ONOMY_VERSION_{ENTRY}`
+//! environment variables (e.x. `ONOMY_VERSION_ONOMYD=v1.1.5-rc1`).
+//!
+//! Containers run by `Topology` do not have these files, so the versions
+//! resolved on the host are forwarded to them as `ONOMY_VERSION_{ENTRY}`
+//! variables (see [Versions::to_env_vars]).
+
+use std::{path::Path, sync::OnceLock};
+
+use serde_derive::{Deserialize, Serialize};
+use super_orchestrator::stacked_errors::{Error, Result, StackableErr};
+
+/// The default path of the versions manifest, relative to the workspace root
+pub const VERSIONS_PATH: &str = "./onomy_test_lib/versions.toml";
+/// If set, the path of the versions manifest to use instead of
+/// [VERSIONS_PATH]
+pub const VERSIONS_ENV: &str = "ONOMY_VERSIONS";
+/// The prefix of the environment variables that override single entries
+pub const VERSION_OVERRIDE_ENV_PREFIX: &str = "ONOMY_VERSION_";
+
+static VERSIONS: OnceLock<Versions> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Versions {
+    pub cosmovisor: String,
+    pub hermes: String,
+    pub onomyd: String,
+    pub onexd: String,
+    /// The firehose version of `onexd`
+    pub onexd_fh: String,
+    /// The standalone ONEX chain
+    pub marketd: String,
+    /// The firehose version of `marketd`
+    pub marketd_fh: String,
+    /// The interchain-security repository version used for test consumers
+    pub ics: String,
+    /// The `DAEMON_VERSION` of the interchain-security test consumer
+    pub ics_consumer: String,
+    /// The genesis version of the `onomyd` upgrade test
+    pub onomyd_upgrade_from: String,
+    /// The upgrade version of the `onomyd` upgrade test
+    pub onomyd_upgrade_to: String,
+    /// The genesis version of the `onexd` upgrade test
+    pub onexd_upgrade_from: String,
+    /// The upgrade version of the `onexd` upgrade test
+    pub onexd_upgrade_to: String,
+}
+
+impl Versions {
+    pub fn from_toml(s: &str) -> Result<Self> {
+        toml::from_str(s).stack_err(|| "Versions::from_toml")
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .stack_err(|| format!("Versions::from_file could not read {path}"))?;
+        Self::from_toml(&s).stack_err(|| format!("Versions::from_file({path})"))
+    }
+
+    /// The manifest as of compilation
+    pub fn embedded() -> Self {
+        Self::from_toml(include_str!("../versions.toml")).unwrap()
+    }
+
+    /// Applies the overrides in `vars` with names of the form
+    /// `ONOMY_VERSION_{ENTRY}`, where `ENTRY` is the uppercase name of a field
+    pub fn with_overrides<I, K, V>(self, vars: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut table = toml::Value::try_from(&self).stack()?;
+        let table = table.as_table_mut().stack()?;
+        for (key, value) in vars {
+            let key = key.as_ref();
+            if let Some(entry) = key.strip_prefix(VERSION_OVERRIDE_ENV_PREFIX) {
+                let entry = entry.to_lowercase();
+                match table.get_mut(&entry) {
+                    Some(v) => *v = toml::Value::String(value.as_ref().to_owned()),
+                    None => {
+                        return Err(Error::from(format!(
+                            "{key} does not override any entry of `Versions`"
+                        )))
+                    }
+                }
+            }
+        }
+        toml::Value::Table(table.clone())
+            .try_into()
+            .stack_err(|| "Versions::with_overrides")
+    }
+
+    /// Returns the `ONOMY_VERSION_{ENTRY}` variables that override every entry
+    /// to the versions of `self`
+    pub fn to_env_vars(&self) -> Result<Vec<(String, String)>> {
+        let table = toml::Value::try_from(self).stack()?;
+        table
+            .as_table()
+            .stack()?
+            .iter()
+            .map(|(entry, value)| {
+                let value = value
+                    .as_str()
+                    .stack_err(|| format!("Versions::to_env_vars entry {entry}"))?;
+                Ok((
+                    format!("{VERSION_OVERRIDE_ENV_PREFIX}{}", entry.to_uppercase()),
+                    value.to_owned(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Loads from the path in [VERSIONS_ENV] if it is set, else from
+    /// [VERSIONS_PATH] if it exists (it will not inside of containers), else
+    /// uses [Versions::embedded]. The `ONOMY_VERSION_{ENTRY}` overrides are
+    /// applied.
+    pub fn load() -> Result<Self> {
+        let versions = match std::env::var(VERSIONS_ENV) {
+            Ok(path) => Self::from_file(&path).stack()?,
+            Err(_) if Path::new(VERSIONS_PATH).exists() => {
+                Self::from_file(VERSIONS_PATH).stack()?
+            }
+            Err(_) => Self::embedded(),
+        };
+        versions.with_overrides(std::env::vars()).stack()
+    }
+}
+
+/// Sets the [versions] of this process to be loaded from `path` with the
+/// environment overrides applied. Returns an error if [versions] has already
+/// been called.
+pub fn init_versions(path: &str) -> Result<()> {
+    let versions = Versions::from_file(path)
+        .stack()?
+        .with_overrides(std::env::vars())
+        .stack()?;
+    VERSIONS
+        .set(versions)
+        .map_err(|_| Error::from("init_versions called after the versions were already loaded"))
+}
+
+/// Returns the versions of this process, see [Versions::load] and
+/// [init_versions]
+pub fn versions() -> Result<&'static Versions> {
+    if let Some(versions) = VERSIONS.get() {
+        return Ok(versions)
+    }
+    let versions = Versions::load().stack()?;
+    Ok(VERSIONS.get_or_init(|| versions))
+}
+
+#[test]
+fn test_versions() {
+    let versions = Versions::embedded();
+    let overridden = versions
+        .clone()
+        .with_overrides([("ONOMY_VERSION_ONOMYD", "v1.1.5-rc1"), ("PATH", "/usr/bin")])
+        .unwrap();
+    assert_eq!(overridden.onomyd, "v1.1.5-rc1");
+    assert_eq!(overridden.onexd, versions.onexd);
+    assert!(versions
+        .clone()
+        .with_overrides([("ONOMY_VERSION_NONEXISTENT", "v0.0.0")])
+        .is_err());
+
+    // what containers get from the host
+    let vars = overridden.to_env_vars().unwrap();
+    assert!(vars.contains(&("ONOMY_VERSION_ONOMYD".to_owned(), "v1.1.5-rc1".to_owned())));
+    assert_eq!(versions.with_overrides(vars).unwrap(), overridden);
+}
@@ -0,0 +1,63 @@
+//! Populates the artifact cache used by `ONOMY_ARTIFACT_CACHE=prefer` or
+//! `ONOMY_ARTIFACT_CACHE=offline` image builds
+
+use clap::Parser;
+use log::info;
+use onomy_test_lib::{
+    dockerfiles::{
+        release_artifact, std_artifacts, ArtifactCache, ArtifactCacheMode, VersionManifest,
+        DOCKERFILES_DIR,
+    },
+    super_orchestrator::{
+        stacked_errors::{Result, StackableErr},
+        std_init,
+    },
+};
+
+/// Downloads the artifacts of the standard dockerfiles into
+/// `{dockerfiles_dir}/dockerfile_resources/artifact_cache/`
+#[derive(Parser, Debug)]
+#[command(about)]
+struct Args {
+    #[arg(long, default_value_t = String::from(DOCKERFILES_DIR))]
+    dockerfiles_dir: String,
+    /// Additional daemon releases in the form `{daemon_name}@{version}`, e.x.
+    /// `onomyd@v1.1.2` for the genesis binary of an upgrade test
+    #[arg(short, long)]
+    release: Vec<String>,
+    /// Pins the hashes of unpinned artifacts in the sha256 manifest (the file
+    /// in `ONOMY_SHA256_MANIFEST` if it is set)
+    #[arg(long)]
+    update_manifest: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    std_init()?;
+    let args = Args::parse();
+
+    let mut artifacts = std_artifacts().stack()?;
+    for release in &args.release {
+        let (daemon_name, version) = release
+            .split_once('@')
+            .stack_err(|| format!("--release {release} is not in the form daemon_name@version"))?;
+        artifacts.push(release_artifact(daemon_name, version).stack()?);
+    }
+
+    let cache = ArtifactCache::new(&args.dockerfiles_dir, ArtifactCacheMode::Offline);
+    let mut manifest = VersionManifest::std().clone();
+    for artifact in &artifacts {
+        cache.fetch(artifact).await.stack()?;
+        let artifact = if args.update_manifest {
+            cache.pin(artifact, &mut manifest).stack()?
+        } else {
+            artifact.clone()
+        };
+        info!("cached {}", cache.path(&artifact));
+    }
+    if args.update_manifest {
+        manifest.save(&VersionManifest::path()).stack()?;
+    }
+
+    Ok(())
+}
@@ -5,7 +5,7 @@ use std::{str::FromStr, time::Duration};
 use clarity::Address;
 use log::info;
 use onomy_test_lib::{
-    dockerfiles::ONOMY_STD,
+    dockerfiles::DockerfileBuilder,
     onomy_std_init,
     super_orchestrator::{
         docker::{Container, ContainerNetwork, Dockerfile},
@@ -33,12 +33,20 @@ async fn main() -> Result<()> {
     }
 }
 
-#[rustfmt::skip]
-const GETH: &str = r#"ADD https://gethstore.blob.core.windows.net/builds/geth-linux-amd64-1.12.0-e501b3b0.tar.gz /tmp/geth.tar.gz
-RUN cd /tmp && tar -xvf * && mv /tmp/geth-linux-amd64-1.12.0-e501b3b0/geth /usr/bin/geth
-
-RUN mkdir /resources
-"#;
+fn dockerfile_geth() -> Result<String> {
+    DockerfileBuilder::onomy_std()
+        .add_url(
+            "https://gethstore.blob.core.windows.net/builds/\
+             geth-linux-amd64-1.12.0-e501b3b0.tar.gz",
+            "/tmp/geth.tar.gz",
+        )
+        .run(
+            "cd /tmp && tar -xvf * && mv /tmp/geth-linux-amd64-1.12.0-e501b3b0/geth \
+             /usr/bin/geth",
+        )
+        .run("mkdir /resources")
+        .build()
+}
 
 async fn container_runner(args: &Args) -> Result<()> {
     let logs_dir = "./tests/logs";
@@ -63,14 +71,17 @@ async fn container_runner(args: &Args) -> Result<()> {
     let mut cn = ContainerNetwork::new(
         "test",
         vec![
-            Container::new("geth", Dockerfile::contents(format!("{ONOMY_STD} {GETH}")))
+            Container::new("geth", Dockerfile::contents(dockerfile_geth().stack()?))
                 .external_entrypoint(entrypoint, ["--entry-name", "geth"])
                 .await
                 .stack()?,
-            Container::new("test", Dockerfile::contents(ONOMY_STD.to_owned()))
-                .external_entrypoint(entrypoint, ["--entry-name", "test"])
-                .await
-                .stack()?,
+            Container::new(
+                "test",
+                Dockerfile::contents(DockerfileBuilder::onomy_std().render()),
+            )
+            .external_entrypoint(entrypoint, ["--entry-name", "test"])
+            .await
+            .stack()?,
             /*Container::new(
                 "prometheus",
                 Dockerfile::NameTag("prom/prometheus:v2.44.0".to_owned()),
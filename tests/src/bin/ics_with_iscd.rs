@@ -5,181 +5,75 @@ use std::time::Duration;
 
 use log::info;
 use onomy_test_lib::{
+    chain::ChainSpec,
     cosmovisor::{
         cosmovisor_bank_send, cosmovisor_get_addr, cosmovisor_get_balances, cosmovisor_start,
-        set_minimum_gas_price, sh_cosmovisor, sh_cosmovisor_no_debug, sh_cosmovisor_tx,
-        wait_for_num_blocks,
-    },
-    dockerfiles::{dockerfile_hermes, dockerfile_onomyd, onomy_std_cosmos_daemon_with_arbitrary},
-    hermes::{
-        hermes_set_gas_price_denom, hermes_start, sh_hermes, write_hermes_config,
-        HermesChainConfig, IbcPair,
+        set_minimum_gas_price, sh_cosmovisor, sh_cosmovisor_no_debug, CosmovisorCmd,
     },
+    hermes::{hermes_set_gas_price_denom, hermes_start, sh_hermes, IbcPair},
+    ibc::{cosmovisor_wait_for_refund, PacketTimeout},
     onomy_std_init, reprefix_bech32,
+    setup_msg::{
+        ChainsConnected, ConsumerGenesis, GasDenomUpdate, HermesMnemonic, Ready, SetupMessenger,
+        Terminate, ValidatorKeys, WaitForTransfers,
+    },
     setups::{cosmovisor_add_consumer, cosmovisor_setup, test_proposal, CosmosSetupOptions},
     super_orchestrator::{
-        docker::{Container, ContainerNetwork, Dockerfile},
-        net_message::NetMessenger,
-        remove_files_in_dir, sh,
+        sh,
         stacked_errors::{ensure, ensure_eq, Error, Result, StackableErr},
         FileOptions,
     },
-    token18, u64_array_bigints,
+    token18,
+    topology::{Topology, TopologyNode},
+    tx::FeePolicy,
+    u64_array_bigints,
     u64_array_bigints::u256,
-    Args, ONOMY_IBC_NOM, STD_DELAY, STD_TRIES, TIMEOUT,
+    Args, STD_DELAY, STD_TRIES, TIMEOUT,
 };
 use tokio::time::sleep;
 
-const CONSUMER_ID: &str = "interchain-security-cd";
-const CONSUMER_VERSION: &str = "v07-Theta";
-const PROVIDER_ACCOUNT_PREFIX: &str = "onomy";
-const CONSUMER_ACCOUNT_PREFIX: &str = "cosmos";
-
-fn consumer_binary_name() -> String {
-    format!("{CONSUMER_ID}d")
-}
-
-fn consumer_directory() -> String {
-    format!(".{CONSUMER_ID}")
-    //format!(".onomy_{CONSUMER_ID}")
-}
-
-#[rustfmt::skip]
-const INTERCHAIN_SECURTY_CDD: &str = r#"ENV ICS_VERSION=1.2.0-multiden
-ADD https://github.com/cosmos/interchain-security/archive/refs/tags/v$ICS_VERSION.tar.gz /root/v$ICS_VERSION.tar.gz
-RUN cd /root && tar -xvf ./v$ICS_VERSION.tar.gz
-RUN cd /root/interchain-security-$ICS_VERSION && go build ./cmd/interchain-security-cdd
-RUN mkdir -p $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/
-RUN mv /root/interchain-security-$ICS_VERSION/interchain-security-cdd $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/$DAEMON_NAME
-"#;
-
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = onomy_std_init()?;
 
-    if let Some(ref s) = args.entry_name {
-        match s.as_str() {
-            "onomyd" => onomyd_runner(&args).await,
-            "consumer" => consumer(&args).await,
-            "hermes" => hermes_runner(&args).await,
-            _ => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
-        }
-    } else {
+    let topology = Topology::new()
+        .node(TopologyNode::hermes("hermes"))
+        .node(TopologyNode::chain("onomyd", ChainSpec::onomy().stack()?))
+        .node(TopologyNode::chain(
+            "consumer",
+            ChainSpec::ics_test_consumer().stack()?,
+        ))
+        .ibc_link("onomyd", "consumer");
+
+    if args.entry_name.is_none() {
         sh(["make --directory ./../onomy/ build"]).await.stack()?;
         // copy to dockerfile resources (docker cannot use files from outside cwd)
         sh(["cp ./../onomy/onomyd ./tests/dockerfiles/dockerfile_resources/onomyd"])
             .await
             .stack()?;
-        container_runner(&args).await.stack()
     }
-}
-
-async fn container_runner(args: &Args) -> Result<()> {
-    let logs_dir = "./tests/logs";
-    let dockerfiles_dir = "./tests/dockerfiles";
-    let bin_entrypoint = &args.bin_name;
-    let container_target = "x86_64-unknown-linux-gnu";
-
-    // build internal runner with `--release`
-    sh([
-        "cargo build --release --bin",
-        bin_entrypoint,
-        "--target",
-        container_target,
-    ])
-    .await
-    .stack()?;
-
-    // prepare volumed resources
-    remove_files_in_dir("./tests/resources/keyring-test/", &[".address", ".info"])
+    let args = &args;
+    topology
+        .run(args, |entry_name| async move {
+            match entry_name.as_str() {
+                "onomyd" => onomyd_runner(args).await,
+                "consumer" => consumer(args).await,
+                "hermes" => hermes_runner(args).await,
+                s => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
+            }
+        })
         .await
-        .stack()?;
-
-    let entrypoint = &format!("./target/{container_target}/release/{bin_entrypoint}");
-
-    let mut cn = ContainerNetwork::new(
-        "test",
-        vec![
-            Container::new(
-                "hermes",
-                Dockerfile::contents(dockerfile_hermes("__tmp_hermes_config.toml")),
-            )
-            .external_entrypoint(entrypoint, ["--entry-name", "hermes"])
-            .await
-            .stack()?,
-            Container::new("onomyd", Dockerfile::contents(dockerfile_onomyd()))
-                .external_entrypoint(entrypoint, ["--entry-name", "onomyd"])
-                .await
-                .stack()?
-                .volume(
-                    "./tests/resources/keyring-test",
-                    "/root/.onomy/keyring-test",
-                ),
-            Container::new(
-                &consumer_binary_name(),
-                Dockerfile::Contents(onomy_std_cosmos_daemon_with_arbitrary(
-                    &consumer_binary_name(),
-                    &consumer_directory(),
-                    CONSUMER_VERSION,
-                    INTERCHAIN_SECURTY_CDD,
-                )),
-            )
-            .external_entrypoint(entrypoint, ["--entry-name", "consumer"])
-            .await
-            .stack()?
-            .volume(
-                "./tests/resources/keyring-test",
-                format!("/root/{}/keyring-test", consumer_directory()),
-            ),
-        ],
-        Some(dockerfiles_dir),
-        true,
-        logs_dir,
-    )
-    .stack()?;
-    cn.add_common_volumes([(logs_dir, "/logs")]);
-    let uuid = cn.uuid_as_string();
-    cn.add_common_entrypoint_args(["--uuid", &uuid]);
-
-    // prepare hermes config
-    write_hermes_config(
-        &[
-            HermesChainConfig::new(
-                "onomy",
-                &format!("onomyd_{uuid}"),
-                "onomy",
-                false,
-                "anom",
-                true,
-            ),
-            HermesChainConfig::new(
-                CONSUMER_ID,
-                &format!("{}_{}", consumer_binary_name(), uuid),
-                CONSUMER_ACCOUNT_PREFIX,
-                true,
-                "anative",
-                true,
-            ),
-        ],
-        &format!("{dockerfiles_dir}/dockerfile_resources"),
-    )
-    .await
-    .stack()?;
-
-    cn.run_all(true).await.stack()?;
-    cn.wait_with_timeout_all(true, TIMEOUT).await.stack()?;
-    cn.terminate_all().await;
-    Ok(())
 }
 
 async fn hermes_runner(args: &Args) -> Result<()> {
     let hermes_home = args.hermes_home.as_ref().stack()?;
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26000", TIMEOUT)
+    let consumer_id = &ChainSpec::ics_test_consumer().stack()?.chain_id;
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26000")
         .await
         .stack()?;
 
     // get mnemonic from onomyd
-    let mnemonic: String = nm_onomyd.recv().await.stack()?;
+    let HermesMnemonic(mnemonic) = nm_onomyd.expect().await.stack()?;
     // set keys for our chains
     FileOptions::write_str("/root/.hermes/mnemonic.txt", &mnemonic)
         .await
@@ -188,16 +82,16 @@ async fn hermes_runner(args: &Args) -> Result<()> {
         .await
         .stack()?;
     sh_hermes([format!(
-        "keys add --chain {CONSUMER_ID} --mnemonic-file /root/.hermes/mnemonic.txt"
+        "keys add --chain {consumer_id} --mnemonic-file /root/.hermes/mnemonic.txt"
     )])
     .await
     .stack()?;
 
     // wait for setup
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
 
     let ibc_pair =
-        IbcPair::hermes_setup_ics_pair(CONSUMER_ID, "07-tendermint-0", "onomy", "07-tendermint-0")
+        IbcPair::hermes_setup_ics_pair(consumer_id, "07-tendermint-0", "onomy", "07-tendermint-0")
             .await
             .stack()?;
     let mut hermes_runner = hermes_start("/logs/hermes_bootstrap_runner.log")
@@ -206,39 +100,62 @@ async fn hermes_runner(args: &Args) -> Result<()> {
     ibc_pair.hermes_check_acks().await.stack()?;
 
     // tell that chains have been connected
-    nm_onomyd.send::<IbcPair>(&ibc_pair).await.stack()?;
+    nm_onomyd
+        .send(ChainsConnected(ibc_pair.clone()))
+        .await
+        .stack()?;
+
+    // wait for the transfers from onomyd to be relayed
+    let WaitForTransfers {
+        chain_id,
+        timed_out,
+    } = nm_onomyd.expect().await.stack()?;
+    ibc_pair
+        .hermes_wait_for_transfers(&chain_id, &timed_out)
+        .await
+        .stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // signal to update gas denom
-    let ibc_nom = nm_onomyd.recv::<String>().await.stack()?;
+    let GasDenomUpdate(ibc_nom) = nm_onomyd.expect().await.stack()?;
     hermes_runner.terminate(TIMEOUT).await.stack()?;
-    hermes_set_gas_price_denom(hermes_home, CONSUMER_ID, &ibc_nom)
+    hermes_set_gas_price_denom(hermes_home, consumer_id, &ibc_nom)
         .await
         .stack()?;
 
     // restart
     let mut hermes_runner = hermes_start("/logs/hermes_runner.log").await.stack()?;
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
+
+    // wait for the transfer back from the consumer to be relayed
+    let WaitForTransfers {
+        chain_id,
+        timed_out,
+    } = nm_onomyd.expect().await.stack()?;
+    ibc_pair
+        .hermes_wait_for_transfers(&chain_id, &timed_out)
+        .await
+        .stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
     hermes_runner.terminate(TIMEOUT).await.stack()?;
     Ok(())
 }
 
 async fn onomyd_runner(args: &Args) -> Result<()> {
-    let consumer_id = CONSUMER_ID;
+    let consumer = ChainSpec::ics_test_consumer().stack()?;
+    let consumer_id = consumer.chain_id.as_str();
     let daemon_home = args.daemon_home.as_ref().stack()?;
-    let mut nm_hermes =
-        NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("hermes_{}:26000", args.uuid))
-            .await
-            .stack()?;
-    let mut nm_consumer = NetMessenger::connect(
-        STD_TRIES,
-        STD_DELAY,
-        &format!("{}_{}:26001", consumer_binary_name(), args.uuid),
+    let mut nm_hermes = SetupMessenger::connect("hermes", &format!("hermes_{}:26000", args.uuid))
+        .await
+        .stack()?;
+    let mut nm_consumer = SetupMessenger::connect(
+        "consumer",
+        &format!("{}:26001", consumer.hostname(&args.uuid)),
     )
     .await
-    .stack()
     .stack()?;
 
     let cosmores = cosmovisor_setup(CosmosSetupOptions::onomy(daemon_home))
@@ -246,7 +163,7 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
         .stack()?;
     // send mnemonic to hermes
     nm_hermes
-        .send::<String>(&cosmores.hermes_mnemonic.stack()?)
+        .send(HermesMnemonic(cosmores.hermes_mnemonic.stack()?))
         .await
         .stack()?;
 
@@ -258,6 +175,7 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
     let ccvconsumer_state = cosmovisor_add_consumer(
         daemon_home,
+        "validator",
         consumer_id,
         &test_proposal(consumer_id, "anative"),
     )
@@ -266,60 +184,100 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
     // send to consumer
     nm_consumer
-        .send::<String>(&ccvconsumer_state)
+        .send(ConsumerGenesis(ccvconsumer_state))
         .await
         .stack()?;
 
     // send keys
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
-                .await
-                .stack()?,
-        )
-        .await
-        .stack()?;
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/priv_validator_key.json"))
-                .await
-                .stack()?,
-        )
+    let validator_keys = ValidatorKeys {
+        node_key: FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
+            .await
+            .stack()?,
+        priv_validator_key: FileOptions::read_to_string(&format!(
+            "{daemon_home}/config/priv_validator_key.json"
+        ))
         .await
-        .stack()?;
+        .stack()?,
+    };
+    nm_consumer.send(validator_keys).await.stack()?;
 
     // wait for consumer to be online
-    nm_consumer.recv::<()>().await.stack()?;
+    nm_consumer.expect::<Ready>().await.stack()?;
     // notify hermes to connect the chains
-    nm_hermes.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Ready).await.stack()?;
     // when hermes is done
-    let ibc_pair = nm_hermes.recv::<IbcPair>().await.stack()?;
+    let ChainsConnected(ibc_pair) = nm_hermes.expect().await.stack()?;
     info!("IbcPair: {ibc_pair:?}");
 
     // send anom to consumer
+    let consumer_addr = &reprefix_bech32(addr, &consumer.account_prefix).stack()?;
     ibc_pair
         .b
         .cosmovisor_ibc_transfer(
             "validator",
-            &reprefix_bech32(addr, CONSUMER_ACCOUNT_PREFIX).stack()?,
+            consumer_addr,
             &token18(2.0e3, ""),
             "anom",
+            &FeePolicy::chain_default("anom"),
+        )
+        .await
+        .stack()?;
+
+    // send a transfer that times out on the consumer, the fee is fixed so that the
+    // balance right after sending is known even if the refund is already relayed
+    let balance = cosmovisor_get_balances(addr)
+        .await
+        .stack()?
+        .get("anom")
+        .cloned()
+        .stack()?;
+    let timeout_amount = u256!(1000);
+    let timeout_fee = u256!(1000000);
+    let sequence = ibc_pair
+        .b
+        .cosmovisor_ibc_transfer_with_timeout(
+            "validator",
+            consumer_addr,
+            &timeout_amount.to_string(),
+            "anom",
+            &FeePolicy::fixed_fees(&format!("{timeout_fee}anom")).with_auto_gas(1.3),
+            &PacketTimeout::default().timestamp(Duration::from_nanos(1)),
         )
         .await
         .stack()?;
-    // it takes time for the relayer to complete relaying
-    wait_for_num_blocks(4).await.stack()?;
+    let balance_after_send = balance
+        .checked_sub(timeout_amount)
+        .and_then(|balance| balance.checked_sub(timeout_fee))
+        .stack_err(|| "validator cannot afford the timed out transfer")?;
+
+    // wait for the relayer to relay the transfer and the timeout
+    nm_hermes
+        .send(WaitForTransfers::new(&ibc_pair.b.chain_id).timed_out(sequence))
+        .await
+        .stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
+    cosmovisor_wait_for_refund(addr, "anom", balance_after_send, timeout_amount)
+        .await
+        .stack()?;
     // notify consumer that we have sent NOM
-    nm_consumer.send::<IbcPair>(&ibc_pair).await.stack()?;
+    nm_consumer
+        .send(ChainsConnected(ibc_pair.clone()))
+        .await
+        .stack()?;
 
     // tell hermes to restart with updated gas denom on its side
-    let ibc_nom = nm_consumer.recv::<String>().await.stack()?;
-    nm_hermes.send::<String>(&ibc_nom).await.stack()?;
-    nm_hermes.recv::<()>().await.stack()?;
-    nm_consumer.send::<()>(&()).await.stack()?;
+    let gas_denom_update = nm_consumer.expect::<GasDenomUpdate>().await.stack()?;
+    nm_hermes.send(gas_denom_update).await.stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
+    nm_consumer.send(Ready).await.stack()?;
 
     // recieve round trip signal
-    nm_consumer.recv::<()>().await.stack()?;
+    nm_consumer.expect::<Ready>().await.stack()?;
+    nm_hermes
+        .send(WaitForTransfers::new(&ibc_pair.a.chain_id))
+        .await
+        .stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
     // check that the IBC NOM converted back to regular NOM
     ensure_eq!(
         cosmovisor_get_balances("onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3")
@@ -329,8 +287,8 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     );
 
     // signal to collectively terminate
-    nm_hermes.send::<()>(&()).await.stack()?;
-    nm_consumer.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Terminate).await.stack()?;
+    nm_consumer.send(Terminate).await.stack()?;
     cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
 
     FileOptions::write_str(
@@ -345,32 +303,29 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
 async fn consumer(args: &Args) -> Result<()> {
     let daemon_home = args.daemon_home.as_ref().stack()?;
-    let chain_id = CONSUMER_ID;
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26001", TIMEOUT)
+    let consumer = ChainSpec::ics_test_consumer().stack()?;
+    let chain_id = consumer.chain_id.as_str();
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26001")
         .await
         .stack()?;
     // we need the initial consumer state
-    let ccvconsumer_state_s: String = nm_onomyd.recv().await.stack()?;
+    let ConsumerGenesis(ccvconsumer_state_s) = nm_onomyd.expect().await.stack()?;
 
-    cosmovisor_setup(CosmosSetupOptions::new(
-        daemon_home,
-        chain_id,
-        "anative",
-        "anative",
-        Some(&ccvconsumer_state_s),
-    ))
-    .await
-    .stack()?;
+    cosmovisor_setup(consumer.setup_options(Some(&ccvconsumer_state_s)))
+        .await
+        .stack()?;
 
     // get keys
-    let node_key = nm_onomyd.recv::<String>().await.stack()?;
+    let ValidatorKeys {
+        node_key,
+        priv_validator_key,
+    } = nm_onomyd.expect().await.stack()?;
     // we used same keys for consumer as provider, need to copy them over or else
     // the node will not be a working validator for itself
     FileOptions::write_str(&format!("{daemon_home}/config/node_key.json"), &node_key)
         .await
         .stack()?;
 
-    let priv_validator_key = nm_onomyd.recv::<String>().await.stack()?;
     FileOptions::write_str(
         &format!("{daemon_home}/config/priv_validator_key.json"),
         &priv_validator_key,
@@ -386,14 +341,14 @@ async fn consumer(args: &Args) -> Result<()> {
     let addr = &cosmovisor_get_addr("validator").await.stack()?;
 
     // signal that we have started
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // wait for provider to send us stuff
-    let ibc_pair = nm_onomyd.recv::<IbcPair>().await.stack()?;
+    let ChainsConnected(ibc_pair) = nm_onomyd.expect().await.stack()?;
     // get the name of the IBC NOM. Note that we can't do this on the onomyd side,
-    // it has to be with respect to the consumer side
+    // it has to be with respect to the consumer side. This also checks the locally
+    // computed trace against the chain.
     let ibc_nom = &ibc_pair.a.get_ibc_denom("anom").await.stack()?;
-    ensure_eq!(ibc_nom, ONOMY_IBC_NOM);
     let balances = cosmovisor_get_balances(addr).await.stack()?;
     ensure!(balances.contains_key(ibc_nom));
 
@@ -406,19 +361,28 @@ async fn consumer(args: &Args) -> Result<()> {
         .await
         .stack()?;
     // tell hermes to restart with updated gas denom on its side
-    nm_onomyd.send::<String>(ibc_nom).await.stack()?;
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd
+        .send(GasDenomUpdate(ibc_nom.clone()))
+        .await
+        .stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
     info!("restarted with new gas denom");
 
     // test normal transfer
     let dst_addr = &reprefix_bech32(
         "onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3",
-        CONSUMER_ACCOUNT_PREFIX,
+        &consumer.account_prefix,
     )
     .stack()?;
-    cosmovisor_bank_send(addr, dst_addr, "5000", ibc_nom)
-        .await
-        .stack()?;
+    cosmovisor_bank_send(
+        addr,
+        dst_addr,
+        "5000",
+        ibc_nom,
+        &FeePolicy::chain_default(ibc_nom),
+    )
+    .await
+    .stack()?;
     ensure_eq!(
         cosmovisor_get_balances(dst_addr).await.stack()?[ibc_nom],
         u256!(5000)
@@ -426,7 +390,7 @@ async fn consumer(args: &Args) -> Result<()> {
 
     let test_addr = &reprefix_bech32(
         "onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3",
-        PROVIDER_ACCOUNT_PREFIX,
+        &ChainSpec::onomy().stack()?.account_prefix,
     )
     .stack()?;
     info!("sending back to {}", test_addr);
@@ -434,44 +398,45 @@ async fn consumer(args: &Args) -> Result<()> {
     // send some IBC NOM back to origin chain using it as gas
     ibc_pair
         .a
-        .cosmovisor_ibc_transfer("validator", test_addr, "5000", ibc_nom)
+        .cosmovisor_ibc_transfer(
+            "validator",
+            test_addr,
+            "5000",
+            ibc_nom,
+            &FeePolicy::chain_default(ibc_nom),
+        )
         .await
         .stack()?;
-    wait_for_num_blocks(4).await.stack()?;
 
     let pubkey = sh_cosmovisor(["tendermint show-validator"]).await.stack()?;
     let pubkey = pubkey.trim();
-    sh_cosmovisor_tx([
-        "staking",
-        "create-validator",
-        "--commission-max-change-rate",
-        "0.01",
-        "--commission-max-rate",
-        "0.10",
-        "--commission-rate",
-        "0.05",
-        "--from",
-        "validator",
-        "--min-self-delegation",
-        "1",
-        "--amount",
-        &token18(500.0, "anative"),
-        "--fees",
-        &format!("1000000{ONOMY_IBC_NOM}"),
-        "--pubkey",
-        pubkey,
-        "-y",
-        "-b",
-        "block",
-    ])
-    .await
-    .stack()?;
+    CosmovisorCmd::tx("staking create-validator")
+        .args([
+            "--commission-max-change-rate",
+            "0.01",
+            "--commission-max-rate",
+            "0.10",
+            "--commission-rate",
+            "0.05",
+            "--min-self-delegation",
+            "1",
+            "--amount",
+            &token18(500.0, "anative"),
+            "--fees",
+            &format!("1000000{ibc_nom}"),
+            "--pubkey",
+            pubkey,
+        ])
+        .from("validator")
+        .run_tx()
+        .await
+        .stack()?;
 
     // round trip signal
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
 
     // interchain-security-cd does not support this proposal
     /*
@@ -485,23 +450,27 @@ async fn consumer(args: &Args) -> Result<()> {
         "type": "Text",
         "deposit": test_deposit
     });
-    cosmovisor_gov_file_proposal(
+    let proposal_id = cosmovisor_gov_file_proposal(
         daemon_home,
+        "validator",
         None,
         &proposal.to_string(),
-        &format!("1{ibc_nom}"),
+        &FeePolicy::chain_default(ibc_nom),
+        &FeePolicy::chain_default(ibc_nom).with_auto_gas(2.3),
     )
     .await
     .stack()?;
-    let proposals = sh_cosmovisor(["query gov proposals"]).await.stack()?;
-    assert!(proposals.contains("PROPOSAL_STATUS_PASSED"));
+    wait_for_proposal_status(STD_TRIES, STD_DELAY, proposal_id, ProposalStatus::Passed)
+        .await
+        .stack()?;
 
     // but first, test governance with IBC NOM as the token
-    let test_crisis_denom = ONOMY_IBC_NOM;
+    let test_crisis_denom = ibc_nom;
     let test_deposit = token18(500.0, "anative");
     wait_for_num_blocks(1).await.stack()?;
     cosmovisor_gov_file_proposal(
         daemon_home,
+        "validator",
         Some("param-change"),
         &format!(
             r#"
@@ -519,7 +488,8 @@ async fn consumer(args: &Args) -> Result<()> {
     }}
     "#
         ),
-        &format!("1{ibc_nom}"),
+        &FeePolicy::chain_default(ibc_nom),
+        &FeePolicy::chain_default(ibc_nom).with_auto_gas(2.3),
     )
     .await
     .stack()?;
@@ -18,7 +18,7 @@ use onomy_test_lib::{
         stacked_errors::{Error, Result, StackableErr},
         Command, FileOptions,
     },
-    u64_array_bigints::{self, u256},
+    tx::FeePolicy,
     Args, TIMEOUT,
 };
 use tokio::time::sleep;
@@ -49,17 +49,16 @@ async fn main() -> Result<()> {
         )])
         .await
         .stack()?;
-        container_runner(&args, &[(
-            "standalone",
-            &onomy_std_cosmos_daemon(
-                &format!("{CHAIN_ID}d"),
-                &format!(".{CHAIN_ID}"),
-                "v0.1.0",
-                &format!("{CHAIN_ID}d"),
-            ),
-        )])
-        .await
-        .stack()
+        let dockerfile = onomy_std_cosmos_daemon(
+            &format!("{CHAIN_ID}d"),
+            &format!(".{CHAIN_ID}"),
+            "v0.1.0",
+            &format!("{CHAIN_ID}d"),
+        )
+        .stack()?;
+        container_runner(&args, &[("standalone", &dockerfile)])
+            .await
+            .stack()
     }
 }
 
@@ -70,15 +69,17 @@ async fn standalone_runner(args: &Args) -> Result<()> {
     cosmovisor_setup(options).await.stack()?;
     let mut cosmovisor_runner = cosmovisor_start(&format!("{CHAIN_ID}d_runner.log"), None).await?;
 
-    let mut market = Market::new("validator", "1000000anative");
-    market.max_gas = Some(u256!(1000000));
+    let market = Market::new(
+        "validator",
+        FeePolicy::fixed_fees("1000000anative").with_fixed_gas(1000000),
+    );
 
     let addr = &cosmovisor_get_addr("validator").await.stack()?;
     info!("{:?}", cosmovisor_get_balances(addr).await.stack()?);
     let coin_pair = CoinPair::new("afootoken", "anative").stack()?;
 
     // test numerical limits
-    market
+    let pool_drop_uid = market
         .create_pool(&coin_pair, Market::MAX_COIN, Market::MAX_COIN)
         .await
         .stack()?;
@@ -98,7 +99,7 @@ async fn standalone_runner(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market.redeem_drop(1).await.stack()?;
+    market.redeem_drop(pool_drop_uid).await.stack()?;
     market
         .create_order(
             coin_pair.coin_a(),
@@ -110,7 +111,7 @@ async fn standalone_runner(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market
+    let limit_order_uid = market
         .create_order(
             coin_pair.coin_a(),
             coin_pair.coin_b(),
@@ -121,7 +122,7 @@ async fn standalone_runner(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market.cancel_order(6).await.stack()?;
+    market.cancel_order(limit_order_uid).await.stack()?;
 
     sleep(Duration::ZERO).await;
     cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
@@ -0,0 +1,110 @@
+//! Runs an onomyd chain with multiple validators and checks that all of them
+//! are bonded and producing blocks
+
+use common::container_runner;
+use log::info;
+use onomy_test_lib::{
+    cosmovisor::{cosmovisor_start, wait_for_num_blocks, CosmovisorCmd},
+    dockerfiles::dockerfile_onomyd,
+    onomy_std_init,
+    setup_msg::{SetupMessenger, Terminate},
+    setups::{cosmovisor_multi_validator_setup, CosmosSetupOptions, ValidatorRole},
+    super_orchestrator::{
+        stacked_errors::{ensure_eq, Error, Result, StackableErr},
+        stacked_get,
+    },
+    Args, TIMEOUT,
+};
+
+const NUM_VALIDATORS: usize = 3;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = onomy_std_init()?;
+
+    if let Some(ref s) = args.entry_name {
+        match s.as_str() {
+            "onomyd" => coordinator(&args).await,
+            s if s.starts_with("validator") => validator(&args, s).await,
+            _ => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
+        }
+    } else {
+        let dockerfile = dockerfile_onomyd().stack()?;
+        let mut names = vec!["onomyd".to_owned()];
+        for i in 1..NUM_VALIDATORS {
+            names.push(format!("validator{i}"));
+        }
+        let name_and_contents: Vec<(&str, &str)> = names
+            .iter()
+            .map(|name| (name.as_str(), dockerfile.as_str()))
+            .collect();
+        container_runner(&args, &name_and_contents).await.stack()
+    }
+}
+
+async fn coordinator(args: &Args) -> Result<()> {
+    let uuid = &args.uuid;
+    let daemon_home = args.daemon_home.as_ref().stack()?;
+    let validator_hostnames = (1..NUM_VALIDATORS)
+        .map(|i| format!("validator{i}_{uuid}"))
+        .collect();
+    cosmovisor_multi_validator_setup(
+        CosmosSetupOptions::onomy(daemon_home),
+        &format!("onomyd_{uuid}"),
+        ValidatorRole::Coordinator {
+            validator_hostnames,
+        },
+    )
+    .await
+    .stack()?;
+    let mut cosmovisor_runner = cosmovisor_start("onomyd_runner.log", None).await.stack()?;
+    wait_for_num_blocks(2).await.stack()?;
+
+    let validators = CosmovisorCmd::query("staking validators")
+        .output_json()
+        .run_json()
+        .await
+        .stack()?;
+    let validators = stacked_get!(validators["validators"]).as_array().stack()?;
+    info!("{validators:?}");
+    ensure_eq!(validators.len(), NUM_VALIDATORS);
+    for validator in validators {
+        ensure_eq!(
+            stacked_get!(validator["status"]).as_str().stack()?,
+            "BOND_STATUS_BONDED"
+        );
+    }
+
+    // termination signal
+    for i in 1..NUM_VALIDATORS {
+        let validator = format!("validator{i}");
+        let mut nm = SetupMessenger::connect(&validator, &format!("{validator}_{uuid}:26000"))
+            .await
+            .stack()?;
+        nm.send(Terminate).await.stack()?;
+    }
+    cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
+    Ok(())
+}
+
+async fn validator(args: &Args, entry_name: &str) -> Result<()> {
+    let uuid = &args.uuid;
+    let daemon_home = args.daemon_home.as_ref().stack()?;
+    cosmovisor_multi_validator_setup(
+        CosmosSetupOptions::onomy(daemon_home),
+        &format!("{entry_name}_{uuid}"),
+        ValidatorRole::Validator,
+    )
+    .await
+    .stack()?;
+    let mut cosmovisor_runner = cosmovisor_start(&format!("{entry_name}_runner.log"), None)
+        .await
+        .stack()?;
+
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26000")
+        .await
+        .stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
+    cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
+    Ok(())
+}
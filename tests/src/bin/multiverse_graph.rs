@@ -4,27 +4,30 @@ use std::time::Duration;
 
 use log::info;
 use onomy_test_lib::{
+    chain::ChainSpec,
     cosmovisor::{
         cosmovisor_get_addr, cosmovisor_start, fast_block_times, get_self_peer_info,
-        set_persistent_peers, sh_cosmovisor, sh_cosmovisor_no_debug, wait_for_num_blocks,
+        set_persistent_peers, wait_for_num_blocks, CosmovisorCmd,
     },
-    dockerfiles::{
-        dockerfile_hermes, dockerfile_onexd, dockerfile_onomyd, COSMOVISOR, DOWNLOAD_ONEXD,
-        ONEXD_FH_VERSION, ONOMY_STD,
-    },
-    hermes::{hermes_start, sh_hermes, write_hermes_config, HermesChainConfig},
+    dockerfiles::{onexd_release, DockerfileBuilder},
+    hermes::{hermes_start, sh_hermes},
     ibc::IbcPair,
     market::{CoinPair, Market},
     onomy_std_init,
+    setup_msg::{
+        ChainsConnected, ConsumerGenesis, HermesMnemonic, Ready, SetupMessenger, Terminate,
+        ValidatorKeys,
+    },
     setups::{cosmovisor_add_consumer, cosmovisor_setup, test_proposal, CosmosSetupOptions},
     super_orchestrator::{
-        docker::{Container, ContainerNetwork, Dockerfile},
+        docker::{Container, Dockerfile},
         net_message::NetMessenger,
-        remove_files_in_dir, sh,
         stacked_errors::{Error, Result, StackableErr},
         wait_for_ok, Command, FileOptions,
     },
-    u64_array_bigints::{self, u256},
+    topology::{Topology, TopologyNode},
+    tx::FeePolicy,
+    versions::versions,
     Args, STD_DELAY, STD_TRIES, TIMEOUT,
 };
 use tokio::time::sleep;
@@ -80,198 +83,109 @@ provider = [
   { label = "market", details = { type = "firehose", url = "http://localhost:9030/" }},
 ]"#;
 
-#[rustfmt::skip]
-fn standalone_dockerfile() -> String {
-    // use the fh version
-    let version = ONEXD_FH_VERSION;
-    let daemon_name = BINARY_NAME;
-    let daemon_dir_name = BINARY_DIR;
-    format!(
-        r#"{ONOMY_STD}
-# postgres and protobuf dependencies
-RUN dnf install -y postgresql libpq-devel protobuf protobuf-compiler protobuf-devel
-# for debug
-RUN go install github.com/fullstorydev/grpcurl/cmd/grpcurl@latest
-# for cosmovisor
-{COSMOVISOR}
-
-# interfacing with the running graph
-RUN npm install -g @graphprotocol/graph-cli
-
-# firehose
-RUN git clone --depth 1 --branch v0.7.1 https://github.com/figment-networks/firehose-cosmos
-# not working for me, too flaky
-#RUN cd /firehose-cosmos && make install
-ADD https://github.com/graphprotocol/firehose-cosmos/releases/download/v0.6.0/firecosmos_linux_amd64 /usr/bin/firecosmos
-RUN chmod +x /usr/bin/firecosmos
-
-# graph-node
-RUN git clone --depth 1 --branch v0.33.0 https://github.com/graphprotocol/graph-node
-RUN cd /graph-node && cargo build --release -p graph-node
-
-# ipfs
-ADD https://dist.ipfs.tech/kubo/v0.25.0/kubo_v0.25.0_linux-amd64.tar.gz /tmp/kubo.tar.gz
-RUN cd /tmp && tar -xf /tmp/kubo.tar.gz && mv /tmp/kubo/ipfs /usr/bin/ipfs
-RUN ipfs init
-
-# our subgraph
-RUN git clone https://github.com/onomyprotocol/mgraph
-#ADD ./dockerfile_resources/mgraph /mgraph
-RUN cd /mgraph && npm install && npm run build
-
-ENV DAEMON_NAME="{daemon_name}"
-ENV DAEMON_HOME="/root/{daemon_dir_name}"
-ENV DAEMON_VERSION={version}
-
-{DOWNLOAD_ONEXD}
-
-# for manual testing
-RUN chmod +x $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}
-
-# set up symbolic links
-RUN cosmovisor init $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}
-
-# some commands don't like if the data directory does not exist
-RUN mkdir $DAEMON_HOME/data
-
-RUN mkdir /firehose
-RUN mkdir /firehose/data
-"#
-    )
+fn standalone_dockerfile() -> Result<String> {
+    let versions = versions().stack()?;
+    DockerfileBuilder::onomy_std()
+        // postgres and protobuf dependencies
+        .dnf_packages([
+            "postgresql",
+            "libpq-devel",
+            "protobuf",
+            "protobuf-compiler",
+            "protobuf-devel",
+        ])
+        .comment("for debug")
+        .run("go install github.com/fullstorydev/grpcurl/cmd/grpcurl@latest")
+        .cosmovisor(&versions.cosmovisor)
+        .comment("interfacing with the running graph")
+        .run("npm install -g @graphprotocol/graph-cli")
+        .comment("firehose")
+        .run(
+            "git clone --depth 1 --branch v0.7.1 \
+             https://github.com/figment-networks/firehose-cosmos",
+        )
+        // not working for me, too flaky
+        //.run("cd /firehose-cosmos && make install")
+        .add_url(
+            "https://github.com/graphprotocol/firehose-cosmos/releases/download/v0.6.0/\
+             firecosmos_linux_amd64",
+            "/usr/bin/firecosmos",
+        )
+        .run("chmod +x /usr/bin/firecosmos")
+        .comment("graph-node")
+        .run("git clone --depth 1 --branch v0.33.0 https://github.com/graphprotocol/graph-node")
+        .run("cd /graph-node && cargo build --release -p graph-node")
+        .comment("ipfs")
+        .add_url(
+            "https://dist.ipfs.tech/kubo/v0.25.0/kubo_v0.25.0_linux-amd64.tar.gz",
+            "/tmp/kubo.tar.gz",
+        )
+        .run("cd /tmp && tar -xf /tmp/kubo.tar.gz && mv /tmp/kubo/ipfs /usr/bin/ipfs")
+        .run("ipfs init")
+        .comment("our subgraph")
+        .run("git clone https://github.com/onomyprotocol/mgraph")
+        //.add_resource("mgraph", "/mgraph")
+        .run("cd /mgraph && npm install && npm run build")
+        // use the fh version
+        .cosmovisor_daemon(
+            BINARY_NAME,
+            BINARY_DIR,
+            &versions.onexd_fh,
+            &onexd_release(&versions.onexd_fh),
+        )
+        .run("mkdir /firehose")
+        .run("mkdir /firehose/data")
+        .build()
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = onomy_std_init()?;
 
-    if let Some(ref s) = args.entry_name {
-        match s.as_str() {
-            "test_runner" => test_runner(&args).await,
-            "onex_node" => onex_node(&args).await,
-            "onomyd" => onomyd_runner(&args).await,
-            "hermes" => hermes_runner().await,
-            _ => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
-        }
-    } else {
-        container_runner(&args).await.stack()
-    }
-}
-
-async fn container_runner(args: &Args) -> Result<()> {
-    let logs_dir = "./tests/logs";
-    let dockerfiles_dir = "./tests/dockerfiles";
-    let bin_entrypoint = &args.bin_name;
-    let container_target = "x86_64-unknown-linux-gnu";
-
-    // build internal runner with `--release`
-    sh([
-        "cargo build --release --bin",
-        bin_entrypoint,
-        "--target",
-        container_target,
-    ])
-    .await
-    .stack()?;
-
-    // prepare volumed resources
-    remove_files_in_dir("./tests/resources/keyring-test/", &[".address", ".info"])
-        .await
-        .stack()?;
-
-    let entrypoint = &format!("./target/{container_target}/release/{bin_entrypoint}");
-
-    // we use a normal onexd for the validator full node, but use the `-fh` version
-    // for the full node that indexes for firehose
-    let mut containers =
-        vec![
-            Container::new("test_runner", Dockerfile::contents(standalone_dockerfile()))
-                .external_entrypoint(entrypoint, ["--entry-name", "test_runner"])
-                .await
-                .stack()?,
-        ];
-    containers.extend_from_slice(&[
-        Container::new("onex_node", Dockerfile::contents(dockerfile_onexd()))
-            .external_entrypoint(entrypoint, ["--entry-name", "onex_node"])
-            .await
-            .stack()?
-            .volume(
-                "./tests/resources/keyring-test",
-                format!("/root/{}/keyring-test", BINARY_DIR),
-            ),
-        Container::new(
-            "hermes",
-            Dockerfile::contents(dockerfile_hermes("__tmp_hermes_config.toml")),
+    let topology = Topology::new()
+        .timeout(Duration::from_secs(9999))
+        .node(TopologyNode::new(
+            "test_runner",
+            &standalone_dockerfile().stack()?,
+        ))
+        .node(
+            TopologyNode::chain("onex_node", ChainSpec::onex().stack()?)
+                .container_name("onex_node"),
         )
-        .external_entrypoint(entrypoint, ["--entry-name", "hermes"])
-        .await
-        .stack()?,
-        Container::new("onomyd", Dockerfile::contents(dockerfile_onomyd()))
-            .external_entrypoint(entrypoint, ["--entry-name", "onomyd"])
-            .await
-            .stack()?
-            .volume(
-                "./tests/resources/keyring-test",
-                "/root/.onomy/keyring-test",
-            ),
-    ]);
-
-    let mut cn =
-        ContainerNetwork::new("test", containers, Some(dockerfiles_dir), true, logs_dir).stack()?;
-    cn.add_common_volumes([(logs_dir, "/logs")]);
-    let uuid = cn.uuid_as_string();
-    cn.add_common_entrypoint_args(["--uuid", &uuid]);
-    cn.add_container(
-        Container::new("postgres", Dockerfile::name_tag("postgres:16"))
-            .environment_vars([
-                ("POSTGRES_PASSWORD", "root"),
-                ("POSTGRES_USER", "postgres"),
-                ("POSTGRES_DB", "graph-node"),
-                ("POSTGRES_INITDB_ARGS", "-E UTF8 --locale=C"),
-            ])
-            .no_uuid_for_host_name(),
-    )
-    .stack()?;
-
-    // prepare hermes config
-    write_hermes_config(
-        &[
-            HermesChainConfig::new(
-                "onomy",
-                &format!("onomyd_{uuid}"),
-                "onomy",
-                false,
-                "anom",
-                true,
-            ),
-            HermesChainConfig::new(
-                CHAIN_ID,
-                &format!("onex_node_{uuid}"),
-                "onomy",
-                true,
-                "aonex",
-                true,
-            ),
-        ],
-        &format!("{dockerfiles_dir}/dockerfile_resources"),
-    )
-    .await
-    .stack()?;
-
-    cn.run_all(true).await.stack()?;
-    cn.wait_with_timeout_all(true, Duration::from_secs(9999))
+        .node(TopologyNode::hermes("hermes"))
+        .node(TopologyNode::chain("onomyd", ChainSpec::onomy().stack()?))
+        .service(
+            Container::new("postgres", Dockerfile::name_tag("postgres:16"))
+                .environment_vars([
+                    ("POSTGRES_PASSWORD", "root"),
+                    ("POSTGRES_USER", "postgres"),
+                    ("POSTGRES_DB", "graph-node"),
+                    ("POSTGRES_INITDB_ARGS", "-E UTF8 --locale=C"),
+                ])
+                .no_uuid_for_host_name(),
+        )
+        .ibc_link("onomyd", "onex_node");
+
+    let args = &args;
+    topology
+        .run(args, |entry_name| async move {
+            match entry_name.as_str() {
+                "test_runner" => test_runner(args).await,
+                "onex_node" => onex_node(args).await,
+                "onomyd" => onomyd_runner(args).await,
+                "hermes" => hermes_runner().await,
+                s => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
+            }
+        })
         .await
-        .stack()?;
-    cn.terminate_all().await;
-    Ok(())
 }
 
 async fn test_runner(args: &Args) -> Result<()> {
     let uuid = &args.uuid;
 
-    let mut nm_onomyd =
-        NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("onomyd_{uuid}:26000"))
-            .await
-            .stack()?;
+    let mut nm_onomyd = SetupMessenger::connect("onomyd", &format!("onomyd_{uuid}:26000"))
+        .await
+        .stack()?;
     let mut nm_onex_node =
         NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("onex_node_{uuid}:26000"))
             .await
@@ -325,13 +239,23 @@ async fn test_runner(args: &Args) -> Result<()> {
     .stack()?.assert_success().stack()?;
     */
 
-    sh_cosmovisor(["config chain-id --home /firehose", CHAIN_ID])
+    CosmovisorCmd::new("config chain-id")
+        .arg(CHAIN_ID)
+        .home("/firehose")
+        .debug(true)
+        .run()
         .await
         .stack()?;
-    sh_cosmovisor(["config keyring-backend test --home /firehose"])
+    CosmovisorCmd::new("config keyring-backend test")
+        .home("/firehose")
+        .debug(true)
+        .run()
         .await
         .stack()?;
-    sh_cosmovisor_no_debug(["init --overwrite --home /firehose", CHAIN_ID])
+    CosmovisorCmd::new("init --overwrite")
+        .arg(CHAIN_ID)
+        .home("/firehose")
+        .run()
         .await
         .stack()?;
     // TODO only for validators?
@@ -445,7 +369,7 @@ async fn test_runner(args: &Args) -> Result<()> {
 
     sleep(Duration::from_secs(9999)).await;
 
-    nm_onomyd.send(&()).await.stack()?;
+    nm_onomyd.send(Terminate).await.stack()?;
 
     sleep(Duration::ZERO).await;
     graph_runner.terminate().await.stack()?;
@@ -456,12 +380,12 @@ async fn test_runner(args: &Args) -> Result<()> {
 }
 
 async fn hermes_runner() -> Result<()> {
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26000", TIMEOUT)
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26000")
         .await
         .stack()?;
 
     // get mnemonic from onomyd
-    let mnemonic: String = nm_onomyd.recv().await.stack()?;
+    let HermesMnemonic(mnemonic) = nm_onomyd.expect().await.stack()?;
     // set keys for our chains
     FileOptions::write_str("/root/.hermes/mnemonic.txt", &mnemonic)
         .await
@@ -476,7 +400,7 @@ async fn hermes_runner() -> Result<()> {
     .stack()?;
 
     // wait for setup
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
 
     let ibc_pair =
         IbcPair::hermes_setup_ics_pair(CHAIN_ID, "07-tendermint-0", "onomy", "07-tendermint-0")
@@ -488,10 +412,10 @@ async fn hermes_runner() -> Result<()> {
     ibc_pair.hermes_check_acks().await.stack()?;
 
     // tell that chains have been connected
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(ChainsConnected(ibc_pair)).await.stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
     hermes_runner.terminate(TIMEOUT).await.stack()?;
     Ok(())
 }
@@ -500,25 +424,22 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     let uuid = &args.uuid;
     let consumer_id = CHAIN_ID;
     let daemon_home = args.daemon_home.as_ref().stack()?;
-    let mut nm_test = NetMessenger::listen("0.0.0.0:26000", TIMEOUT)
+    let mut nm_test = SetupMessenger::listen("test_runner", "0.0.0.0:26000")
+        .await
+        .stack()?;
+    let mut nm_hermes = SetupMessenger::connect("hermes", &format!("hermes_{uuid}:26000"))
+        .await
+        .stack()?;
+    let mut nm_consumer = SetupMessenger::connect("onex_node", &format!("onex_node_{uuid}:26001"))
         .await
         .stack()?;
-    let mut nm_hermes =
-        NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("hermes_{uuid}:26000"))
-            .await
-            .stack()?;
-    let mut nm_consumer =
-        NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("onex_node_{uuid}:26001"))
-            .await
-            .stack()
-            .stack()?;
 
     let mut options = CosmosSetupOptions::onomy(daemon_home);
     options.large_test_amount = true;
     let cosmores = cosmovisor_setup(options).await.stack()?;
     // send mnemonic to hermes
     nm_hermes
-        .send::<String>(&cosmores.hermes_mnemonic.stack()?)
+        .send(HermesMnemonic(cosmores.hermes_mnemonic.stack()?))
         .await
         .stack()?;
 
@@ -530,6 +451,7 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
     let ccvconsumer_state = cosmovisor_add_consumer(
         daemon_home,
+        "validator",
         consumer_id,
         &test_proposal(consumer_id, "aonex"),
     )
@@ -538,46 +460,41 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
     // send to consumer
     nm_consumer
-        .send::<String>(&ccvconsumer_state)
+        .send(ConsumerGenesis(ccvconsumer_state))
         .await
         .stack()?;
 
     // send keys
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
-                .await
-                .stack()?,
-        )
-        .await
-        .stack()?;
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/priv_validator_key.json"))
-                .await
-                .stack()?,
-        )
+    let validator_keys = ValidatorKeys {
+        node_key: FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
+            .await
+            .stack()?,
+        priv_validator_key: FileOptions::read_to_string(&format!(
+            "{daemon_home}/config/priv_validator_key.json"
+        ))
         .await
-        .stack()?;
+        .stack()?,
+    };
+    nm_consumer.send(validator_keys).await.stack()?;
 
     // wait for consumer to be online
-    nm_consumer.recv::<()>().await.stack()?;
+    nm_consumer.expect::<Ready>().await.stack()?;
 
     // notify hermes to connect the chains
-    nm_hermes.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Ready).await.stack()?;
     // when hermes is done
-    nm_hermes.recv::<()>().await.stack()?;
+    nm_hermes.expect::<ChainsConnected>().await.stack()?;
 
     // notify main runner that we should be ready
-    nm_test.send::<()>(&()).await.stack()?;
+    nm_test.send(Ready).await.stack()?;
     // notify onexd runner to make some test transactions
-    nm_consumer.send::<()>(&()).await.stack()?;
+    nm_consumer.send(Ready).await.stack()?;
 
-    nm_test.recv::<()>().await.stack()?;
+    nm_test.expect::<Terminate>().await.stack()?;
 
     // signal to collectively terminate
-    nm_hermes.send::<()>(&()).await.stack()?;
-    nm_consumer.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Terminate).await.stack()?;
+    nm_consumer.send(Terminate).await.stack()?;
     cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
 
     Ok(())
@@ -590,11 +507,11 @@ async fn onex_node(args: &Args) -> Result<()> {
     let mut nm_test = NetMessenger::listen("0.0.0.0:26000", TIMEOUT)
         .await
         .stack()?;
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26001", TIMEOUT)
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26001")
         .await
         .stack()?;
     // we need the initial consumer state
-    let ccvconsumer_state_s: String = nm_onomyd.recv().await.stack()?;
+    let ConsumerGenesis(ccvconsumer_state_s) = nm_onomyd.expect().await.stack()?;
 
     let mut options = CosmosSetupOptions::new(
         daemon_home,
@@ -607,14 +524,16 @@ async fn onex_node(args: &Args) -> Result<()> {
     cosmovisor_setup(options).await.stack()?;
 
     // get keys
-    let node_key = nm_onomyd.recv::<String>().await.stack()?;
+    let ValidatorKeys {
+        node_key,
+        priv_validator_key,
+    } = nm_onomyd.expect().await.stack()?;
     // we used same keys for consumer as producer, need to copy them over or else
     // the node will not be a working validator for itself
     FileOptions::write_str(&format!("{daemon_home}/config/node_key.json"), &node_key)
         .await
         .stack()?;
 
-    let priv_validator_key = nm_onomyd.recv::<String>().await.stack()?;
     FileOptions::write_str(
         &format!("{daemon_home}/config/priv_validator_key.json"),
         &priv_validator_key,
@@ -628,7 +547,7 @@ async fn onex_node(args: &Args) -> Result<()> {
             .stack()?;
 
     // signal that we have started
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // do this after the final genesis is assembled
     let genesis_s = FileOptions::read_to_string(&format!("{daemon_home}/config/genesis.json"))
@@ -644,15 +563,17 @@ async fn onex_node(args: &Args) -> Result<()> {
         .await
         .stack()?;
 
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
 
     // wait for Hermes to be done
     wait_for_num_blocks(5).await.stack()?;
 
     let coin_pair = CoinPair::new("afootoken", "aonex").stack()?;
-    let mut market = Market::new("validator", "1000000aonex");
-    market.max_gas = Some(u256!(1000000));
-    market
+    let market = Market::new(
+        "validator",
+        FeePolicy::fixed_fees("1000000aonex").with_fixed_gas(1000000),
+    );
+    let pool_drop_uid = market
         .create_pool(&coin_pair, Market::MAX_COIN, Market::MAX_COIN)
         .await
         .stack()?;
@@ -672,7 +593,7 @@ async fn onex_node(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market.redeem_drop(1).await.stack()?;
+    market.redeem_drop(pool_drop_uid).await.stack()?;
     market
         .create_order(
             coin_pair.coin_a(),
@@ -697,7 +618,7 @@ async fn onex_node(args: &Args) -> Result<()> {
         .stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
 
     cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
 
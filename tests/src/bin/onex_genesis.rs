@@ -28,38 +28,37 @@ use std::time::Duration;
 
 use log::info;
 use onomy_test_lib::{
+    chain::ChainSpec,
     cosmovisor::{
         cosmovisor_bank_send, cosmovisor_get_addr, cosmovisor_get_balances,
         cosmovisor_gov_file_proposal, cosmovisor_start, fast_block_times, set_minimum_gas_price,
-        sh_cosmovisor, sh_cosmovisor_no_debug, sh_cosmovisor_tx, wait_for_num_blocks,
-    },
-    dockerfiles::{dockerfile_hermes, dockerfile_onexd, dockerfile_onomyd},
-    hermes::{
-        hermes_set_gas_price_denom, hermes_start, sh_hermes, write_hermes_config,
-        HermesChainConfig, IbcPair,
+        sh_cosmovisor, sh_cosmovisor_no_debug, wait_for_num_blocks, CosmovisorCmd,
     },
+    genesis::GenesisBuilder,
+    gov::{wait_for_proposal_status, ProposalStatus},
+    hermes::{hermes_set_gas_price_denom, hermes_start, sh_hermes, IbcPair},
     market::{CoinPair, Market},
     onomy_std_init, reprefix_bech32,
+    setup_msg::{
+        ChainsConnected, ConsumerGenesis, GasDenomUpdate, HermesMnemonic, Ready, SetupMessenger,
+        Terminate, ValidatorKeys, WaitForTransfers,
+    },
     setups::{cosmovisor_add_consumer, cosmovisor_setup, CosmosSetupOptions},
     super_orchestrator::{
-        docker::{Container, ContainerNetwork, Dockerfile},
-        net_message::NetMessenger,
-        remove_files_in_dir, sh,
         stacked_errors::{ensure, ensure_eq, Error, Result, StackableErr},
         stacked_get, stacked_get_mut, Command, FileOptions,
     },
     token18,
+    topology::{Topology, TopologyNode},
+    tx::FeePolicy,
     u64_array_bigints::{
         u256, {self},
     },
-    yaml_str_to_json_value, Args, ONOMY_IBC_NOM, STD_DELAY, STD_TRIES, TIMEOUT,
+    yaml_str_to_json_value, Args, STD_DELAY, STD_TRIES, TIMEOUT,
 };
 use serde_json::{json, Value};
 use tokio::time::sleep;
 
-const PROVIDER_ACCOUNT_PREFIX: &str = "onomy";
-const CONSUMER_ACCOUNT_PREFIX: &str = "onomy";
-
 const HERMES_MNEMONIC: &str = "suspect glove east just retreat relax south garment ketchup salmon \
                                chicken toilet nasty coach stairs logic churn solve super seminar \
                                dune midnight monitor peace";
@@ -78,73 +77,45 @@ pub async fn onexd_setup(
         .stack()?;
     let genesis_file_path = format!("{daemon_home}/config/genesis.json");
 
-    let genesis_s = FileOptions::read_to_string("/resources/tmp/genesis.json")
+    let mut genesis = GenesisBuilder::from_file("/resources/tmp/genesis.json")
         .await
         .stack()?;
 
-    let mut genesis: Value = serde_json::from_str(&genesis_s).stack()?;
-
     let time = chrono::offset::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
 
     // NOTE comment this out for the last test
-    *stacked_get_mut!(genesis["genesis_time"]) = time.into();
+    genesis.genesis_time(&time).stack()?;
 
     // put some aONEX balance on our account so it can be bonded
-    let array = genesis["app_state"]["bank"]["balances"]
-        .as_array_mut()
-        .stack()?;
-    for balance in array {
-        if balance["address"].as_str().unwrap() == "onomy1yks83spz6lvrrys8kh0untt22399tskk6jafcv" {
-            balance["coins"].as_array_mut().unwrap().insert(
-                1,
-                json!({"denom": "aonex", "amount": "20000000000000000000000000000"}),
-            );
-            break
-        }
-    }
+    genesis
+        .add_balance(
+            "onomy1yks83spz6lvrrys8kh0untt22399tskk6jafcv",
+            "20000000000000000000000000000",
+            "aonex",
+        )
+        .stack()?;
 
     // need to add the hermes account manually
-    stacked_get_mut!(genesis["app_state"]["auth"]["accounts"])
-        .as_array_mut()
-        .stack()?
-        .push(json!(
-            {
-                "@type": "/cosmos.auth.v1beta1.BaseAccount",
-                "address": "onomy1p8zprjj83p7elv0dpjeefexrdjpqhj29tw7gre",
-                "pub_key": null,
-                "account_number": "0",
-                "sequence": "0"
-            }
-        ));
-    stacked_get_mut!(genesis["app_state"]["bank"]["balances"])
-        .as_array_mut()
-        .stack()?
-        .push(json!(
-            {
-            "address": "onomy1p8zprjj83p7elv0dpjeefexrdjpqhj29tw7gre",
-            "coins": [
-                {
-                    "denom": "aonex",
-                    "amount": "100000000000000000000"
-                }
-            ]
-            }
-        ));
-
-    let ccvconsumer_state: Value = serde_json::from_str(ccvconsumer_state_s).stack()?;
+    genesis
+        .add_account_with_balance(
+            "onomy1p8zprjj83p7elv0dpjeefexrdjpqhj29tw7gre",
+            "100000000000000000000",
+            "aonex",
+        )
+        .stack()?;
 
     // NOTE comment this out for the last test
-    *stacked_get_mut!(genesis["app_state"]["ccvconsumer"]) = ccvconsumer_state;
+    genesis.ccvconsumer_state(ccvconsumer_state_s).stack()?;
 
     // decrease the governing period for fast tests
     let gov_period = "800ms";
-    let gov_period: Value = gov_period.into();
-    *stacked_get_mut!(genesis["app_state"]["gov"]["voting_params"]["voting_period"]) =
-        gov_period.clone();
-    *stacked_get_mut!(genesis["app_state"]["gov"]["deposit_params"]["max_deposit_period"]) =
-        gov_period;
+    genesis
+        .gov_voting_period(gov_period)
+        .stack()?
+        .gov_max_deposit_period(gov_period)
+        .stack()?;
 
-    let genesis_s = genesis.to_string();
+    let genesis_s = genesis.to_json_string().stack()?;
 
     FileOptions::write_str(&genesis_file_path, &genesis_s)
         .await
@@ -185,28 +156,6 @@ async fn main() -> Result<()> {
 }
 
 async fn container_runner(args: &Args) -> Result<()> {
-    let logs_dir = "./tests/logs";
-    let dockerfiles_dir = "./tests/dockerfiles";
-    let bin_entrypoint = &args.bin_name;
-    let container_target = "x86_64-unknown-linux-gnu";
-
-    // build internal runner with `--release`
-    sh([
-        "cargo build --release --bin",
-        bin_entrypoint,
-        "--target",
-        container_target,
-        "--features",
-        "onex_genesis",
-    ])
-    .await
-    .stack()?;
-
-    // prepare volumed resources
-    remove_files_in_dir("./tests/resources/keyring-test/", &[".address", ".info"])
-        .await
-        .stack()?;
-
     // put in the genesis files
     FileOptions::copy(
         args.genesis_path
@@ -233,8 +182,10 @@ async fn container_runner(args: &Args) -> Result<()> {
         .as_str()
         .stack()?
         .to_owned();
+    let mut consumer = ChainSpec::onex().stack()?;
+    consumer.chain_id = consumer_id.clone();
 
-    let mut onomyd_args = vec!["--entry-name", "onomyd", "--consumer-id", &consumer_id];
+    let mut onomyd_args = vec!["--consumer-id", &consumer_id];
     if let Some(mnemonic_path) = args.mnemonic_path.as_deref() {
         FileOptions::copy(mnemonic_path, "./tests/resources/tmp/mnemonic.txt")
             .await
@@ -242,100 +193,35 @@ async fn container_runner(args: &Args) -> Result<()> {
         onomyd_args.extend(["--mnemonic-path", "/resources/tmp/mnemonic.txt"])
     }
 
-    let entrypoint = &format!("./target/{container_target}/release/{bin_entrypoint}");
-
-    let mut cn = ContainerNetwork::new(
-        "test",
-        vec![
-            Container::new(
-                "hermes",
-                Dockerfile::contents(dockerfile_hermes("__tmp_hermes_config.toml")),
-            )
-            .external_entrypoint(entrypoint, [
-                "--entry-name",
-                "hermes",
-                "--consumer-id",
-                &consumer_id,
-            ])
-            .await
-            .stack()?,
-            Container::new("onomyd", Dockerfile::contents(dockerfile_onomyd()))
-                .external_entrypoint(entrypoint, onomyd_args)
-                .await
-                .stack()?
-                .volumes([
-                    (
-                        "./tests/resources/keyring-test",
-                        "/root/.onomy/keyring-test",
-                    ),
-                    ("./tests/resources/tmp", "/resources/tmp"),
-                ]),
-            Container::new("consumer", Dockerfile::Contents(dockerfile_onexd()))
-                .external_entrypoint(entrypoint, [
-                    "--entry-name",
-                    "consumer",
-                    "--consumer-id",
-                    &consumer_id,
-                ])
-                .await
-                .stack()?
-                .volumes([
-                    (
-                        "./tests/resources/keyring-test",
-                        "/root/.onomy_onex/keyring-test",
-                    ),
-                    ("./tests/resources/tmp", "/resources/tmp"),
-                ]),
-        ],
-        Some(dockerfiles_dir),
-        true,
-        logs_dir,
-    )
-    .stack()?;
-    cn.add_common_volumes([(logs_dir, "/logs")]);
-    let uuid = cn.uuid_as_string();
-    cn.add_common_entrypoint_args(["--uuid", &uuid]);
-
-    // prepare hermes config
-    write_hermes_config(
-        &[
-            HermesChainConfig::new(
-                "onomy",
-                &format!("onomyd_{uuid}"),
-                "onomy",
-                false,
-                "anom",
-                true,
-            ),
-            HermesChainConfig::new(
-                &consumer_id,
-                &format!("consumer_{uuid}"),
-                CONSUMER_ACCOUNT_PREFIX,
-                true,
-                "aonex",
-                true,
-            ),
-        ],
-        &format!("{dockerfiles_dir}/dockerfile_resources"),
-    )
-    .await
-    .stack()?;
-
-    cn.run_all(true).await.stack()?;
-    cn.wait_with_timeout_all(true, TIMEOUT).await.stack()?;
-    cn.terminate_all().await;
-    Ok(())
+    Topology::new()
+        .build_args(["--features", "onex_genesis"])
+        .node(TopologyNode::hermes("hermes").entrypoint_args(["--consumer-id", &consumer_id]))
+        .node(
+            TopologyNode::chain("onomyd", ChainSpec::onomy().stack()?)
+                .entrypoint_args(onomyd_args)
+                .volume("./tests/resources/tmp", "/resources/tmp"),
+        )
+        .node(
+            TopologyNode::chain("consumer", consumer)
+                .container_name("consumer")
+                .entrypoint_args(["--consumer-id", &consumer_id])
+                .volume("./tests/resources/tmp", "/resources/tmp"),
+        )
+        .ibc_link("onomyd", "consumer")
+        .run_containers(args)
+        .await
+        .stack()
 }
 
 async fn hermes_runner(args: &Args) -> Result<()> {
     let hermes_home = args.hermes_home.as_ref().stack()?;
     let consumer_id = args.consumer_id.as_deref().stack()?;
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26000", TIMEOUT)
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26000")
         .await
         .stack()?;
 
     // get mnemonic from onomyd
-    let mnemonic: String = nm_onomyd.recv().await.stack()?;
+    let HermesMnemonic(mnemonic) = nm_onomyd.expect().await.stack()?;
     // set keys for our chains
     FileOptions::write_str("/root/.hermes/mnemonic.txt", &mnemonic)
         .await
@@ -353,7 +239,7 @@ async fn hermes_runner(args: &Args) -> Result<()> {
     .stack()?;
 
     // wait for setup
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
 
     let ibc_pair =
         IbcPair::hermes_setup_ics_pair(consumer_id, "07-tendermint-0", "onomy", "07-tendermint-0")
@@ -365,10 +251,24 @@ async fn hermes_runner(args: &Args) -> Result<()> {
     ibc_pair.hermes_check_acks().await.stack()?;
 
     // tell that chains have been connected
-    nm_onomyd.send::<IbcPair>(&ibc_pair).await.stack()?;
+    nm_onomyd
+        .send(ChainsConnected(ibc_pair.clone()))
+        .await
+        .stack()?;
+
+    // wait for the transfer from onomyd to be relayed
+    let WaitForTransfers {
+        chain_id,
+        timed_out,
+    } = nm_onomyd.expect().await.stack()?;
+    ibc_pair
+        .hermes_wait_for_transfers(&chain_id, &timed_out)
+        .await
+        .stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // signal to update gas denom
-    let ibc_nom = nm_onomyd.recv::<String>().await.stack()?;
+    let GasDenomUpdate(ibc_nom) = nm_onomyd.expect().await.stack()?;
     hermes_runner.terminate(TIMEOUT).await.stack()?;
     hermes_set_gas_price_denom(hermes_home, consumer_id, &ibc_nom)
         .await
@@ -376,10 +276,21 @@ async fn hermes_runner(args: &Args) -> Result<()> {
 
     // restart
     let mut hermes_runner = hermes_start("/logs/hermes_runner.log").await.stack()?;
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
+
+    // wait for the transfer back from the consumer to be relayed
+    let WaitForTransfers {
+        chain_id,
+        timed_out,
+    } = nm_onomyd.expect().await.stack()?;
+    ibc_pair
+        .hermes_wait_for_transfers(&chain_id, &timed_out)
+        .await
+        .stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
     hermes_runner.terminate(TIMEOUT).await.stack()?;
     Ok(())
 }
@@ -388,14 +299,12 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     let uuid = &args.uuid;
     let consumer_id = args.consumer_id.as_deref().stack()?;
     let daemon_home = args.daemon_home.as_ref().stack()?;
-    let mut nm_hermes =
-        NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("hermes_{uuid}:26000"))
-            .await
-            .stack()?;
-    let mut nm_consumer =
-        NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("consumer_{uuid}:26001"))
-            .await
-            .stack()?;
+    let mut nm_hermes = SetupMessenger::connect("hermes", &format!("hermes_{uuid}:26000"))
+        .await
+        .stack()?;
+    let mut nm_consumer = SetupMessenger::connect("consumer", &format!("consumer_{uuid}:26001"))
+        .await
+        .stack()?;
 
     let mut options = CosmosSetupOptions::onomy(daemon_home);
     if let Some(ref mnemonic_path) = args.mnemonic_path {
@@ -421,7 +330,7 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
     // send mnemonic to hermes
     nm_hermes
-        .send::<String>(&cosmores.validator_mnemonic.stack()?)
+        .send(HermesMnemonic(cosmores.validator_mnemonic.stack()?))
         .await
         .stack()?;
 
@@ -441,40 +350,36 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     *stacked_get_mut!(proposal["spawn_time"]) = time.into();
     let proposal = &proposal.to_string();
     info!("PROPOSAL: {proposal}");
-    let ccvconsumer_state = cosmovisor_add_consumer(daemon_home, consumer_id, proposal)
-        .await
-        .stack()?;
+    let ccvconsumer_state =
+        cosmovisor_add_consumer(daemon_home, "validator", consumer_id, proposal)
+            .await
+            .stack()?;
 
     // send to consumer
     nm_consumer
-        .send::<String>(&ccvconsumer_state)
+        .send(ConsumerGenesis(ccvconsumer_state))
         .await
         .stack()?;
 
     // send keys
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
-                .await
-                .stack()?,
-        )
-        .await
-        .stack()?;
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/priv_validator_key.json"))
-                .await
-                .stack()?,
-        )
+    let validator_keys = ValidatorKeys {
+        node_key: FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
+            .await
+            .stack()?,
+        priv_validator_key: FileOptions::read_to_string(&format!(
+            "{daemon_home}/config/priv_validator_key.json"
+        ))
         .await
-        .stack()?;
+        .stack()?,
+    };
+    nm_consumer.send(validator_keys).await.stack()?;
 
     // wait for consumer to be online
-    nm_consumer.recv::<()>().await.stack()?;
+    nm_consumer.expect::<Ready>().await.stack()?;
     // notify hermes to connect the chains
-    nm_hermes.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Ready).await.stack()?;
     // when hermes is done
-    let ibc_pair = nm_hermes.recv::<IbcPair>().await.stack()?;
+    let ChainsConnected(ibc_pair) = nm_hermes.expect().await.stack()?;
     info!("IbcPair: {ibc_pair:?}");
 
     // send anom to consumer
@@ -482,25 +387,38 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
         .b
         .cosmovisor_ibc_transfer(
             "validator",
-            &reprefix_bech32(addr, CONSUMER_ACCOUNT_PREFIX).stack()?,
+            &reprefix_bech32(addr, &ChainSpec::onex().stack()?.account_prefix).stack()?,
             &token18(2.0e3, ""),
             "anom",
+            &FeePolicy::chain_default("anom"),
         )
         .await
         .stack()?;
     // it takes time for the relayer to complete relaying
-    wait_for_num_blocks(4).await.stack()?;
+    nm_hermes
+        .send(WaitForTransfers::new(&ibc_pair.b.chain_id))
+        .await
+        .stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
     // notify consumer that we have sent NOM
-    nm_consumer.send::<IbcPair>(&ibc_pair).await.stack()?;
+    nm_consumer
+        .send(ChainsConnected(ibc_pair.clone()))
+        .await
+        .stack()?;
 
     // tell hermes to restart with updated gas denom on its side
-    let ibc_nom = nm_consumer.recv::<String>().await.stack()?;
-    nm_hermes.send::<String>(&ibc_nom).await.stack()?;
-    nm_hermes.recv::<()>().await.stack()?;
-    nm_consumer.send::<()>(&()).await.stack()?;
+    let gas_denom_update = nm_consumer.expect::<GasDenomUpdate>().await.stack()?;
+    nm_hermes.send(gas_denom_update).await.stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
+    nm_consumer.send(Ready).await.stack()?;
 
     // recieve round trip signal
-    nm_consumer.recv::<()>().await.stack()?;
+    nm_consumer.expect::<Ready>().await.stack()?;
+    nm_hermes
+        .send(WaitForTransfers::new(&ibc_pair.a.chain_id))
+        .await
+        .stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
     // check that the IBC NOM converted back to regular NOM
     ensure_eq!(
         cosmovisor_get_balances("onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3")
@@ -510,8 +428,8 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     );
 
     // signal to collectively terminate
-    nm_hermes.send::<()>(&()).await.stack()?;
-    nm_consumer.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Terminate).await.stack()?;
+    nm_consumer.send(Terminate).await.stack()?;
     cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
 
     FileOptions::write_str(
@@ -528,25 +446,27 @@ async fn consumer(args: &Args) -> Result<()> {
     let daemon_home = args.daemon_home.as_ref().stack()?;
     let consumer_id = args.consumer_id.as_deref().stack()?;
     let chain_id = consumer_id;
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26001", TIMEOUT)
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26001")
         .await
         .stack()?;
     // we need the initial consumer state
-    let ccvconsumer_state_s: String = nm_onomyd.recv().await.stack()?;
+    let ConsumerGenesis(ccvconsumer_state_s) = nm_onomyd.expect().await.stack()?;
 
     onexd_setup(daemon_home, chain_id, &ccvconsumer_state_s)
         .await
         .stack()?;
 
     // get keys
-    let node_key = nm_onomyd.recv::<String>().await.stack()?;
+    let ValidatorKeys {
+        node_key,
+        priv_validator_key,
+    } = nm_onomyd.expect().await.stack()?;
     // we used same keys for consumer as provider, need to copy them over or else
     // the node will not be a working validator for itself
     FileOptions::write_str(&format!("{daemon_home}/config/node_key.json"), &node_key)
         .await
         .stack()?;
 
-    let priv_validator_key = nm_onomyd.recv::<String>().await.stack()?;
     FileOptions::write_str(
         &format!("{daemon_home}/config/priv_validator_key.json"),
         &priv_validator_key,
@@ -562,14 +482,14 @@ async fn consumer(args: &Args) -> Result<()> {
     let addr = &cosmovisor_get_addr("validator").await.stack()?;
 
     // signal that we have started
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // wait for provider to send us stuff
-    let ibc_pair = nm_onomyd.recv::<IbcPair>().await.stack()?;
+    let ChainsConnected(ibc_pair) = nm_onomyd.expect().await.stack()?;
     // get the name of the IBC NOM. Note that we can't do this on the onomyd side,
-    // it has to be with respect to the consumer side
+    // it has to be with respect to the consumer side. This also checks the locally
+    // computed trace against the chain.
     let ibc_nom = &ibc_pair.a.get_ibc_denom("anom").await.stack()?;
-    ensure_eq!(ibc_nom, ONOMY_IBC_NOM);
     let balances = cosmovisor_get_balances(addr).await.stack()?;
     ensure!(balances.contains_key(ibc_nom));
 
@@ -582,19 +502,28 @@ async fn consumer(args: &Args) -> Result<()> {
         .await
         .stack()?;
     // tell hermes to restart with updated gas denom on its side
-    nm_onomyd.send::<String>(ibc_nom).await.stack()?;
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd
+        .send(GasDenomUpdate(ibc_nom.clone()))
+        .await
+        .stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
     info!("restarted with new gas denom");
 
     // test normal transfer
     let dst_addr = &reprefix_bech32(
         "onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3",
-        CONSUMER_ACCOUNT_PREFIX,
+        &ChainSpec::onex().stack()?.account_prefix,
     )
     .stack()?;
-    cosmovisor_bank_send(addr, dst_addr, "5000", ibc_nom)
-        .await
-        .stack()?;
+    cosmovisor_bank_send(
+        addr,
+        dst_addr,
+        "5000",
+        ibc_nom,
+        &FeePolicy::chain_default(ibc_nom),
+    )
+    .await
+    .stack()?;
     ensure_eq!(
         cosmovisor_get_balances(dst_addr).await.stack()?[ibc_nom],
         u256!(5000)
@@ -604,13 +533,14 @@ async fn consumer(args: &Args) -> Result<()> {
         "onomy1y3c6q58vvuxr5tcmesay74wvhrey3pqv8g6y3r",
         "50000000",
         ibc_nom,
+        &FeePolicy::chain_default(ibc_nom),
     )
     .await
     .stack()?;
 
     let test_addr = &reprefix_bech32(
         "onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3",
-        PROVIDER_ACCOUNT_PREFIX,
+        &ChainSpec::onomy().stack()?.account_prefix,
     )
     .stack()?;
     info!("sending back to {}", test_addr);
@@ -618,10 +548,15 @@ async fn consumer(args: &Args) -> Result<()> {
     // send some IBC NOM back to origin chain using it as gas
     ibc_pair
         .a
-        .cosmovisor_ibc_transfer("validator", test_addr, "5000", ibc_nom)
+        .cosmovisor_ibc_transfer(
+            "validator",
+            test_addr,
+            "5000",
+            ibc_nom,
+            &FeePolicy::chain_default(ibc_nom),
+        )
         .await
         .stack()?;
-    wait_for_num_blocks(4).await.stack()?;
 
     // market module specific sanity checks (need to check all tx commands
     // specifically to make sure permissions are correct)
@@ -629,9 +564,11 @@ async fn consumer(args: &Args) -> Result<()> {
     let amount = u256!(100000000000000000);
     let amount_sqr = amount.checked_mul(amount).unwrap();
     let coin_pair = CoinPair::new("aonex", ibc_nom).stack()?;
-    let mut market = Market::new("validator", &format!("1000000{ibc_nom}"));
-    market.max_gas = Some(u256!(1000000));
-    market
+    let market = Market::new(
+        "validator",
+        FeePolicy::fixed_fees(&format!("1000000{ibc_nom}")).with_fixed_gas(1000000),
+    );
+    let pool_drop_uid = market
         .create_pool(&coin_pair, amount, amount)
         .await
         .stack()?;
@@ -642,7 +579,7 @@ async fn consumer(args: &Args) -> Result<()> {
         .market_order(coin_pair.coin_a(), amount, coin_pair.coin_b(), amount, 5000)
         .await
         .stack()?;
-    market.redeem_drop(1).await.stack()?;
+    market.redeem_drop(pool_drop_uid).await.stack()?;
     market
         .create_order(
             coin_pair.coin_a(),
@@ -669,37 +606,33 @@ async fn consumer(args: &Args) -> Result<()> {
 
     let pubkey = sh_cosmovisor(["tendermint show-validator"]).await.stack()?;
     let pubkey = pubkey.trim();
-    sh_cosmovisor_tx([
-        "staking",
-        "create-validator",
-        "--commission-max-change-rate",
-        "0.01",
-        "--commission-max-rate",
-        "0.10",
-        "--commission-rate",
-        "0.05",
-        "--from",
-        "validator",
-        "--min-self-delegation",
-        "1",
-        "--amount",
-        &token18(500.0, "aonex"),
-        "--fees",
-        &format!("1000000{ONOMY_IBC_NOM}"),
-        "--pubkey",
-        pubkey,
-        "-y",
-        "-b",
-        "block",
-    ])
-    .await
-    .stack()?;
+    CosmovisorCmd::tx("staking create-validator")
+        .args([
+            "--commission-max-change-rate",
+            "0.01",
+            "--commission-max-rate",
+            "0.10",
+            "--commission-rate",
+            "0.05",
+            "--min-self-delegation",
+            "1",
+            "--amount",
+            &token18(500.0, "aonex"),
+            "--fees",
+            &format!("1000000{ibc_nom}"),
+            "--pubkey",
+            pubkey,
+        ])
+        .from("validator")
+        .run_tx()
+        .await
+        .stack()?;
 
     // round trip signal
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
 
     wait_for_num_blocks(1).await.stack()?;
 
@@ -711,23 +644,27 @@ async fn consumer(args: &Args) -> Result<()> {
         "type": "Text",
         "deposit": test_deposit
     });
-    cosmovisor_gov_file_proposal(
+    let proposal_id = cosmovisor_gov_file_proposal(
         daemon_home,
+        "validator",
         None,
         &proposal.to_string(),
-        &format!("1{ibc_nom}"),
+        &FeePolicy::chain_default(ibc_nom),
+        &FeePolicy::chain_default(ibc_nom).with_auto_gas(2.3),
     )
     .await
     .stack()?;
-    let proposals = sh_cosmovisor(["query gov proposals"]).await.stack()?;
-    assert!(proposals.contains("PROPOSAL_STATUS_PASSED"));
+    wait_for_proposal_status(STD_TRIES, STD_DELAY, proposal_id, ProposalStatus::Passed)
+        .await
+        .stack()?;
 
     // but first, test governance with IBC NOM as the token
     let test_crisis_denom = ibc_nom.as_str();
     let test_deposit = token18(500.0, "aonex");
     wait_for_num_blocks(1).await.stack()?;
-    cosmovisor_gov_file_proposal(
+    let proposal_id = cosmovisor_gov_file_proposal(
         daemon_home,
+        "validator",
         Some("param-change"),
         &format!(
             r#"
@@ -745,11 +682,14 @@ async fn consumer(args: &Args) -> Result<()> {
     }}
     "#
         ),
-        &format!("1{ibc_nom}"),
+        &FeePolicy::chain_default(ibc_nom),
+        &FeePolicy::chain_default(ibc_nom).with_auto_gas(2.3),
     )
     .await
     .stack()?;
-    wait_for_num_blocks(5).await.stack()?;
+    wait_for_proposal_status(STD_TRIES, STD_DELAY, proposal_id, ProposalStatus::Passed)
+        .await
+        .stack()?;
     // just running this for debug, param querying is weird because it is json
     // inside of yaml, so we will instead test the exported genesis
     sh_cosmovisor(["query params subspace crisis ConstantFee"])
@@ -1,180 +1,98 @@
-//! uses ./tests/dockerfiles/onex_upgrade in testing ONEX upgrading
+//! Tests upgrading the ONEX consumer from `onexd_upgrade_from` to
+//! `onexd_upgrade_to` of the versions manifest
 
 use std::time::Duration;
 
 use log::info;
 use onomy_test_lib::{
+    chain::ChainSpec,
     cosmovisor::{
-        cosmovisor_bank_send, cosmovisor_get_addr, cosmovisor_get_balances,
-        cosmovisor_gov_proposal, cosmovisor_start, get_block_height, set_minimum_gas_price,
-        sh_cosmovisor, sh_cosmovisor_no_debug, sh_cosmovisor_tx, wait_for_height,
-        wait_for_num_blocks,
-    },
-    dockerfiles::{dockerfile_hermes, dockerfile_onomyd},
-    hermes::{
-        hermes_set_gas_price_denom, hermes_start, sh_hermes, write_hermes_config,
-        HermesChainConfig, IbcPair,
+        cosmovisor_bank_send, cosmovisor_get_addr, cosmovisor_get_balances, cosmovisor_start,
+        set_minimum_gas_price, sh_cosmovisor, sh_cosmovisor_no_debug, wait_for_num_blocks,
+        CosmovisorCmd,
     },
+    dockerfiles::dockerfile_onexd_upgrades,
+    hermes::{hermes_set_gas_price_denom, hermes_start, sh_hermes, IbcPair},
     market::{CoinPair, Market},
     onomy_std_init, reprefix_bech32,
+    setup_msg::{
+        ChainsConnected, ConsumerGenesis, GasDenomUpdate, HermesMnemonic, Ready, SetupMessenger,
+        Terminate, ValidatorKeys, WaitForTransfers,
+    },
     setups::{cosmovisor_add_consumer, cosmovisor_setup, test_proposal, CosmosSetupOptions},
     super_orchestrator::{
-        docker::{Container, ContainerNetwork, Dockerfile},
-        net_message::NetMessenger,
-        remove_files_in_dir, sh,
         stacked_errors::{ensure, ensure_eq, Error, Result, StackableErr},
         FileOptions,
     },
     token18,
+    topology::{Topology, TopologyNode},
+    tx::FeePolicy,
     u64_array_bigints::{
         u256, {self},
     },
-    Args, ONOMY_IBC_NOM, STD_DELAY, STD_TRIES, TEST_AMOUNT, TIMEOUT,
+    upgrade::{schedule_upgrade, wait_for_upgrade},
+    versions::versions,
+    Args, TEST_AMOUNT, TIMEOUT,
 };
 use tokio::time::sleep;
 
-const CONSUMER_ID: &str = "onex";
-const PROVIDER_ACCOUNT_PREFIX: &str = "onomy";
-const CONSUMER_ACCOUNT_PREFIX: &str = "onomy";
-
-fn consumer_binary_name() -> String {
-    format!("{CONSUMER_ID}d")
-}
-
-fn consumer_directory() -> String {
-    //format!(".{CONSUMER_ID}")
-    format!(".onomy_{CONSUMER_ID}")
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = onomy_std_init()?;
+    let versions = versions().stack()?;
 
-    if let Some(ref s) = args.entry_name {
-        match s.as_str() {
-            "onomyd" => onomyd_runner(&args).await,
-            "consumer" => consumer(&args).await,
-            "hermes" => hermes_runner(&args).await,
-            _ => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
-        }
-    } else {
-        /*
-        sh("make --directory ./../multiverse/ build", &[])
-            .await
-            .stack()?;
-        // copy to dockerfile resources (docker cannot use files from outside cwd)
-        sh(
-            "mv ./../multiverse/onexd ./tests/dockerfiles/dockerfile_resources/onexd",
-            &[],
-        )
+    /*
+    sh("make --directory ./../multiverse/ build", &[])
         .await
         .stack()?;
-        */
-        container_runner(&args).await.stack()
-    }
-}
-
-async fn container_runner(args: &Args) -> Result<()> {
-    let logs_dir = "./tests/logs";
-    let dockerfiles_dir = "./tests/dockerfiles";
-    let bin_entrypoint = &args.bin_name;
-    let container_target = "x86_64-unknown-linux-gnu";
-
-    // build internal runner with `--release`
-    sh([
-        "cargo build --release --bin",
-        bin_entrypoint,
-        "--target",
-        container_target,
-    ])
-    .await
-    .stack()?;
-
-    // prepare volumed resources
-    remove_files_in_dir("./tests/resources/keyring-test/", &[".address", ".info"])
-        .await
-        .stack()?;
-
-    let entrypoint = &format!("./target/{container_target}/release/{bin_entrypoint}");
-
-    let mut cn = ContainerNetwork::new(
-        "test",
-        vec![
-            Container::new(
-                "hermes",
-                Dockerfile::contents(dockerfile_hermes("__tmp_hermes_config.toml")),
-            )
-            .external_entrypoint(entrypoint, ["--entry-name", "hermes"])
-            .await
-            .stack()?,
-            Container::new("onomyd", Dockerfile::contents(dockerfile_onomyd()))
-                .external_entrypoint(entrypoint, ["--entry-name", "onomyd"])
-                .await
-                .stack()?
-                .volume(
-                    "./tests/resources/keyring-test",
-                    "/root/.onomy/keyring-test",
-                ),
-            Container::new(
-                &consumer_binary_name(),
-                Dockerfile::path(format!("{dockerfiles_dir}/onex_upgrade.dockerfile")),
-            )
-            .external_entrypoint(entrypoint, ["--entry-name", "consumer"])
-            .await
-            .stack()?
-            .volume(
-                "./tests/resources/keyring-test",
-                format!("/root/{}/keyring-test", consumer_directory()),
-            ),
-        ],
-        Some(dockerfiles_dir),
-        true,
-        logs_dir,
-    )
-    .stack()?;
-    cn.add_common_volumes([(logs_dir, "/logs")]);
-    let uuid = cn.uuid_as_string();
-    cn.add_common_entrypoint_args(["--uuid", &uuid]);
-
-    // prepare hermes config
-    write_hermes_config(
-        &[
-            HermesChainConfig::new(
-                "onomy",
-                &format!("onomyd_{uuid}"),
-                "onomy",
-                false,
-                "anom",
-                true,
-            ),
-            HermesChainConfig::new(
-                CONSUMER_ID,
-                &format!("{}_{}", consumer_binary_name(), uuid),
-                CONSUMER_ACCOUNT_PREFIX,
-                true,
-                "aonex",
-                true,
-            ),
-        ],
-        &format!("{dockerfiles_dir}/dockerfile_resources"),
+    // copy to dockerfile resources (docker cannot use files from outside cwd)
+    sh(
+        "mv ./../multiverse/onexd ./tests/dockerfiles/dockerfile_resources/onexd",
+        &[],
     )
     .await
     .stack()?;
-
-    cn.run_all(true).await.stack()?;
-    cn.wait_with_timeout_all(true, TIMEOUT).await.stack()?;
-    cn.terminate_all().await;
-    Ok(())
+    */
+    let consumer_dockerfile =
+        dockerfile_onexd_upgrades(&versions.onexd_upgrade_from, &[&versions.onexd_upgrade_to])
+            .stack()?;
+    let topology = Topology::new()
+        .node(TopologyNode::hermes("hermes"))
+        .node(TopologyNode::chain("onomyd", ChainSpec::onomy().stack()?))
+        .node(
+            TopologyNode::chain("consumer", ChainSpec::onex().stack()?)
+                .dockerfile(&consumer_dockerfile)
+                .entrypoint_args([
+                    "--current-version",
+                    &versions.onexd_upgrade_from,
+                    "--upgrade-version",
+                    &versions.onexd_upgrade_to,
+                ]),
+        )
+        .ibc_link("onomyd", "consumer");
+
+    let args = &args;
+    topology
+        .run(args, |entry_name| async move {
+            match entry_name.as_str() {
+                "onomyd" => onomyd_runner(args).await,
+                "consumer" => consumer(args).await,
+                "hermes" => hermes_runner(args).await,
+                s => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
+            }
+        })
+        .await
 }
 
 async fn hermes_runner(args: &Args) -> Result<()> {
     let hermes_home = args.hermes_home.as_ref().stack()?;
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26000", TIMEOUT)
+    let consumer_id = &ChainSpec::onex().stack()?.chain_id;
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26000")
         .await
         .stack()?;
 
     // get mnemonic from onomyd
-    let mnemonic: String = nm_onomyd.recv().await.stack()?;
+    let HermesMnemonic(mnemonic) = nm_onomyd.expect().await.stack()?;
     // set keys for our chains
     FileOptions::write_str("/root/.hermes/mnemonic.txt", &mnemonic)
         .await
@@ -183,16 +101,16 @@ async fn hermes_runner(args: &Args) -> Result<()> {
         .await
         .stack()?;
     sh_hermes([format!(
-        "keys add --chain {CONSUMER_ID} --mnemonic-file /root/.hermes/mnemonic.txt"
+        "keys add --chain {consumer_id} --mnemonic-file /root/.hermes/mnemonic.txt"
     )])
     .await
     .stack()?;
 
     // wait for setup
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
 
     let ibc_pair =
-        IbcPair::hermes_setup_ics_pair(CONSUMER_ID, "07-tendermint-0", "onomy", "07-tendermint-0")
+        IbcPair::hermes_setup_ics_pair(consumer_id, "07-tendermint-0", "onomy", "07-tendermint-0")
             .await
             .stack()?;
     let mut hermes_runner = hermes_start("/logs/hermes_bootstrap_runner.log")
@@ -201,48 +119,69 @@ async fn hermes_runner(args: &Args) -> Result<()> {
     ibc_pair.hermes_check_acks().await.stack()?;
 
     // tell that chains have been connected
-    nm_onomyd.send::<IbcPair>(&ibc_pair).await.stack()?;
+    nm_onomyd
+        .send(ChainsConnected(ibc_pair.clone()))
+        .await
+        .stack()?;
+
+    // wait for the transfer from onomyd to be relayed
+    let WaitForTransfers {
+        chain_id,
+        timed_out,
+    } = nm_onomyd.expect().await.stack()?;
+    ibc_pair
+        .hermes_wait_for_transfers(&chain_id, &timed_out)
+        .await
+        .stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // signal to update gas denom
-    let ibc_nom = nm_onomyd.recv::<String>().await.stack()?;
+    let GasDenomUpdate(ibc_nom) = nm_onomyd.expect().await.stack()?;
     hermes_runner.terminate(TIMEOUT).await.stack()?;
-    hermes_set_gas_price_denom(hermes_home, CONSUMER_ID, &ibc_nom)
+    hermes_set_gas_price_denom(hermes_home, consumer_id, &ibc_nom)
         .await
         .stack()?;
 
     // restart
     let mut hermes_runner = hermes_start("/logs/hermes_runner.log").await.stack()?;
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
+
+    // wait for the transfer back from the consumer to be relayed
+    let WaitForTransfers {
+        chain_id,
+        timed_out,
+    } = nm_onomyd.expect().await.stack()?;
+    ibc_pair
+        .hermes_wait_for_transfers(&chain_id, &timed_out)
+        .await
+        .stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
     hermes_runner.terminate(TIMEOUT).await.stack()?;
     Ok(())
 }
 
 async fn onomyd_runner(args: &Args) -> Result<()> {
     let uuid = &args.uuid;
-    let consumer_id = CONSUMER_ID;
+    let consumer = ChainSpec::onex().stack()?;
+    let consumer_id = consumer.chain_id.as_str();
     let daemon_home = args.daemon_home.as_ref().stack()?;
-    let mut nm_hermes =
-        NetMessenger::connect(STD_TRIES, STD_DELAY, &format!("hermes_{uuid}:26000"))
+    let mut nm_hermes = SetupMessenger::connect("hermes", &format!("hermes_{uuid}:26000"))
+        .await
+        .stack()?;
+    let mut nm_consumer =
+        SetupMessenger::connect("consumer", &format!("{}:26001", consumer.hostname(uuid)))
             .await
             .stack()?;
-    let mut nm_consumer = NetMessenger::connect(
-        STD_TRIES,
-        STD_DELAY,
-        &format!("{}_{}:26001", consumer_binary_name(), uuid),
-    )
-    .await
-    .stack()
-    .stack()?;
 
     let mut options = CosmosSetupOptions::onomy(daemon_home);
     options.large_test_amount = true;
     let cosmores = cosmovisor_setup(options).await.stack()?;
     // send mnemonic to hermes
     nm_hermes
-        .send::<String>(&cosmores.hermes_mnemonic.stack()?)
+        .send(HermesMnemonic(cosmores.hermes_mnemonic.stack()?))
         .await
         .stack()?;
 
@@ -254,6 +193,7 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
     let ccvconsumer_state = cosmovisor_add_consumer(
         daemon_home,
+        "validator",
         consumer_id,
         &test_proposal(consumer_id, "anative"),
     )
@@ -262,34 +202,29 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
 
     // send to consumer
     nm_consumer
-        .send::<String>(&ccvconsumer_state)
+        .send(ConsumerGenesis(ccvconsumer_state))
         .await
         .stack()?;
 
     // send keys
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
-                .await
-                .stack()?,
-        )
-        .await
-        .stack()?;
-    nm_consumer
-        .send::<String>(
-            &FileOptions::read_to_string(&format!("{daemon_home}/config/priv_validator_key.json"))
-                .await
-                .stack()?,
-        )
+    let validator_keys = ValidatorKeys {
+        node_key: FileOptions::read_to_string(&format!("{daemon_home}/config/node_key.json"))
+            .await
+            .stack()?,
+        priv_validator_key: FileOptions::read_to_string(&format!(
+            "{daemon_home}/config/priv_validator_key.json"
+        ))
         .await
-        .stack()?;
+        .stack()?,
+    };
+    nm_consumer.send(validator_keys).await.stack()?;
 
     // wait for consumer to be online
-    nm_consumer.recv::<()>().await.stack()?;
+    nm_consumer.expect::<Ready>().await.stack()?;
     // notify hermes to connect the chains
-    nm_hermes.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Ready).await.stack()?;
     // when hermes is done
-    let ibc_pair = nm_hermes.recv::<IbcPair>().await.stack()?;
+    let ChainsConnected(ibc_pair) = nm_hermes.expect().await.stack()?;
     info!("IbcPair: {ibc_pair:?}");
 
     // send anom to consumer
@@ -297,25 +232,38 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
         .b
         .cosmovisor_ibc_transfer(
             "validator",
-            &reprefix_bech32(addr, CONSUMER_ACCOUNT_PREFIX).stack()?,
+            &reprefix_bech32(addr, &consumer.account_prefix).stack()?,
             &TEST_AMOUNT.checked_short_divide(5).unwrap().0.to_string(),
             "anom",
+            &FeePolicy::chain_default("anom"),
         )
         .await
         .stack()?;
     // it takes time for the relayer to complete relaying
-    wait_for_num_blocks(4).await.stack()?;
+    nm_hermes
+        .send(WaitForTransfers::new(&ibc_pair.b.chain_id))
+        .await
+        .stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
     // notify consumer that we have sent NOM
-    nm_consumer.send::<IbcPair>(&ibc_pair).await.stack()?;
+    nm_consumer
+        .send(ChainsConnected(ibc_pair.clone()))
+        .await
+        .stack()?;
 
     // tell hermes to restart with updated gas denom on its side
-    let ibc_nom = nm_consumer.recv::<String>().await.stack()?;
-    nm_hermes.send::<String>(&ibc_nom).await.stack()?;
-    nm_hermes.recv::<()>().await.stack()?;
-    nm_consumer.send::<()>(&()).await.stack()?;
+    let gas_denom_update = nm_consumer.expect::<GasDenomUpdate>().await.stack()?;
+    nm_hermes.send(gas_denom_update).await.stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
+    nm_consumer.send(Ready).await.stack()?;
 
     // recieve round trip signal
-    nm_consumer.recv::<()>().await.stack()?;
+    nm_consumer.expect::<Ready>().await.stack()?;
+    nm_hermes
+        .send(WaitForTransfers::new(&ibc_pair.a.chain_id))
+        .await
+        .stack()?;
+    nm_hermes.expect::<Ready>().await.stack()?;
     // check that the IBC NOM converted back to regular NOM
     ensure_eq!(
         cosmovisor_get_balances("onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3")
@@ -325,8 +273,8 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     );
 
     // signal to collectively terminate
-    nm_hermes.send::<()>(&()).await.stack()?;
-    nm_consumer.send::<()>(&()).await.stack()?;
+    nm_hermes.send(Terminate).await.stack()?;
+    nm_consumer.send(Terminate).await.stack()?;
     cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
 
     FileOptions::write_str(
@@ -343,32 +291,29 @@ async fn consumer(args: &Args) -> Result<()> {
     let daemon_home = args.daemon_home.as_ref().stack()?;
     let current_version = args.current_version.as_ref().stack()?;
     let upgrade_version = args.upgrade_version.as_ref().stack()?;
-    let chain_id = CONSUMER_ID;
-    let mut nm_onomyd = NetMessenger::listen("0.0.0.0:26001", TIMEOUT)
+    let consumer = ChainSpec::onex().stack()?;
+    let chain_id = consumer.chain_id.as_str();
+    let mut nm_onomyd = SetupMessenger::listen("onomyd", "0.0.0.0:26001")
         .await
         .stack()?;
     // we need the initial consumer state
-    let ccvconsumer_state_s: String = nm_onomyd.recv().await.stack()?;
+    let ConsumerGenesis(ccvconsumer_state_s) = nm_onomyd.expect().await.stack()?;
 
-    let mut options = CosmosSetupOptions::new(
-        daemon_home,
-        chain_id,
-        "aonex",
-        "aonex",
-        Some(&ccvconsumer_state_s),
-    );
+    let mut options = consumer.setup_options(Some(&ccvconsumer_state_s));
     options.large_test_amount = true;
     cosmovisor_setup(options).await.stack()?;
 
     // get keys
-    let node_key = nm_onomyd.recv::<String>().await.stack()?;
+    let ValidatorKeys {
+        node_key,
+        priv_validator_key,
+    } = nm_onomyd.expect().await.stack()?;
     // we used same keys for consumer as producer, need to copy them over or else
     // the node will not be a working validator for itself
     FileOptions::write_str(&format!("{daemon_home}/config/node_key.json"), &node_key)
         .await
         .stack()?;
 
-    let priv_validator_key = nm_onomyd.recv::<String>().await.stack()?;
     FileOptions::write_str(
         &format!("{daemon_home}/config/priv_validator_key.json"),
         &priv_validator_key,
@@ -384,14 +329,14 @@ async fn consumer(args: &Args) -> Result<()> {
     let addr = &cosmovisor_get_addr("validator").await.stack()?;
 
     // signal that we have started
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // wait for producer to send us stuff
-    let ibc_pair = nm_onomyd.recv::<IbcPair>().await.stack()?;
+    let ChainsConnected(ibc_pair) = nm_onomyd.expect().await.stack()?;
     // get the name of the IBC NOM. Note that we can't do this on the onomyd side,
-    // it has to be with respect to the consumer side
+    // it has to be with respect to the consumer side. This also checks the locally
+    // computed trace against the chain.
     let ibc_nom = &ibc_pair.a.get_ibc_denom("anom").await.stack()?;
-    ensure_eq!(ibc_nom, ONOMY_IBC_NOM);
     let balances = cosmovisor_get_balances(addr).await.stack()?;
     ensure!(balances.contains_key(ibc_nom));
 
@@ -404,37 +349,36 @@ async fn consumer(args: &Args) -> Result<()> {
         .await
         .stack()?;
     // tell hermes to restart with updated gas denom on its side
-    nm_onomyd.send::<String>(ibc_nom).await.stack()?;
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd
+        .send(GasDenomUpdate(ibc_nom.clone()))
+        .await
+        .stack()?;
+    nm_onomyd.expect::<Ready>().await.stack()?;
     info!("restarted with new gas denom");
 
     let pubkey = sh_cosmovisor(["tendermint show-validator"]).await.stack()?;
     let pubkey = pubkey.trim();
-    sh_cosmovisor_tx([
-        "staking",
-        "create-validator",
-        "--commission-max-change-rate",
-        "0.01",
-        "--commission-max-rate",
-        "0.10",
-        "--commission-rate",
-        "0.05",
-        "--from",
-        "validator",
-        "--min-self-delegation",
-        "1",
-        "--amount",
-        &token18(1.0e3, "aonex"),
-        "--fees",
-        &format!("1000000{ONOMY_IBC_NOM}"),
-        "--pubkey",
-        pubkey,
-        "-y",
-        "-b",
-        "block",
-    ])
-    .await
-    .stack()?;
+    CosmovisorCmd::tx("staking create-validator")
+        .args([
+            "--commission-max-change-rate",
+            "0.01",
+            "--commission-max-rate",
+            "0.10",
+            "--commission-rate",
+            "0.05",
+            "--min-self-delegation",
+            "1",
+            "--amount",
+            &token18(1.0e3, "aonex"),
+            "--fees",
+            &format!("1000000{ibc_nom}"),
+            "--pubkey",
+            pubkey,
+        ])
+        .from("validator")
+        .run_tx()
+        .await
+        .stack()?;
 
     wait_for_num_blocks(4).await.stack()?;
 
@@ -448,48 +392,37 @@ async fn consumer(args: &Args) -> Result<()> {
         current_version
     );
 
-    let upgrade_prepare_start = get_block_height().await.stack()?;
-    let upgrade_height = &format!("{}", upgrade_prepare_start + 4);
-
     //sh(&format!("cosmovisor add-upgrade {upgrade_version} /logs/onexd
     // --upgrade-height {upgrade_height}"), &[]).await.stack()?;
 
-    let description = &format!("\"upgrade {upgrade_version}\"");
-    cosmovisor_gov_proposal(
-        "software-upgrade",
-        &[
-            upgrade_version,
-            "--title",
-            description,
-            "--description",
-            description,
-            "--upgrade-height",
-            upgrade_height,
-        ],
+    let plan = schedule_upgrade(
+        "validator",
+        upgrade_version,
+        4,
+        "",
         &token18(500.0, "aonex"),
-        &format!("10{ONOMY_IBC_NOM}"),
+        &FeePolicy::auto_gas(1.3, &format!("10{ibc_nom}")),
+        &FeePolicy::auto_gas(2.3, &format!("10{ibc_nom}")),
     )
     .await
     .stack()?;
-
-    wait_for_height(STD_TRIES, STD_DELAY, upgrade_prepare_start + 7)
-        .await
-        .stack()?;
-
-    ensure_eq!(
-        sh_cosmovisor(["version"]).await.stack()?.trim(),
-        upgrade_version
-    );
+    wait_for_upgrade(&plan, upgrade_version).await.stack()?;
 
     // test normal transfer
     let dst_addr = &reprefix_bech32(
         "onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3",
-        CONSUMER_ACCOUNT_PREFIX,
+        &consumer.account_prefix,
     )
     .stack()?;
-    cosmovisor_bank_send(addr, dst_addr, "5000", ibc_nom)
-        .await
-        .stack()?;
+    cosmovisor_bank_send(
+        addr,
+        dst_addr,
+        "5000",
+        ibc_nom,
+        &FeePolicy::chain_default(ibc_nom),
+    )
+    .await
+    .stack()?;
     ensure_eq!(
         cosmovisor_get_balances(dst_addr).await.stack()?[ibc_nom],
         u256!(5000)
@@ -497,7 +430,7 @@ async fn consumer(args: &Args) -> Result<()> {
 
     let test_addr = &reprefix_bech32(
         "onomy1gk7lg5kd73mcr8xuyw727ys22t7mtz9gh07ul3",
-        PROVIDER_ACCOUNT_PREFIX,
+        &ChainSpec::onomy().stack()?.account_prefix,
     )
     .stack()?;
     info!("sending back to {}", test_addr);
@@ -505,18 +438,25 @@ async fn consumer(args: &Args) -> Result<()> {
     // send some IBC NOM back to origin chain using it as gas
     ibc_pair
         .a
-        .cosmovisor_ibc_transfer("validator", test_addr, "5000", ibc_nom)
+        .cosmovisor_ibc_transfer(
+            "validator",
+            test_addr,
+            "5000",
+            ibc_nom,
+            &FeePolicy::chain_default(ibc_nom),
+        )
         .await
         .stack()?;
-    wait_for_num_blocks(4).await.stack()?;
 
     // market module specific sanity checks (need to check all tx commands
     // specifically to make sure permissions are correct)
 
     let coin_pair = CoinPair::new("aonex", ibc_nom).stack()?;
-    let mut market = Market::new("validator", &format!("1000000{ibc_nom}"));
-    market.max_gas = Some(u256!(1000000));
-    market
+    let market = Market::new(
+        "validator",
+        FeePolicy::fixed_fees(&format!("1000000{ibc_nom}")).with_fixed_gas(1000000),
+    );
+    let pool_drop_uid = market
         .create_pool(&coin_pair, Market::MAX_COIN, Market::MAX_COIN)
         .await
         .stack()?;
@@ -536,7 +476,7 @@ async fn consumer(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market.redeem_drop(1).await.stack()?;
+    market.redeem_drop(pool_drop_uid).await.stack()?;
     market
         .create_order(
             coin_pair.coin_a(),
@@ -548,7 +488,7 @@ async fn consumer(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market
+    let limit_order_uid = market
         .create_order(
             coin_pair.coin_a(),
             coin_pair.coin_b(),
@@ -559,13 +499,13 @@ async fn consumer(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market.cancel_order(6).await.stack()?;
+    market.cancel_order(limit_order_uid).await.stack()?;
 
     // round trip signal
-    nm_onomyd.send::<()>(&()).await.stack()?;
+    nm_onomyd.send(Ready).await.stack()?;
 
     // termination signal
-    nm_onomyd.recv::<()>().await.stack()?;
+    nm_onomyd.expect::<Terminate>().await.stack()?;
 
     cosmovisor_runner.terminate(TIMEOUT).await.stack()?;
 
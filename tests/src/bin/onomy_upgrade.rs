@@ -1,81 +1,53 @@
-//! uses ./tests/dockerfiles/onomy_upgrade in testing onomy upgrading
+//! Tests upgrading onomyd from `onomyd_upgrade_from` to `onomyd_upgrade_to` of
+//! the versions manifest
 
 use log::info;
 use onomy_test_lib::{
     cosmovisor::{
-        cosmovisor_gov_proposal, cosmovisor_start, get_block_height, get_staking_pool,
-        get_treasury, get_treasury_inflation_annual, sh_cosmovisor, wait_for_height,
+        cosmovisor_start, get_staking_pool, get_treasury, get_treasury_inflation_annual,
+        sh_cosmovisor,
     },
+    dockerfiles::dockerfile_onomyd_upgrades,
     nom, onomy_std_init,
     setups::{cosmovisor_setup, CosmosSetupOptions},
-    super_orchestrator::{
-        docker::{Container, ContainerNetwork, Dockerfile},
-        sh,
-        stacked_errors::{ensure_eq, Error, Result, StackableErr},
-    },
-    Args, STD_DELAY, STD_TRIES, TIMEOUT,
+    super_orchestrator::stacked_errors::{ensure_eq, Error, Result, StackableErr},
+    topology::{Topology, TopologyNode},
+    tx::FeePolicy,
+    upgrade::{schedule_upgrade, wait_for_upgrade},
+    versions::versions,
+    Args, TIMEOUT,
 };
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = onomy_std_init()?;
+    let versions = versions().stack()?;
 
-    if let Some(ref s) = args.entry_name {
-        match s.as_str() {
-            "onomyd" => onomyd_runner(&args).await,
-            _ => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
-        }
-    } else {
-        /*sh(["make --directory ./../onomy/ build"]).await.stack()?;
-        // copy to dockerfile resources (docker cannot use files from outside cwd)
-        sh(
-            ["cp ./../onomy/onomyd ./tests/dockerfiles/dockerfile_resources/onomyd"],
-        )
-        .await.stack()?;*/
-        container_runner(&args).await
-    }
-}
-
-async fn container_runner(args: &Args) -> Result<()> {
-    let logs_dir = "./tests/logs";
-    let dockerfiles_dir = "./tests/dockerfiles";
-    let bin_entrypoint = &args.bin_name;
-    let container_target = "x86_64-unknown-linux-gnu";
-
-    // build internal runner
-    sh([
-        "cargo build --release --bin",
-        bin_entrypoint,
-        "--target",
-        container_target,
-    ])
-    .await
-    .stack()?;
-
-    let mut cn = ContainerNetwork::new(
-        "test",
-        vec![Container::new(
-            "onomyd",
-            Dockerfile::path(format!("{dockerfiles_dir}/chain_upgrade.dockerfile")),
-        )
-        .external_entrypoint(
-            format!("./target/{container_target}/release/{bin_entrypoint}"),
-            ["--entry-name", "onomyd"],
-        )
-        .await
-        .stack()?],
-        None,
-        true,
-        logs_dir,
+    /*sh(["make --directory ./../onomy/ build"]).await.stack()?;
+    // copy to dockerfile resources (docker cannot use files from outside cwd)
+    sh(
+        ["cp ./../onomy/onomyd ./tests/dockerfiles/dockerfile_resources/onomyd"],
     )
-    .stack()?;
-    cn.add_common_volumes([(logs_dir, "/logs")]);
-    let uuid = cn.uuid_as_string();
-    cn.add_common_entrypoint_args(["--uuid", &uuid]);
-    cn.run_all(true).await.stack()?;
-    cn.wait_with_timeout_all(true, TIMEOUT).await.stack()?;
-    cn.terminate_all().await;
-    Ok(())
+    .await.stack()?;*/
+    let (from, to) = (&versions.onomyd_upgrade_from, &versions.onomyd_upgrade_to);
+    let dockerfile = dockerfile_onomyd_upgrades(from, &[to]).stack()?;
+    let onomyd = TopologyNode::new("onomyd", &dockerfile).entrypoint_args([
+        "--current-version",
+        from,
+        "--upgrade-version",
+        to,
+    ]);
+    let topology = Topology::new().node(onomyd);
+
+    let args = &args;
+    topology
+        .run(args, |entry_name| async move {
+            match entry_name.as_str() {
+                "onomyd" => onomyd_runner(args).await,
+                s => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
+            }
+        })
+        .await
 }
 
 async fn onomyd_runner(args: &Args) -> Result<()> {
@@ -99,36 +71,18 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     //sh(&format!("cosmovisor add-upgrade v1.1.2 /logs/onomyd --upgrade-height
     // 10"), &[]).await.stack()?;
 
-    let upgrade_prepare_start = get_block_height().await.stack()?;
-    let upgrade_height = &format!("{}", upgrade_prepare_start + 4);
-
-    let description = &format!("\"upgrade {upgrade_version}\"");
-
-    cosmovisor_gov_proposal(
-        "software-upgrade",
-        &[
-            upgrade_version,
-            "--title",
-            description,
-            "--description",
-            description,
-            "--upgrade-height",
-            upgrade_height,
-        ],
+    let plan = schedule_upgrade(
+        "validator",
+        upgrade_version,
+        4,
+        "",
         &nom(2000.0),
-        "1anom",
+        &FeePolicy::chain_default("anom"),
+        &FeePolicy::chain_default("anom").with_auto_gas(2.3),
     )
     .await
     .stack()?;
-
-    wait_for_height(STD_TRIES, STD_DELAY, upgrade_prepare_start + 5)
-        .await
-        .stack()?;
-
-    ensure_eq!(
-        sh_cosmovisor(["version"]).await.stack()?.trim(),
-        upgrade_version
-    );
+    wait_for_upgrade(&plan, upgrade_version).await.stack()?;
 
     info!("{:?}", get_staking_pool().await.stack()?);
     info!("{}", get_treasury().await.stack()?);
@@ -9,9 +9,11 @@ use onomy_test_lib::{
     cosmovisor::{
         cosmovisor_get_addr, cosmovisor_gov_file_proposal, cosmovisor_start, get_apr_annual,
         get_delegations_to, get_staking_pool, get_treasury, get_treasury_inflation_annual,
-        sh_cosmovisor, sh_cosmovisor_no_debug, sh_cosmovisor_tx, wait_for_num_blocks,
+        sh_cosmovisor, sh_cosmovisor_no_debug, wait_for_num_blocks, CosmovisorCmd,
     },
+    denom_trace::DenomTrace,
     dockerfiles::dockerfile_onomyd,
+    gov::{wait_for_proposal_status, ProposalStatus},
     onomy_std_init, reprefix_bech32,
     setups::{cosmovisor_setup, CosmosSetupOptions},
     super_orchestrator::{
@@ -19,7 +21,9 @@ use onomy_test_lib::{
         stacked_errors::{ensure, ensure_eq, Error, Result, StackableErr},
         stacked_get, FileOptions,
     },
-    token18, yaml_str_to_json_value, Args, ONOMY_IBC_NOM, TIMEOUT,
+    token18,
+    tx::FeePolicy,
+    yaml_str_to_json_value, Args, STD_DELAY, STD_TRIES, TIMEOUT,
 };
 use serde_json::json;
 use tokio::time::sleep;
@@ -39,7 +43,7 @@ async fn main() -> Result<()> {
         sh(["cp ./../onomy/onomyd ./tests/dockerfiles/dockerfile_resources/onomyd"])
             .await
             .stack()?;
-        container_runner(&args, &[("onomyd", &dockerfile_onomyd())])
+        container_runner(&args, &[("onomyd", &dockerfile_onomyd().stack()?)])
             .await
             .stack()
     }
@@ -67,11 +71,19 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
         "type": "Text",
         "deposit": test_deposit
     });
-    cosmovisor_gov_file_proposal(daemon_home, None, &proposal.to_string(), "1anom")
+    let proposal_id = cosmovisor_gov_file_proposal(
+        daemon_home,
+        "validator",
+        None,
+        &proposal.to_string(),
+        &FeePolicy::chain_default("anom"),
+        &FeePolicy::chain_default("anom").with_auto_gas(2.3),
+    )
+    .await
+    .stack()?;
+    wait_for_proposal_status(STD_TRIES, STD_DELAY, proposal_id, ProposalStatus::Passed)
         .await
         .stack()?;
-    let proposals = sh_cosmovisor(["query gov proposals"]).await.stack()?;
-    ensure!(proposals.contains("PROPOSAL_STATUS_PASSED"));
 
     // get valcons bech32 and pub key
     // cosmovisor run query tendermint-validator-set
@@ -83,12 +95,13 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     // get mapping of cons pub keys and valoper addr
     // cosmovisor run query staking validators
 
-    sh_cosmovisor_tx([format!(
-        "staking delegate {valoper_addr} 1000000000000000000000anom --fees 1000000anom -y -b \
-         block --from validator"
-    )])
-    .await
-    .stack()?;
+    CosmovisorCmd::tx("staking delegate")
+        .args([valoper_addr.as_str(), "1000000000000000000000anom"])
+        .fee_policy(&FeePolicy::fixed_fees("1000000anom"))
+        .from("validator")
+        .run_tx()
+        .await
+        .stack()?;
     sh_cosmovisor(["query staking validators"]).await.stack()?;
 
     let apr0 = get_apr_annual(valoper_addr, 6311520.0).await.stack()?;
@@ -104,22 +117,31 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     info!("{}", get_treasury().await.stack()?);
     info!("{}", get_treasury_inflation_annual().await.stack()?);
 
-    sh([format!(
-        "cosmovisor run tx bank send {addr} onomy1a69w3hfjqere4crkgyee79x2mxq0w2pfj9tu2m 1337anom \
-         --fees 1000000anom -y -b block"
-    )])
-    .await
-    .stack()?;
+    CosmovisorCmd::tx("bank send")
+        .args([
+            addr.as_str(),
+            "onomy1a69w3hfjqere4crkgyee79x2mxq0w2pfj9tu2m",
+            "1337anom",
+        ])
+        .fee_policy(&FeePolicy::fixed_fees("1000000anom"))
+        .run_tx()
+        .await
+        .stack()?;
 
     //cosmovisor run tx staking delegate onomyvaloper
     // 10000000000000000000000ibc/
     // 0EEDE4D6082034D6CD465BD65761C305AACC6FCA1246F87D6A3C1F5488D18A7B --gas auto
-    // --gas-adjustment 1.3 -y -b block
+    // --gas-adjustment 1.3 -y -b sync
 
-    let test_crisis_denom = ONOMY_IBC_NOM;
+    // an IBC denom that does not exist on this chain
+    let ibc_nom = DenomTrace::new("anom")
+        .prefix("transfer", "channel-1")
+        .ibc_denom();
+    let test_crisis_denom = ibc_nom.as_str();
     let test_deposit = token18(2000.0, "anom");
-    cosmovisor_gov_file_proposal(
+    let proposal_id = cosmovisor_gov_file_proposal(
         daemon_home,
+        "validator",
         Some("param-change"),
         &format!(
             r#"
@@ -137,11 +159,14 @@ async fn onomyd_runner(args: &Args) -> Result<()> {
     }}
     "#
         ),
-        "1anom",
+        &FeePolicy::chain_default("anom"),
+        &FeePolicy::chain_default("anom").with_auto_gas(2.3),
     )
     .await
     .stack()?;
-    wait_for_num_blocks(1).await.stack()?;
+    wait_for_proposal_status(STD_TRIES, STD_DELAY, proposal_id, ProposalStatus::Passed)
+        .await
+        .stack()?;
     // just running this for debug, param querying is weird because it is json
     // inside of yaml, so we will instead test the exported genesis
     sh_cosmovisor(["query params subspace crisis ConstantFee"])
@@ -6,23 +6,21 @@ use log::info;
 use onomy_test_lib::{
     cosmovisor::{
         cosmovisor_get_addr, cosmovisor_get_balances, cosmovisor_start, fast_block_times,
-        get_self_peer_info, set_persistent_peers, sh_cosmovisor, sh_cosmovisor_no_debug,
-    },
-    dockerfiles::{
-        dockerfile_standalone_onexd, COSMOVISOR, DOWNLOAD_STANDALONE_ONEXD, ONOMY_STD,
-        STANDALONE_ONEX_FH_VERSION,
+        get_self_peer_info, set_persistent_peers, CosmovisorCmd,
     },
+    dockerfiles::{dockerfile_standalone_onexd, standalone_onexd_release, DockerfileBuilder},
     market::{CoinPair, Market},
     onomy_std_init,
     setups::{cosmovisor_setup, CosmosSetupOptions},
     super_orchestrator::{
-        docker::{Container, ContainerNetwork, Dockerfile},
+        docker::{Container, Dockerfile},
         net_message::NetMessenger,
-        sh,
         stacked_errors::{Error, Result, StackableErr},
         wait_for_ok, Command, FileOptions,
     },
-    u64_array_bigints::{self, u256},
+    topology::{Topology, TopologyNode},
+    tx::FeePolicy,
+    versions::versions,
     Args, STD_DELAY, STD_TRIES, TIMEOUT,
 };
 use tokio::time::sleep;
@@ -78,141 +76,96 @@ provider = [
   { label = "market", details = { type = "firehose", url = "http://localhost:9030/" }},
 ]"#;
 
-#[rustfmt::skip]
-fn standalone_dockerfile() -> String {
-    // use the fh version
-    let version = STANDALONE_ONEX_FH_VERSION;
-    let daemon_name = BINARY_NAME;
-    let daemon_dir_name = BINARY_DIR;
-    format!(
-        r#"{ONOMY_STD}
-# postgres and protobuf dependencies
-RUN dnf install -y postgresql libpq-devel protobuf protobuf-compiler protobuf-devel
-# for debug
-RUN go install github.com/fullstorydev/grpcurl/cmd/grpcurl@latest
-# for cosmovisor
-{COSMOVISOR}
-
-# interfacing with the running graph
-RUN npm install -g @graphprotocol/graph-cli
-
-# firehose
-RUN git clone --depth 1 --branch v0.6.0 https://github.com/figment-networks/firehose-cosmos
-# not working for me, too flaky
-#RUN cd /firehose-cosmos && make install
-ADD https://github.com/graphprotocol/firehose-cosmos/releases/download/v0.6.0/firecosmos_linux_amd64 /usr/bin/firecosmos
-RUN chmod +x /usr/bin/firecosmos
-
-# graph-node
-RUN git clone --depth 1 --branch v0.32.0 https://github.com/graphprotocol/graph-node
-RUN cd /graph-node && cargo build --release -p graph-node
-
-# ipfs
-ADD https://dist.ipfs.tech/kubo/v0.23.0/kubo_v0.23.0_linux-amd64.tar.gz /tmp/kubo.tar.gz
-RUN cd /tmp && tar -xf /tmp/kubo.tar.gz && mv /tmp/kubo/ipfs /usr/bin/ipfs
-RUN ipfs init
-
-# our subgraph
-RUN git clone https://github.com/onomyprotocol/mgraph
-#ADD ./dockerfile_resources/mgraph /mgraph
-RUN cd /mgraph && npm install && npm run build
-
-ENV DAEMON_NAME="{daemon_name}"
-ENV DAEMON_HOME="/root/{daemon_dir_name}"
-ENV DAEMON_VERSION={version}
-
-{DOWNLOAD_STANDALONE_ONEXD}
-
-# for manual testing
-RUN chmod +x $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}
-
-# set up symbolic links
-RUN cosmovisor init $DAEMON_HOME/cosmovisor/genesis/$DAEMON_VERSION/bin/{daemon_name}
-
-# some commands don't like if the data directory does not exist
-RUN mkdir $DAEMON_HOME/data
-
-RUN mkdir /firehose
-RUN mkdir /firehose/data
-"#
-    )
+fn standalone_dockerfile() -> Result<String> {
+    let versions = versions().stack()?;
+    DockerfileBuilder::onomy_std()
+        // postgres and protobuf dependencies
+        .dnf_packages([
+            "postgresql",
+            "libpq-devel",
+            "protobuf",
+            "protobuf-compiler",
+            "protobuf-devel",
+        ])
+        .comment("for debug")
+        .run("go install github.com/fullstorydev/grpcurl/cmd/grpcurl@latest")
+        .cosmovisor(&versions.cosmovisor)
+        .comment("interfacing with the running graph")
+        .run("npm install -g @graphprotocol/graph-cli")
+        .comment("firehose")
+        .run(
+            "git clone --depth 1 --branch v0.6.0 \
+             https://github.com/figment-networks/firehose-cosmos",
+        )
+        // not working for me, too flaky
+        //.run("cd /firehose-cosmos && make install")
+        .add_url(
+            "https://github.com/graphprotocol/firehose-cosmos/releases/download/v0.6.0/\
+             firecosmos_linux_amd64",
+            "/usr/bin/firecosmos",
+        )
+        .run("chmod +x /usr/bin/firecosmos")
+        .comment("graph-node")
+        .run("git clone --depth 1 --branch v0.32.0 https://github.com/graphprotocol/graph-node")
+        .run("cd /graph-node && cargo build --release -p graph-node")
+        .comment("ipfs")
+        .add_url(
+            "https://dist.ipfs.tech/kubo/v0.23.0/kubo_v0.23.0_linux-amd64.tar.gz",
+            "/tmp/kubo.tar.gz",
+        )
+        .run("cd /tmp && tar -xf /tmp/kubo.tar.gz && mv /tmp/kubo/ipfs /usr/bin/ipfs")
+        .run("ipfs init")
+        .comment("our subgraph")
+        .run("git clone https://github.com/onomyprotocol/mgraph")
+        //.add_resource("mgraph", "/mgraph")
+        .run("cd /mgraph && npm install && npm run build")
+        // use the fh version
+        .cosmovisor_daemon(
+            BINARY_NAME,
+            BINARY_DIR,
+            &versions.marketd_fh,
+            &standalone_onexd_release(&versions.marketd_fh),
+        )
+        .run("mkdir /firehose")
+        .run("mkdir /firehose/data")
+        .build()
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = onomy_std_init()?;
 
-    if let Some(ref s) = args.entry_name {
-        match s.as_str() {
-            "standalone" => standalone_runner(&args).await,
-            "onex_node" => onex_node(&args).await,
-            _ => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
-        }
-    } else {
-        container_runner(&args).await.stack()
-    }
-}
-
-async fn container_runner(args: &Args) -> Result<()> {
-    let logs_dir = "./tests/logs";
-    let dockerfiles_dir = "./tests/dockerfiles";
-    let bin_entrypoint = &args.bin_name;
-    let container_target = "x86_64-unknown-linux-gnu";
-
-    // build internal runner with `--release`
-    sh([
-        "cargo build --release --bin",
-        bin_entrypoint,
-        "--target",
-        container_target,
-    ])
-    .await
-    .stack()?;
-
-    let entrypoint = &format!("./target/{container_target}/release/{bin_entrypoint}");
-
-    // we use a normal onexd for the validator full node, but use the `-fh` version
-    // for the full node that indexes for firehose
-    let mut containers =
-        vec![
-            Container::new("standalone", Dockerfile::contents(standalone_dockerfile()))
-                .external_entrypoint(entrypoint, ["--entry-name", "standalone"])
-                .await
-                .stack()?,
-        ];
-    containers.push(
-        Container::new(
+    let topology = Topology::new()
+        .timeout(Duration::from_secs(9999))
+        .node(TopologyNode::new(
+            "standalone",
+            &standalone_dockerfile().stack()?,
+        ))
+        .node(TopologyNode::new(
             "onex_node",
-            Dockerfile::contents(dockerfile_standalone_onexd()),
-        )
-        .external_entrypoint(entrypoint, ["--entry-name", "onex_node"])
-        .await
-        .stack()?,
-    );
-
-    let mut cn =
-        ContainerNetwork::new("test", containers, Some(dockerfiles_dir), true, logs_dir).stack()?;
-    cn.add_common_volumes([(logs_dir, "/logs")]);
-    let uuid = cn.uuid_as_string();
-    cn.add_common_entrypoint_args(["--uuid", &uuid]);
-    cn.add_container(
-        Container::new("postgres", Dockerfile::name_tag("postgres:16"))
-            .environment_vars([
-                ("POSTGRES_PASSWORD", "root"),
-                ("POSTGRES_USER", "postgres"),
-                ("POSTGRES_DB", "graph-node"),
-                ("POSTGRES_INITDB_ARGS", "-E UTF8 --locale=C"),
-            ])
-            .no_uuid_for_host_name(),
-    )
-    .stack()?;
-
-    cn.run_all(true).await.stack()?;
-    cn.wait_with_timeout_all(true, Duration::from_secs(9999))
+            &dockerfile_standalone_onexd().stack()?,
+        ))
+        .service(
+            Container::new("postgres", Dockerfile::name_tag("postgres:16"))
+                .environment_vars([
+                    ("POSTGRES_PASSWORD", "root"),
+                    ("POSTGRES_USER", "postgres"),
+                    ("POSTGRES_DB", "graph-node"),
+                    ("POSTGRES_INITDB_ARGS", "-E UTF8 --locale=C"),
+                ])
+                .no_uuid_for_host_name(),
+        );
+
+    let args = &args;
+    topology
+        .run(args, |entry_name| async move {
+            match entry_name.as_str() {
+                "standalone" => standalone_runner(args).await,
+                "onex_node" => onex_node(args).await,
+                s => Err(Error::from(format!("entry_name \"{s}\" is not recognized"))),
+            }
+        })
         .await
-        .stack()?;
-    cn.terminate_all().await;
-    Ok(())
 }
 
 async fn standalone_runner(args: &Args) -> Result<()> {
@@ -271,13 +224,23 @@ async fn standalone_runner(args: &Args) -> Result<()> {
     .stack()?.assert_success().stack()?;
     */
 
-    sh_cosmovisor(["config chain-id --home /firehose", CHAIN_ID])
+    CosmovisorCmd::new("config chain-id")
+        .arg(CHAIN_ID)
+        .home("/firehose")
+        .debug(true)
+        .run()
         .await
         .stack()?;
-    sh_cosmovisor(["config keyring-backend test --home /firehose"])
+    CosmovisorCmd::new("config keyring-backend test")
+        .home("/firehose")
+        .debug(true)
+        .run()
         .await
         .stack()?;
-    sh_cosmovisor_no_debug(["init --overwrite --home /firehose", CHAIN_ID])
+    CosmovisorCmd::new("init --overwrite")
+        .arg(CHAIN_ID)
+        .home("/firehose")
+        .run()
         .await
         .stack()?;
     // TODO only for validators?
@@ -431,15 +394,17 @@ async fn onex_node(args: &Args) -> Result<()> {
         .await
         .stack()?;
 
-    let mut market = Market::new("validator", "1000000anative");
-    market.max_gas = Some(u256!(1000000));
+    let market = Market::new(
+        "validator",
+        FeePolicy::fixed_fees("1000000anative").with_fixed_gas(1000000),
+    );
 
     let addr = &cosmovisor_get_addr("validator").await.stack()?;
     info!("{:?}", cosmovisor_get_balances(addr).await.stack()?);
     let coin_pair = CoinPair::new("afootoken", "anative").stack()?;
 
     // test numerical limits
-    market
+    let pool_drop_uid = market
         .create_pool(&coin_pair, Market::MAX_COIN, Market::MAX_COIN)
         .await
         .stack()?;
@@ -459,7 +424,7 @@ async fn onex_node(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market.redeem_drop(1).await.stack()?;
+    market.redeem_drop(pool_drop_uid).await.stack()?;
     market
         .create_order(
             coin_pair.coin_a(),
@@ -471,7 +436,7 @@ async fn onex_node(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market
+    let limit_order_uid = market
         .create_order(
             coin_pair.coin_a(),
             coin_pair.coin_b(),
@@ -482,7 +447,7 @@ async fn onex_node(args: &Args) -> Result<()> {
         )
         .await
         .stack()?;
-    market.cancel_order(6).await.stack()?;
+    market.cancel_order(limit_order_uid).await.stack()?;
 
     sleep(Duration::from_secs(9999)).await;
 
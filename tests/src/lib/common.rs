@@ -1,50 +1,15 @@
 use onomy_test_lib::{
-    super_orchestrator::{
-        docker::{Container, ContainerNetwork, Dockerfile},
-        sh,
-        stacked_errors::{Result, StackableErr},
-    },
-    Args, TIMEOUT,
+    super_orchestrator::stacked_errors::{Result, StackableErr},
+    topology::{Topology, TopologyNode},
+    Args,
 };
 
 /// Useful for running simple container networks that have a standard format and
-/// don't need extra build or volume arguments.
+/// don't need extra build or volume arguments, see [Topology] for the rest.
 pub async fn container_runner(args: &Args, name_and_contents: &[(&str, &str)]) -> Result<()> {
-    let logs_dir = "./tests/logs";
-    let dockerfiles_dir = "./tests/dockerfiles";
-    let bin_entrypoint = &args.bin_name;
-    let container_target = "x86_64-unknown-linux-gnu";
-
-    // build internal runner
-    sh([
-        "cargo build --release --bin",
-        bin_entrypoint,
-        "--target",
-        container_target,
-    ])
-    .await
-    .stack()?;
-
-    let mut containers = vec![];
+    let mut topology = Topology::new();
     for (name, contents) in name_and_contents {
-        containers.push(
-            Container::new(name, Dockerfile::contents(contents))
-                .external_entrypoint(
-                    format!("./target/{container_target}/release/{bin_entrypoint}"),
-                    ["--entry-name", name],
-                )
-                .await
-                .stack()?,
-        );
+        topology = topology.node(TopologyNode::new(name, contents));
     }
-
-    let mut cn =
-        ContainerNetwork::new("test", containers, Some(dockerfiles_dir), true, logs_dir).stack()?;
-    cn.add_common_volumes([(logs_dir, "/logs")]);
-    let uuid = cn.uuid_as_string();
-    cn.add_common_entrypoint_args(["--uuid", &uuid]);
-    cn.run_all(true).await.stack()?;
-    cn.wait_with_timeout_all(true, TIMEOUT).await.stack()?;
-    cn.terminate_all().await;
-    Ok(())
+    topology.run_containers(args).await.stack()
 }